@@ -0,0 +1,172 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, Extensions, HeaderMap, HeaderName},
+};
+
+use crate::{config::IpNetwork, AppState};
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Address the request was observed to come from, as opposed to the `ip_address`
+/// an agent reports about itself. `None` only when there is no TCP peer and no
+/// usable `X-Forwarded-For`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let trusted = &state.settings().trusted_proxies;
+        Ok(ClientIp(resolve(
+            &parts.extensions,
+            &parts.headers,
+            trusted,
+        )))
+    }
+}
+
+/// Work out the client address from the connection and `X-Forwarded-For`.
+///
+/// The header is only believed when the TCP peer is a trusted proxy, or when there is
+/// no TCP peer at all (a Unix socket, which only local processes can reach). It is then
+/// read right to left, skipping further trusted proxies; the first other address is
+/// the client.
+pub fn resolve(
+    extensions: &Extensions,
+    headers: &HeaderMap,
+    trusted: &[IpNetwork],
+) -> Option<IpAddr> {
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if let Some(ip) = peer {
+        if !is_trusted(&ip, trusted) {
+            return Some(ip);
+        }
+    }
+
+    let mut client = peer;
+    for hop in forwarded_for(headers).rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            // Unparseable entry: don't look past it
+            break;
+        };
+        client = Some(ip);
+        if !is_trusted(&ip, trusted) {
+            break;
+        }
+    }
+    client
+}
+
+fn is_trusted(ip: &IpAddr, trusted: &[IpNetwork]) -> bool {
+    trusted.iter().any(|net| net.contains(ip))
+}
+
+/// Entries of every `X-Forwarded-For` header, in order
+fn forwarded_for(headers: &HeaderMap) -> impl DoubleEndedIterator<Item = &str> {
+    headers
+        .get_all(&X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn from_peer(addr: &str) -> Extensions {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+        extensions
+    }
+
+    fn with_xff(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(&X_FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn networks(list: &[&str]) -> Vec<IpNetwork> {
+        list.iter()
+            .map(|s| IpNetwork::try_from(s.to_string()).unwrap())
+            .collect()
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_header() {
+        let resolved = resolve(
+            &from_peer("203.0.113.7:50000"),
+            &with_xff("10.1.1.1"),
+            &networks(&["127.0.0.1"]),
+        );
+        assert_eq!(resolved, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_trusted_peer_uses_forwarded_client() {
+        let resolved = resolve(
+            &from_peer("127.0.0.1:50000"),
+            &with_xff("198.51.100.20"),
+            &networks(&["127.0.0.1"]),
+        );
+        assert_eq!(resolved, ip("198.51.100.20"));
+    }
+
+    #[test]
+    fn test_skips_trusted_hops_but_not_spoofed_ones() {
+        // The client prepended a fake address; only the hops our proxies added count
+        let resolved = resolve(
+            &from_peer("127.0.0.1:50000"),
+            &with_xff("1.2.3.4, 198.51.100.20, 10.20.0.5"),
+            &networks(&["127.0.0.1", "10.20.0.0/24"]),
+        );
+        assert_eq!(resolved, ip("198.51.100.20"));
+    }
+
+    #[test]
+    fn test_trusted_peer_without_header() {
+        let resolved = resolve(
+            &from_peer("127.0.0.1:50000"),
+            &HeaderMap::new(),
+            &networks(&["127.0.0.1"]),
+        );
+        assert_eq!(resolved, ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_garbage_entry_stops_walk() {
+        let resolved = resolve(
+            &from_peer("127.0.0.1:50000"),
+            &with_xff("198.51.100.20, unknown"),
+            &networks(&["127.0.0.1"]),
+        );
+        assert_eq!(resolved, ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_no_peer_uses_header() {
+        let resolved = resolve(&Extensions::new(), &with_xff("198.51.100.20"), &[]);
+        assert_eq!(resolved, ip("198.51.100.20"));
+        assert_eq!(resolve(&Extensions::new(), &HeaderMap::new(), &[]), None);
+    }
+}
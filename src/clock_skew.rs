@@ -0,0 +1,144 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::config::{ClockSkewConfig, SkewAction};
+
+/// Timestamp to store for a check-in, after comparing the agent's clock with ours
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assessed {
+    /// `timestamp_utc` as sent, or the receive time when it was clamped
+    pub timestamp_utc: String,
+    pub received_at_utc: String,
+    /// Agent time minus server time; positive when the agent clock is ahead
+    pub skew_secs: i64,
+    pub clamped: bool,
+}
+
+/// Compare `timestamp_utc` (already validated as RFC 3339) with `received`.
+/// Returns the skew in seconds as the error when the window is exceeded and
+/// `cfg.action` is [`SkewAction::Reject`].
+pub fn assess(
+    timestamp_utc: &str,
+    received: DateTime<Utc>,
+    cfg: &ClockSkewConfig,
+) -> Result<Assessed, i64> {
+    let received_at_utc = format_utc(received);
+    let skew_secs = DateTime::parse_from_rfc3339(timestamp_utc)
+        .map(|ts| (ts.with_timezone(&Utc) - received).num_seconds())
+        .unwrap_or(0);
+
+    if !outside_window(skew_secs, cfg) {
+        return Ok(Assessed {
+            timestamp_utc: timestamp_utc.to_string(),
+            received_at_utc,
+            skew_secs,
+            clamped: false,
+        });
+    }
+
+    match cfg.action {
+        SkewAction::Reject => Err(skew_secs),
+        SkewAction::Clamp => Ok(Assessed {
+            timestamp_utc: received_at_utc.clone(),
+            received_at_utc,
+            skew_secs,
+            clamped: true,
+        }),
+    }
+}
+
+/// Whether a recorded skew falls outside the configured window
+pub fn outside_window(skew_secs: i64, cfg: &ClockSkewConfig) -> bool {
+    let ahead = skew_secs > 0 && skew_secs.unsigned_abs() > cfg.max_ahead_secs;
+    let behind =
+        skew_secs < 0 && cfg.max_behind_secs > 0 && skew_secs.unsigned_abs() > cfg.max_behind_secs;
+    ahead || behind
+}
+
+/// Human-readable skew for the UI, e.g. `2h 5m ahead`
+pub fn describe(skew_secs: i64) -> String {
+    let abs = skew_secs.unsigned_abs();
+    let (days, hours, mins, secs) = (abs / 86400, abs % 86400 / 3600, abs % 3600 / 60, abs % 60);
+    let amount = if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else if mins > 0 {
+        format!("{mins}m {secs}s")
+    } else {
+        format!("{secs}s")
+    };
+
+    match skew_secs {
+        0 => "none".to_string(),
+        s if s > 0 => format!("{amount} ahead"),
+        _ => format!("{amount} behind"),
+    }
+}
+
+/// Timestamp format used for server-generated times, matching what agents send
+pub fn format_utc(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received() -> DateTime<Utc> {
+        "2024-01-15T10:30:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_within_window_keeps_agent_timestamp() {
+        let cfg = ClockSkewConfig::default();
+        let assessed = assess("2024-01-15T10:32:00+00:00", received(), &cfg).unwrap();
+        assert_eq!(assessed.timestamp_utc, "2024-01-15T10:32:00+00:00");
+        assert_eq!(assessed.received_at_utc, "2024-01-15T10:30:00Z");
+        assert_eq!(assessed.skew_secs, 120);
+        assert!(!assessed.clamped);
+    }
+
+    #[test]
+    fn test_future_timestamp_is_clamped() {
+        let cfg = ClockSkewConfig::default();
+        let assessed = assess("2025-01-15T10:30:00Z", received(), &cfg).unwrap();
+        assert_eq!(assessed.timestamp_utc, "2024-01-15T10:30:00Z");
+        assert_eq!(assessed.skew_secs, 366 * 86400);
+        assert!(assessed.clamped);
+    }
+
+    #[test]
+    fn test_future_timestamp_is_rejected() {
+        let cfg = ClockSkewConfig {
+            action: SkewAction::Reject,
+            ..Default::default()
+        };
+        assert_eq!(assess("2024-01-15T11:30:00Z", received(), &cfg), Err(3600));
+    }
+
+    #[test]
+    fn test_late_timestamp_allowed_unless_limited() {
+        let mut cfg = ClockSkewConfig::default();
+        assert!(
+            !assess("2024-01-01T00:00:00Z", received(), &cfg)
+                .unwrap()
+                .clamped
+        );
+
+        cfg.max_behind_secs = 3600;
+        assert!(
+            assess("2024-01-01T00:00:00Z", received(), &cfg)
+                .unwrap()
+                .clamped
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(0), "none");
+        assert_eq!(describe(45), "45s ahead");
+        assert_eq!(describe(-125), "2m 5s behind");
+        assert_eq!(describe(7500), "2h 5m ahead");
+        assert_eq!(describe(-90000), "1d 1h behind");
+    }
+}
@@ -1,9 +1,10 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     #[serde(default = "default_bind")]
     pub bind: String,
@@ -19,12 +20,525 @@ pub struct Config {
 
     #[serde(default)]
     pub debug: bool,
+
+    /// Seconds to let in-flight requests finish after a stop signal
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+
+    /// Seconds between checks of config.toml and the TLS files for changes (0 disables)
+    #[serde(default = "default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+
+    /// Reverse proxies whose `X-Forwarded-For` header is believed (addresses or CIDR ranges)
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNetwork>,
+
+    /// Hours an `Idempotency-Key` or `checkin_id` is remembered; a replay within this
+    /// window returns the original result instead of storing the check-in again
+    #[serde(default = "default_idempotency_key_retention_hours")]
+    pub idempotency_key_retention_hours: u64,
+
+    /// Explicit listeners. When empty, a single listener is started on `bind`
+    /// (HTTPS when `tls_cert`/`tls_key` are set).
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+
+    #[serde(default)]
+    pub limits: LimitsConfig,
+
+    #[serde(default)]
+    pub clock_skew: ClockSkewConfig,
+
+    #[serde(default)]
+    pub validation: ValidationConfig,
+
+    #[serde(default)]
+    pub users: UsersConfig,
+
+    /// Named networks, e.g. office sites and VPN pools. A device's location is the
+    /// name of the most specific one containing its reported or observed address.
+    #[serde(default)]
+    pub subnets: BTreeMap<String, Vec<IpNetwork>>,
+
+    /// Typed fields admins fill in per device on its page, by key
+    #[serde(default)]
+    pub custom_fields: BTreeMap<String, CustomField>,
+
+    #[serde(default)]
+    pub identity: IdentityConfig,
+
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+/// An IP network written as a CIDR range (`10.0.0.0/8`) or a single address (`10.0.0.1`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IpNetwork(pub ipnet::IpNet);
+
+impl IpNetwork {
+    pub fn contains(&self, ip: &std::net::IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+impl TryFrom<String> for IpNetwork {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse::<ipnet::IpNet>()
+            .or_else(|_| s.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+            .map(IpNetwork)
+            .map_err(|_| format!("invalid IP address or CIDR range: {s}"))
+    }
+}
+
+/// What a listener serves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// The full API and web UI
+    #[default]
+    All,
+    /// Permanent redirect of every request to the HTTPS listener
+    Redirect,
+    /// Only `/healthz` and `/readyz`
+    Health,
+}
+
+/// One socket the server listens on
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ListenerConfig {
+    /// TCP address (IP:port). Exactly one of `bind` and `unix_socket` must be set.
+    #[serde(default)]
+    pub bind: Option<String>,
+
+    /// Unix domain socket path, for a reverse proxy on the same host (Unix only)
+    #[serde(default)]
+    pub unix_socket: Option<String>,
+
+    /// Serve HTTPS using `tls_cert`/`tls_key`
+    #[serde(default)]
+    pub tls: bool,
+
+    #[serde(default)]
+    pub serve: ListenerRole,
+
+    /// Port to redirect to when `serve = "redirect"`. Defaults to the first TLS listener's port.
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
+}
+
+/// Protection for `/checkin` against looping agents and oversized payloads
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LimitsConfig {
+    /// Largest accepted request body (KiB). Read at startup.
+    #[serde(default = "default_max_body_kb")]
+    pub max_body_kb: usize,
+
+    /// Check-ins accepted per source IP per minute (0, the default, disables)
+    #[serde(default)]
+    pub per_ip_per_minute: u32,
+
+    /// Check-ins accepted per `laptop_serial` per minute (0, the default, disables)
+    #[serde(default)]
+    pub per_serial_per_minute: u32,
+
+    /// Minimum seconds between accepted check-ins from one device (0 disables)
+    #[serde(default)]
+    pub min_checkin_interval_secs: u64,
+
+    /// Largest accepted `/checkin/batch` body (KiB). Read at startup.
+    #[serde(default = "default_max_batch_body_kb")]
+    pub max_batch_body_kb: usize,
+
+    /// Most check-ins accepted in one `/checkin/batch` request
+    #[serde(default = "default_max_batch_items")]
+    pub max_batch_items: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_body_kb: default_max_body_kb(),
+            per_ip_per_minute: 0,
+            per_serial_per_minute: 0,
+            min_checkin_interval_secs: 0,
+            max_batch_body_kb: default_max_batch_body_kb(),
+            max_batch_items: default_max_batch_items(),
+        }
+    }
+}
+
+fn default_max_batch_body_kb() -> usize {
+    8192
+}
+
+fn default_max_batch_items() -> usize {
+    1000
+}
+
+fn default_max_body_kb() -> usize {
+    256
+}
+
+/// What to do with a check-in whose `timestamp_utc` is outside the skew window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkewAction {
+    /// Store the check-in with the server's receive time as its timestamp
+    #[default]
+    Clamp,
+    /// Refuse the check-in with 400 Bad Request
+    Reject,
+}
+
+/// How far an agent's `timestamp_utc` may drift from the server's clock
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClockSkewConfig {
+    /// Seconds an agent timestamp may be ahead of the server
+    #[serde(default = "default_max_ahead_secs")]
+    pub max_ahead_secs: u64,
+
+    /// Seconds an agent timestamp may be behind the server (0 disables, since
+    /// check-ins queued while offline legitimately arrive late)
+    #[serde(default)]
+    pub max_behind_secs: u64,
+
+    #[serde(default)]
+    pub action: SkewAction,
+}
+
+impl Default for ClockSkewConfig {
+    fn default() -> Self {
+        Self {
+            max_ahead_secs: default_max_ahead_secs(),
+            max_behind_secs: 0,
+            action: SkewAction::default(),
+        }
+    }
+}
+
+fn default_max_ahead_secs() -> u64 {
+    300
+}
+
+/// Characters accepted in user names and drive models and serials
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    /// Printable ASCII only
+    #[default]
+    Ascii,
+    /// Any NFC-normalized Unicode without control or bidi formatting characters
+    Unicode,
+}
+
+/// Form of `hostname` accepted from agents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostnamePolicy {
+    /// Windows computer name: letters, digits, hyphens and underscores, up to 63
+    #[default]
+    Netbios,
+    /// Single RFC 1123 DNS label: letters, digits and hyphens, up to 63
+    Label,
+    /// Short name, optionally followed by a DNS domain, up to 253 in all
+    Fqdn,
+}
+
+/// How check-in payloads are validated, and how failures are reported
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ValidationConfig {
+    /// Characters allowed in `logged_in_user` and drive `model` and `serial_number`
+    #[serde(default)]
+    pub charset: Charset,
+
+    #[serde(default)]
+    pub hostname: HostnamePolicy,
+
+    /// List each failing field and validator code in the 400 response instead of
+    /// only "Invalid input data". Useful while developing agents.
+    #[serde(default)]
+    pub detailed_errors: bool,
+
+    /// Rejected check-ins kept in the quarantine table for inspection and
+    /// re-processing; the oldest are dropped beyond this (0 disables)
+    #[serde(default = "default_quarantine_max_rows")]
+    pub quarantine_max_rows: usize,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            charset: Charset::default(),
+            hostname: HostnamePolicy::default(),
+            detailed_errors: false,
+            quarantine_max_rows: default_quarantine_max_rows(),
+        }
+    }
+}
+
+fn default_quarantine_max_rows() -> usize {
+    1000
+}
+
+/// How logged-in user names are matched up across check-ins
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct UsersConfig {
+    /// Domains to treat as another, by name, e.g. `"corp.example.com" = "EXAMPLE"`
+    /// where the NetBIOS name isn't the first label of the DNS name. Matched without
+    /// regard to case.
+    #[serde(default)]
+    pub domain_aliases: BTreeMap<String, String>,
+}
+
+/// How check-ins are matched to devices
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct IdentityConfig {
+    /// Serials firmware reports when none was set, matched without regard to case
+    /// or surrounding spaces. A device reporting one is identified by its hostname
+    /// and drive serials instead.
+    #[serde(default = "default_placeholder_serials")]
+    pub placeholder_serials: Vec<String>,
+}
+
+fn default_placeholder_serials() -> Vec<String> {
+    [
+        "To be filled by O.E.M.",
+        "Default string",
+        "System Serial Number",
+        "Chassis Serial Number",
+        "Not Specified",
+        "Not Applicable",
+        "None",
+        "N/A",
+        "Invalid",
+        "0",
+        "0123456789",
+        "123456789",
+    ]
+    .map(str::to_string)
+    .into()
+}
+
+impl Default for IdentityConfig {
+    fn default() -> Self {
+        Self {
+            placeholder_serials: default_placeholder_serials(),
+        }
+    }
+}
+
+/// Kind of value a custom field holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    Text,
+    /// Integer or decimal number
+    Number,
+    /// Calendar date, `YYYY-MM-DD`
+    Date,
+    /// Yes or no
+    Bool,
+    /// One of `choices`
+    Choice,
+}
+
+/// A typed field admins can set on any device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawCustomField")]
+pub struct CustomField {
+    /// Shown on pages and used as the export column heading; the key when not set
+    pub label: Option<String>,
+    pub kind: FieldType,
+    pub choices: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCustomField {
+    label: Option<String>,
+    #[serde(default, rename = "type")]
+    kind: FieldType,
+    #[serde(default)]
+    choices: Vec<String>,
+}
+
+impl TryFrom<RawCustomField> for CustomField {
+    type Error = String;
+
+    fn try_from(raw: RawCustomField) -> Result<Self, Self::Error> {
+        match (raw.kind, raw.choices.is_empty()) {
+            (FieldType::Choice, true) => Err("type = \"choice\" requires choices".to_string()),
+            (FieldType::Choice, false) | (_, true) => Ok(Self {
+                label: raw.label,
+                kind: raw.kind,
+                choices: raw.choices,
+            }),
+            (_, false) => Err("choices are only allowed with type = \"choice\"".to_string()),
+        }
+    }
+}
+
+/// Thresholds used by the `/readyz` endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReadinessConfig {
+    /// Minimum free space (MiB) on the volume holding the database
+    #[serde(default = "default_min_free_disk_mb")]
+    pub min_free_disk_mb: u64,
+
+    /// Maximum size (MiB) of the `-wal` file before the server reports not ready
+    #[serde(default = "default_max_wal_mb")]
+    pub max_wal_mb: u64,
+}
+
+fn default_min_free_disk_mb() -> u64 {
+    512
+}
+
+fn default_max_wal_mb() -> u64 {
+    256
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            min_free_disk_mb: default_min_free_disk_mb(),
+            max_wal_mb: default_max_wal_mb(),
+        }
+    }
+}
+
+/// Output format for log events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable single-line output
+    #[default]
+    Text,
+    /// One JSON object per event, for log shippers
+    Json,
+}
+
+/// How often the log file is rolled over, independent of its size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+/// Log output settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Mask user names in debug payload events
+    #[serde(default = "default_true")]
+    pub redact_user_names: bool,
+
+    /// Directory for log files. Console-only logging when unset.
+    #[serde(default)]
+    pub directory: Option<String>,
+
+    #[serde(default = "default_log_file_name")]
+    pub file_name: String,
+
+    #[serde(default)]
+    pub rotation: LogRotation,
+
+    /// Roll the file over once it reaches this size (MiB)
+    #[serde(default = "default_log_max_file_size_mb")]
+    pub max_file_size_mb: u64,
+
+    /// Number of rotated files to keep before the oldest is deleted
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_log_file_name() -> String {
+    "inventory-server.log".to_string()
+}
+
+fn default_log_max_file_size_mb() -> u64 {
+    50
+}
+
+fn default_log_max_files() -> usize {
+    14
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            redact_user_names: true,
+            directory: None,
+            file_name: default_log_file_name(),
+            rotation: LogRotation::default(),
+            max_file_size_mb: default_log_max_file_size_mb(),
+            max_files: default_log_max_files(),
+        }
+    }
+}
+
+/// OpenTelemetry span export settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    /// Export is disabled when unset.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+
+    /// `service.name` resource attribute attached to exported spans
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    "inventory-server".to_string()
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+        }
+    }
 }
 
 fn default_bind() -> String {
     "0.0.0.0:8443".to_string()
 }
 
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_reload_interval_secs() -> u64 {
+    30
+}
+
+fn default_idempotency_key_retention_hours() -> u64 {
+    72
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -33,6 +547,21 @@ impl Default for Config {
             tls_cert: None,
             tls_key: None,
             debug: false,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            reload_interval_secs: default_reload_interval_secs(),
+            trusted_proxies: Vec::new(),
+            idempotency_key_retention_hours: default_idempotency_key_retention_hours(),
+            listeners: Vec::new(),
+            limits: LimitsConfig::default(),
+            clock_skew: ClockSkewConfig::default(),
+            validation: ValidationConfig::default(),
+            users: UsersConfig::default(),
+            subnets: BTreeMap::new(),
+            custom_fields: BTreeMap::new(),
+            identity: IdentityConfig::default(),
+            readiness: ReadinessConfig::default(),
+            logging: LoggingConfig::default(),
+            telemetry: TelemetryConfig::default(),
         }
     }
 }
@@ -70,6 +599,142 @@ bind = "0.0.0.0:8443"
 
 # Enable debug mode to log all incoming check-ins
 debug = false
+
+# Seconds to let in-flight requests finish after SIGTERM/Ctrl+C before closing connections
+shutdown_timeout_secs = 30
+
+# Seconds between checks of this file and the TLS cert/key for changes (0 disables).
+# Changes are also picked up immediately on SIGHUP (Unix). bind, db_path, tls_cert/tls_key
+# paths and the [logging]/[telemetry] output settings still require a restart.
+reload_interval_secs = 30
+
+# Reverse proxies (addresses or CIDR ranges) whose X-Forwarded-For header is trusted to
+# carry the real client address. Requests from anywhere else are recorded with the TCP
+# peer address.
+# trusted_proxies = ["127.0.0.1", "10.20.0.0/24"]
+
+# Hours to remember the Idempotency-Key header or checkin_id of each stored check-in.
+# An agent retrying within this window gets the original result and nothing is stored twice.
+idempotency_key_retention_hours = 72
+
+# Optional explicit listeners, replacing `bind` above. Each has either `bind` (IP:port) or
+# `unix_socket` (Unix only), `tls = true` to serve HTTPS with tls_cert/tls_key, and
+# `serve` = "all" (API and UI), "redirect" (redirect everything to HTTPS) or "health"
+# (only /healthz and /readyz).
+#
+# [[listeners]]
+# bind = "0.0.0.0:8443"
+# tls = true
+#
+# [[listeners]]
+# bind = "0.0.0.0:8080"
+# serve = "redirect"
+#
+# [[listeners]]
+# unix_socket = "/run/inventory-server/http.sock"
+
+# Limits on POST /checkin. Requests over a rate limit get 429 Too Many Requests.
+[limits]
+# Largest accepted request body in KiB (requires a restart)
+max_body_kb = 256
+# Check-ins accepted per source IP and per laptop_serial per minute (0 disables). Both
+# are off by default. Behind NAT or a reverse proxy, including one on a Unix socket,
+# every agent shares the proxy's address unless it is listed in trusted_proxies, so set
+# trusted_proxies before enabling per_ip_per_minute there.
+per_ip_per_minute = 0
+per_serial_per_minute = 0
+# Minimum seconds between accepted check-ins from the same device (0 disables)
+min_checkin_interval_secs = 0
+# POST /checkin/batch: largest body in KiB (requires a restart) and most items per request
+max_batch_body_kb = 8192
+max_batch_items = 1000
+
+# Agent clock checks. The server records its own receive time with every check-in and
+# the difference from the agent's timestamp_utc (the clock skew).
+[clock_skew]
+# Seconds an agent timestamp may be ahead of the server
+max_ahead_secs = 300
+# Seconds an agent timestamp may be behind the server (0 = no limit, as agents may
+# deliver queued check-ins late)
+max_behind_secs = 0
+# Outside the window: "clamp" stores the server receive time instead, "reject" refuses it
+action = "clamp"
+
+# Check-in validation
+[validation]
+# Characters allowed in logged_in_user and drive model and serial_number: "ascii"
+# (printable ASCII) or "unicode" (NFC-normalized, no control or bidi formatting characters)
+charset = "ascii"
+# Form of hostname accepted: "netbios" (Windows computer name, may contain underscores),
+# "label" (a single RFC 1123 DNS label) or "fqdn" (a short name optionally followed by a
+# DNS domain, e.g. host.example.com; the domain is stored and shown separately)
+hostname = "netbios"
+# Return each failing field and validator code (e.g. drives[2].serial_number,
+# invalid_characters) instead of a generic "Invalid input data"
+detailed_errors = false
+# Rejected check-ins kept for inspection at /admin/quarantine and re-processing after a
+# validation change; the oldest are dropped beyond this (0 disables)
+quarantine_max_rows = 1000
+
+# Grouping of logged_in_user values on the /users pages. CONTOSO\jdoe and
+# jdoe@contoso.com are the same person: case is ignored and a DNS domain matches the
+# NetBIOS domain equal to its first label. List other domains that belong together here.
+[users]
+# domain_aliases = { "corp.example.com" = "EXAMPLE" }
+
+# Named networks (CIDR ranges or single addresses, IPv4 or IPv6). Devices are shown with
+# the name of the most specific one containing their reported IP, or failing that the
+# address their check-in came from, as their location. /network?q=<name> lists the
+# devices seen in one.
+[subnets]
+# "HQ" = ["10.1.0.0/16", "fd00:1::/48"]
+# "Branch office" = ["10.2.0.0/16"]
+# VPN = ["10.200.0.0/20"]
+
+# Typed fields admins can fill in on each device's page, alongside free-form tags and
+# notes. Values are checked against the type: "text", "number", "date" (YYYY-MM-DD),
+# "bool" or "choice" (one of choices). They can filter the device list and are exported
+# with it.
+[custom_fields]
+# asset_tag = { label = "Asset tag" }
+# purchased = { label = "Purchase date", type = "date" }
+# cost_center = { label = "Cost center", type = "choice", choices = ["IT", "Sales", "Lab"] }
+
+# Serials that don't identify a machine, reported by firmware nobody filled in. A
+# device reporting one of these (ignoring case) is identified by its hostname and drive
+# serials instead, so different machines don't collapse into one device.
+[identity]
+placeholder_serials = [
+    "To be filled by O.E.M.", "Default string", "System Serial Number",
+    "Chassis Serial Number", "Not Specified", "Not Applicable", "None", "N/A", "Invalid",
+    "0", "0123456789", "123456789",
+]
+
+# Thresholds for the /readyz endpoint
+[readiness]
+# Report not ready when free disk space on the database volume drops below this (MiB)
+min_free_disk_mb = 512
+# Report not ready when the SQLite WAL file grows beyond this (MiB)
+max_wal_mb = 256
+
+[logging]
+# "text" for human-readable output or "json" for log shippers
+format = "text"
+# Mask user names in debug-mode payload events
+redact_user_names = true
+# Also write logs to rolling files in this directory
+# directory = "C:\\ProgramData\\InventoryServer\\logs"
+# file_name = "inventory-server.log"
+# Roll over "hourly", "daily" or "never", and whenever a file reaches max_file_size_mb
+# rotation = "daily"
+# max_file_size_mb = 50
+# Number of rotated files to keep
+# max_files = 14
+
+[telemetry]
+# Export request, handler and database spans to an OpenTelemetry collector (OTLP/HTTP)
+# otlp_endpoint = "http://localhost:4318/v1/traces"
+# service_name = "inventory-server"
 "#;
 
     std::fs::write(config_path, template).with_context(|| {
@@ -79,22 +744,22 @@ debug = false
         )
     })?;
 
-    println!("Generated template config file: {}", config_path.display());
+    tracing::info!(path = %config_path.display(), "Generated template config file");
     Ok(())
 }
 
+/// Path of config.toml in the same directory as the executable
+pub fn config_path() -> Result<PathBuf> {
+    Ok(exe_dir()?.join("config.toml"))
+}
+
 /// Load config from config.toml in the same directory as the executable.
 /// Generates a template file if it doesn't exist.
 pub fn load_config() -> Result<Config> {
-    let exe_dir = exe_dir()?;
-    let config_path = exe_dir.join("config.toml");
+    let config_path = config_path()?;
 
     if config_path.exists() {
-        let contents = std::fs::read_to_string(&config_path)
-            .with_context(|| format!("failed to read config file: {}", config_path.display()))?;
-        let config: Config = toml::from_str(&contents)
-            .with_context(|| format!("failed to parse config file: {}", config_path.display()))?;
-        Ok(config)
+        read_config(&config_path)
     } else {
         // Auto-generate template config file
         generate_template_config(&config_path)?;
@@ -102,6 +767,14 @@ pub fn load_config() -> Result<Config> {
     }
 }
 
+/// Read and parse an existing config file
+pub fn read_config(config_path: &Path) -> Result<Config> {
+    let contents = std::fs::read_to_string(config_path)
+        .with_context(|| format!("failed to read config file: {}", config_path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("failed to parse config file: {}", config_path.display()))
+}
+
 /// Get the default database path (inventory.db in the same directory as the executable)
 pub fn default_db_path() -> Result<String> {
     let exe_dir = exe_dir()?;
@@ -121,6 +794,37 @@ mod tests {
         assert_eq!(config.tls_cert, None);
         assert_eq!(config.tls_key, None);
         assert!(!config.debug);
+        assert_eq!(config.shutdown_timeout_secs, 30);
+        assert_eq!(config.reload_interval_secs, 30);
+        assert!(config.listeners.is_empty());
+        assert!(config.trusted_proxies.is_empty());
+        assert_eq!(config.idempotency_key_retention_hours, 72);
+        assert_eq!(config.limits.max_body_kb, 256);
+        assert_eq!(config.limits.per_ip_per_minute, 0);
+        assert_eq!(config.limits.per_serial_per_minute, 0);
+        assert_eq!(config.limits.min_checkin_interval_secs, 0);
+        assert_eq!(config.limits.max_batch_items, 1000);
+        assert_eq!(config.clock_skew.max_ahead_secs, 300);
+        assert_eq!(config.clock_skew.action, SkewAction::Clamp);
+        assert_eq!(config.validation.charset, Charset::Ascii);
+        assert_eq!(config.validation.hostname, HostnamePolicy::Netbios);
+        assert!(!config.validation.detailed_errors);
+        assert_eq!(config.validation.quarantine_max_rows, 1000);
+        assert!(config.users.domain_aliases.is_empty());
+        assert!(config.subnets.is_empty());
+        assert!(config.custom_fields.is_empty());
+        assert_eq!(config.identity.placeholder_serials.len(), 12);
+        assert_eq!(
+            config.identity.placeholder_serials[0],
+            "To be filled by O.E.M."
+        );
+        assert_eq!(config.readiness.min_free_disk_mb, 512);
+        assert_eq!(config.readiness.max_wal_mb, 256);
+        assert_eq!(config.logging.format, LogFormat::Text);
+        assert!(config.logging.redact_user_names);
+        assert_eq!(config.logging.directory, None);
+        assert_eq!(config.telemetry.otlp_endpoint, None);
+        assert_eq!(config.telemetry.service_name, "inventory-server");
     }
 
     #[test]
@@ -144,6 +848,7 @@ mod tests {
             tls_cert = "/path/to/cert.pem"
             tls_key = "/path/to/key.pem"
             debug = true
+            idempotency_key_retention_hours = 24
         "#;
         let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(config.bind, "127.0.0.1:9000");
@@ -151,6 +856,7 @@ mod tests {
         assert_eq!(config.tls_cert, Some("/path/to/cert.pem".to_string()));
         assert_eq!(config.tls_key, Some("/path/to/key.pem".to_string()));
         assert!(config.debug);
+        assert_eq!(config.idempotency_key_retention_hours, 24);
     }
 
     #[test]
@@ -165,6 +871,228 @@ mod tests {
         assert!(config.debug);
     }
 
+    #[test]
+    fn test_toml_parse_listeners() {
+        let toml = r#"
+            [[listeners]]
+            bind = "0.0.0.0:8443"
+            tls = true
+
+            [[listeners]]
+            bind = "0.0.0.0:8080"
+            serve = "redirect"
+
+            [[listeners]]
+            unix_socket = "/run/inventory.sock"
+            serve = "health"
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.listeners.len(), 3);
+        assert!(config.listeners[0].tls);
+        assert_eq!(config.listeners[0].serve, ListenerRole::All);
+        assert_eq!(config.listeners[1].serve, ListenerRole::Redirect);
+        assert_eq!(
+            config.listeners[2].unix_socket,
+            Some("/run/inventory.sock".to_string())
+        );
+        assert_eq!(config.listeners[2].serve, ListenerRole::Health);
+    }
+
+    #[test]
+    fn test_toml_parse_limits() {
+        let toml = r#"
+            [limits]
+            per_ip_per_minute = 0
+            min_checkin_interval_secs = 300
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.limits.per_ip_per_minute, 0);
+        assert_eq!(config.limits.min_checkin_interval_secs, 300);
+        assert_eq!(config.limits.max_body_kb, 256);
+    }
+
+    #[test]
+    fn test_toml_parse_clock_skew() {
+        let toml = r#"
+            [clock_skew]
+            max_behind_secs = 86400
+            action = "reject"
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.clock_skew.max_ahead_secs, 300);
+        assert_eq!(config.clock_skew.max_behind_secs, 86400);
+        assert_eq!(config.clock_skew.action, SkewAction::Reject);
+    }
+
+    #[test]
+    fn test_toml_parse_validation() {
+        let toml = r#"
+            [validation]
+            charset = "unicode"
+            hostname = "fqdn"
+            detailed_errors = true
+            quarantine_max_rows = 50
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.validation.charset, Charset::Unicode);
+        assert_eq!(config.validation.hostname, HostnamePolicy::Fqdn);
+        assert!(config.validation.detailed_errors);
+        assert_eq!(config.validation.quarantine_max_rows, 50);
+    }
+
+    #[test]
+    fn test_toml_parse_users() {
+        let toml = r#"
+            [users]
+            domain_aliases = { "corp.example.com" = "EXAMPLE" }
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config
+                .users
+                .domain_aliases
+                .get("corp.example.com")
+                .map(String::as_str),
+            Some("EXAMPLE")
+        );
+    }
+
+    #[test]
+    fn test_toml_parse_subnets() {
+        let toml = r#"
+            [subnets]
+            HQ = ["10.1.0.0/16", "fd00:1::/48"]
+            "VPN pool" = ["10.200.0.1"]
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.subnets.len(), 2);
+        assert_eq!(config.subnets["HQ"][1].0.to_string(), "fd00:1::/48");
+        assert_eq!(config.subnets["VPN pool"][0].0.to_string(), "10.200.0.1/32");
+
+        let toml = r#"
+            [subnets]
+            HQ = ["10.1.0.0/33"]
+        "#;
+        assert!(toml::from_str::<Config>(toml).is_err());
+    }
+
+    #[test]
+    fn test_toml_parse_custom_fields() {
+        let toml = r#"
+            [custom_fields]
+            asset_tag = { label = "Asset tag" }
+            purchased = { type = "date" }
+            cost_center = { type = "choice", choices = ["IT", "Sales"] }
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let fields = &config.custom_fields;
+        assert_eq!(fields["asset_tag"].label.as_deref(), Some("Asset tag"));
+        assert_eq!(fields["asset_tag"].kind, FieldType::Text);
+        assert_eq!(fields["purchased"].kind, FieldType::Date);
+        assert_eq!(fields["cost_center"].choices, ["IT", "Sales"]);
+
+        for invalid in [
+            r#"x = { type = "choice" }"#,
+            r#"x = { type = "text", choices = ["a"] }"#,
+            r#"x = { type = "colour" }"#,
+        ] {
+            let toml = format!("[custom_fields]\n{invalid}");
+            assert!(toml::from_str::<Config>(&toml).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_toml_parse_identity() {
+        let toml = r#"
+            [identity]
+            placeholder_serials = ["Default string", "XXXXXXXX"]
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config.identity.placeholder_serials,
+            ["Default string", "XXXXXXXX"]
+        );
+
+        let config: Config = toml::from_str("[identity]").unwrap();
+        assert_eq!(config.identity, IdentityConfig::default());
+    }
+
+    #[test]
+    fn test_toml_parse_trusted_proxies() {
+        let toml = r#"trusted_proxies = ["127.0.0.1", "10.20.0.0/24", "::1"]"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.trusted_proxies.len(), 3);
+        assert!(config.trusted_proxies[0].contains(&"127.0.0.1".parse().unwrap()));
+        assert!(config.trusted_proxies[1].contains(&"10.20.0.99".parse().unwrap()));
+        assert!(!config.trusted_proxies[1].contains(&"10.21.0.1".parse().unwrap()));
+
+        let invalid: Result<Config, _> = toml::from_str(r#"trusted_proxies = ["proxy.local"]"#);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_toml_parse_readiness() {
+        let toml = r#"
+            [readiness]
+            min_free_disk_mb = 1024
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.readiness.min_free_disk_mb, 1024);
+        assert_eq!(config.readiness.max_wal_mb, 256);
+    }
+
+    #[test]
+    fn test_toml_parse_logging() {
+        let toml = r#"
+            [logging]
+            format = "json"
+            directory = "/var/log/inventory"
+            rotation = "hourly"
+            max_files = 3
+        "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(
+            config.logging.directory,
+            Some("/var/log/inventory".to_string())
+        );
+        assert_eq!(config.logging.rotation, LogRotation::Hourly);
+        assert_eq!(config.logging.max_files, 3);
+        assert_eq!(config.logging.max_file_size_mb, 50);
+    }
+
+    #[test]
+    fn test_toml_invalid_log_format() {
+        let toml = r#"
+            [logging]
+            format = "xml"
+        "#;
+        let result: Result<Config, toml::de::Error> = toml::from_str(toml);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_config_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "debug = true\n[readiness]\nmax_wal_mb = 64\n").unwrap();
+
+        let config = read_config(&path).unwrap();
+
+        assert!(config.debug);
+        assert_eq!(config.readiness.max_wal_mb, 64);
+    }
+
+    #[test]
+    fn test_read_config_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "debug = \"yes\"").unwrap();
+
+        assert!(read_config(&path).is_err());
+        assert!(read_config(&dir.path().join("missing.toml")).is_err());
+    }
+
     #[test]
     fn test_exe_dir_success() {
         // This will succeed in test environment
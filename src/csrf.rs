@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Form,
+};
+use serde::Deserialize;
+
+use crate::AppState;
+
+/// Header that may carry the token instead, for scripted requests
+pub const HEADER: &str = "x-csrf-token";
+
+/// Largest form body read to find the token
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+/// Secret that forms changing data must send back, so another site can't make a
+/// browser submit them. A new one is drawn at startup; a form loaded before a
+/// restart has to be reloaded.
+#[derive(Clone)]
+pub struct Token(String);
+
+impl Token {
+    pub fn generate() -> Self {
+        let (a, b) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        Self(format!("{}{}", a.simple(), b.simple()))
+    }
+
+    /// Value to put in forms
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether `submitted` is this token, compared in constant time
+    pub fn matches(&self, submitted: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), submitted.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(..)")
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenField {
+    csrf_token: String,
+}
+
+/// Middleware for routes that change data: refuses the request with 403 unless it
+/// carries the token in the `csrf_token` form field or the `X-CSRF-Token` header.
+/// The form body is read here and handed on unchanged.
+pub async fn guard(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_FORM_BYTES).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Form too large").into_response();
+    };
+
+    let submitted = match parts.headers.get(HEADER).and_then(|v| v.to_str().ok()) {
+        Some(token) => Some(token.to_string()),
+        None => form_token(bytes.clone()).await,
+    };
+    if !submitted.is_some_and(|t| state.csrf_token.matches(&t)) {
+        tracing::warn!(path = %parts.uri.path(), "Form rejected: missing or invalid CSRF token");
+        return (
+            StatusCode::FORBIDDEN,
+            "Missing or expired form token; reload the page and try again",
+        )
+            .into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// The `csrf_token` field of a URL-encoded form body
+async fn form_token(bytes: axum::body::Bytes) -> Option<String> {
+    let mut request = Request::new(Body::from(bytes));
+    *request.method_mut() = Method::POST;
+    request.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    let Form(field) = Form::<TokenField>::from_request(request, &()).await.ok()?;
+    Some(field.csrf_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_matches() {
+        let token = Token::generate();
+        assert_eq!(token.as_str().len(), 64);
+        assert!(token.matches(token.as_str()));
+        assert!(!token.matches(&token.as_str()[1..]));
+        assert!(!token.matches(&Token::generate().0));
+        assert!(!token.matches(""));
+        assert_eq!(format!("{token:?}"), "Token(..)");
+    }
+}
@@ -1,8 +1,203 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
-use rusqlite::Connection;
+use chrono::{DateTime, Utc};
+use rusqlite::{functions::FunctionFlags, params, Connection, OptionalExtension};
+
+use crate::lifecycle::LifecycleState;
+use crate::models::{
+    CheckinRow, DeviceMeta, HostnameCount, LaptopRow, LifecycleAlertRow, LifecycleEventRow,
+    NetworkRow, QuarantineRow, SearchRow, SerialCollisionRow, SerialRemapRow, UserDeviceRow,
+};
+use crate::network;
+use crate::users;
+
+/// Schema version recorded in `PRAGMA user_version` once initialization completes
+pub const SCHEMA_VERSION: i32 = 15;
 
-use crate::models::{CheckinRow, LaptopRow};
+/// Changes applied on top of the version 1 tables, in order. Entry `i` upgrades a
+/// database from version `i + 1` to `i + 2`. Add new columns as nullable or with a
+/// default so rows written by older versions stay valid.
+const MIGRATIONS: &[&str] = &[
+    // 2: server receive time and agent clock skew
+    r#"
+    ALTER TABLE checkins ADD COLUMN received_at_utc TEXT;
+    ALTER TABLE checkins ADD COLUMN clock_skew_secs INTEGER;
+    ALTER TABLE laptops ADD COLUMN received_at_utc TEXT;
+    ALTER TABLE laptops ADD COLUMN clock_skew_secs INTEGER;
+    "#,
+    // 3: observed source address of the connection
+    r#"
+    ALTER TABLE checkins ADD COLUMN source_ip TEXT;
+    ALTER TABLE laptops ADD COLUMN source_ip TEXT;
+    "#,
+    // 4: idempotency keys of recently stored check-ins
+    r#"
+    CREATE TABLE idempotency_keys (
+      key TEXT PRIMARY KEY,
+      laptop_serial TEXT NOT NULL,
+      checkin_id INTEGER,
+      created_at_utc TEXT NOT NULL
+    );
+    CREATE INDEX idx_idempotency_keys_created ON idempotency_keys(created_at_utc);
+    "#,
+    // 5: rejected check-ins per source
+    r#"
+    CREATE TABLE rejected_payloads (
+      source TEXT PRIMARY KEY,
+      rejected_count INTEGER NOT NULL,
+      last_rejected_utc TEXT NOT NULL,
+      last_error TEXT NOT NULL
+    );
+    "#,
+    // 6: quarantined check-ins that failed validation
+    r#"
+    CREATE TABLE quarantine (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      received_at_utc TEXT NOT NULL,
+      source_ip TEXT,
+      laptop_serial TEXT,
+      body TEXT NOT NULL,
+      error TEXT NOT NULL,
+      errors_json TEXT NOT NULL DEFAULT '[]'
+    );
+    "#,
+    // 7: hostname split into short name and lowercased domain
+    r#"
+    ALTER TABLE checkins ADD COLUMN short_name TEXT;
+    ALTER TABLE checkins ADD COLUMN domain TEXT;
+    ALTER TABLE laptops ADD COLUMN short_name TEXT;
+    ALTER TABLE laptops ADD COLUMN domain TEXT;
+    UPDATE checkins SET
+      short_name = CASE WHEN instr(hostname, '.') > 0
+        THEN substr(hostname, 1, instr(hostname, '.') - 1) ELSE hostname END,
+      domain = CASE WHEN instr(hostname, '.') > 0
+        THEN lower(substr(hostname, instr(hostname, '.') + 1)) END;
+    UPDATE laptops SET
+      short_name = CASE WHEN instr(hostname, '.') > 0
+        THEN substr(hostname, 1, instr(hostname, '.') - 1) ELSE hostname END,
+      domain = CASE WHEN instr(hostname, '.') > 0
+        THEN lower(substr(hostname, instr(hostname, '.') + 1)) END;
+    CREATE INDEX idx_laptops_domain ON laptops(domain);
+    "#,
+    // 8: full-text index of check-ins, one row per check-in keyed by its id
+    r#"
+    CREATE VIRTUAL TABLE search_index USING fts5(
+      laptop_serial, hostname, logged_in_user, addresses, drive_models, drive_serials,
+      tokenize = 'unicode61 remove_diacritics 2'
+    );
+    INSERT INTO search_index (
+      rowid, laptop_serial, hostname, logged_in_user, addresses, drive_models, drive_serials
+    )
+    SELECT id, laptop_serial, hostname, logged_in_user,
+      ip_address || coalesce(' ' || source_ip, ''),
+      (SELECT group_concat(json_extract(d.value, '$.model'), ', ') FROM json_each(drives_json) d),
+      (SELECT group_concat(json_extract(d.value, '$.serial_number'), ', ')
+       FROM json_each(drives_json) d)
+    FROM checkins;
+    "#,
+    // 9: check-in time as Unix milliseconds, for point-in-time lookups per device
+    r#"
+    ALTER TABLE checkins ADD COLUMN timestamp_ms INTEGER;
+    UPDATE checkins SET timestamp_ms = CAST(round(unixepoch(timestamp_utc, 'subsec') * 1000) AS INTEGER);
+    CREATE INDEX idx_checkins_serial_time ON checkins(laptop_serial, timestamp_ms);
+    "#,
+    // 10: reported and observed addresses of check-ins, keyed for range lookups
+    // (`ip_key` is registered by `open_and_init`)
+    r#"
+    CREATE TABLE checkin_addresses (
+      ip_key TEXT NOT NULL,
+      checkin_id INTEGER NOT NULL,
+      PRIMARY KEY (ip_key, checkin_id)
+    ) WITHOUT ROWID;
+    INSERT OR IGNORE INTO checkin_addresses (ip_key, checkin_id)
+    SELECT key, id FROM (
+      SELECT id, ip_key(ip_address) AS key FROM checkins
+      UNION ALL
+      SELECT id, ip_key(source_ip) FROM checkins
+    )
+    WHERE key IS NOT NULL;
+    "#,
+    // 11: tags, notes and custom field values entered by admins per device
+    r#"
+    CREATE TABLE device_tags (
+      laptop_serial TEXT NOT NULL,
+      tag TEXT NOT NULL COLLATE NOCASE,
+      PRIMARY KEY (laptop_serial, tag)
+    ) WITHOUT ROWID;
+    CREATE INDEX idx_device_tags_tag ON device_tags(tag);
+    CREATE TABLE device_notes (
+      laptop_serial TEXT PRIMARY KEY,
+      notes TEXT NOT NULL,
+      updated_at_utc TEXT NOT NULL
+    );
+    CREATE TABLE device_fields (
+      laptop_serial TEXT NOT NULL,
+      key TEXT NOT NULL,
+      value TEXT NOT NULL,
+      updated_at_utc TEXT NOT NULL,
+      PRIMARY KEY (laptop_serial, key)
+    ) WITHOUT ROWID;
+    "#,
+    // 12: lifecycle state changes, and check-ins from devices taken out of service
+    r#"
+    CREATE TABLE lifecycle_events (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      laptop_serial TEXT NOT NULL,
+      state TEXT NOT NULL,
+      changed_at_utc TEXT NOT NULL,
+      changed_at_ms INTEGER NOT NULL,
+      changed_by TEXT NOT NULL,
+      reason TEXT NOT NULL
+    );
+    CREATE INDEX idx_lifecycle_events_serial ON lifecycle_events(laptop_serial, changed_at_ms);
+    CREATE TABLE lifecycle_alerts (
+      laptop_serial TEXT PRIMARY KEY,
+      state TEXT NOT NULL,
+      checkins INTEGER NOT NULL,
+      first_received_utc TEXT NOT NULL,
+      last_received_utc TEXT NOT NULL
+    );
+    "#,
+    // 13: serials as reported when stored under another, serial remaps and collisions
+    r#"
+    ALTER TABLE checkins ADD COLUMN reported_serial TEXT;
+    CREATE TABLE serial_remaps (
+      reported_serial TEXT NOT NULL,
+      hostname TEXT NOT NULL COLLATE NOCASE,
+      laptop_serial TEXT NOT NULL,
+      created_at_utc TEXT NOT NULL,
+      created_by TEXT NOT NULL,
+      reason TEXT NOT NULL,
+      PRIMARY KEY (reported_serial, hostname)
+    ) WITHOUT ROWID;
+    CREATE TABLE serial_collisions (
+      laptop_serial TEXT PRIMARY KEY,
+      detections INTEGER NOT NULL,
+      first_detected_utc TEXT NOT NULL,
+      last_detected_utc TEXT NOT NULL,
+      last_hostname TEXT NOT NULL
+    );
+    "#,
+    // 14: rejected check-ins per source and code
+    r#"
+    CREATE TABLE rejected_payload_codes (
+      source TEXT NOT NULL,
+      code TEXT NOT NULL,
+      rejected_count INTEGER NOT NULL,
+      last_rejected_utc TEXT NOT NULL,
+      PRIMARY KEY (source, code)
+    ) WITHOUT ROWID;
+    "#,
+    // 15: account part of each logged-in user name, to find one person's check-ins
+    r#"
+    ALTER TABLE checkins ADD COLUMN user_account TEXT;
+    UPDATE checkins SET user_account = user_account(logged_in_user);
+    CREATE INDEX idx_checkins_user_account ON checkins(user_account, laptop_serial);
+    "#,
+];
 
+#[tracing::instrument]
 pub fn open_and_init(db_path: &str) -> Result<Connection> {
     let conn = Connection::open(db_path).context("open sqlite db failed")?;
 
@@ -37,16 +232,1285 @@ pub fn open_and_init(db_path: &str) -> Result<Connection> {
     )
     .context("db init batch failed")?;
 
+    // Used by migrations to key stored addresses as new check-ins are keyed
+    conn.create_scalar_function(
+        "ip_key",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let address: Option<String> = ctx.get(0)?;
+            Ok(address.as_deref().and_then(network::parse_key))
+        },
+    )
+    .context("register ip_key function")?;
+    conn.create_scalar_function(
+        "user_account",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let user: Option<String> = ctx.get(0)?;
+            Ok(user.as_deref().and_then(users::account_name))
+        },
+    )
+    .context("register user_account function")?;
+
+    migrate(&conn)?;
+
     Ok(conn)
 }
 
-/// Fetch all laptops ordered by last_seen_utc descending (most recent first)
-pub fn get_all_laptops(conn: &Connection) -> Result<Vec<LaptopRow>> {
+/// Bring the schema up to [`SCHEMA_VERSION`], one migration per transaction
+fn migrate(conn: &Connection) -> Result<()> {
+    // A brand-new database reports 0 but already has the version 1 tables
+    let mut version = schema_version(conn)?.max(1);
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "database schema version {version} is newer than this server supports ({SCHEMA_VERSION})"
+        );
+    }
+
+    while version < SCHEMA_VERSION {
+        let sql = MIGRATIONS[(version - 1) as usize];
+        let next = version + 1;
+        conn.execute_batch(&format!(
+            "BEGIN; {sql} PRAGMA user_version = {next}; COMMIT;"
+        ))
+        .with_context(|| format!("migrate schema to version {next}"))?;
+        tracing::info!(version = next, "Database schema migrated");
+        version = next;
+    }
+
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+        .context("set schema version failed")?;
+
+    Ok(())
+}
+
+/// Read the schema version stored in `PRAGMA user_version`
+pub fn schema_version(conn: &Connection) -> Result<i32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("read schema version")
+}
+
+/// Run a truncating WAL checkpoint.
+/// Returns `(busy, log_frames, checkpointed_frames)` as reported by SQLite.
+#[tracing::instrument(skip(conn))]
+pub fn checkpoint(conn: &Connection) -> Result<(bool, i64, i64)> {
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+        Ok((row.get::<_, i64>(0)? != 0, row.get(1)?, row.get(2)?))
+    })
+    .context("wal checkpoint")
+}
+
+/// A check-in ready to be written to `checkins` and `laptops`
+#[derive(Debug, Clone)]
+pub struct NewCheckin {
+    pub laptop_serial: String,
+    pub hostname: String,
+    /// `hostname` up to the first dot
+    pub short_name: String,
+    /// Lowercased rest of `hostname` after the first dot, if any
+    pub domain: Option<String>,
+    pub ip_address: String,
+    pub logged_in_user: Option<String>,
+    pub timestamp_utc: String,
+    pub drives_json: String,
+    pub received_at_utc: String,
+    pub clock_skew_secs: i64,
+    pub source_ip: Option<String>,
+    /// Serial the agent sent, when the check-in is stored under another one
+    pub reported_serial: Option<String>,
+}
+
+/// Append a check-in to the history, returning its row ID
+pub fn insert_checkin(conn: &Connection, c: &NewCheckin) -> rusqlite::Result<i64> {
+    conn.execute(
+        r#"
+        INSERT INTO checkins (
+            laptop_serial, hostname, ip_address, logged_in_user, timestamp_utc, drives_json,
+            received_at_utc, clock_skew_secs, source_ip, short_name, domain, timestamp_ms,
+            reported_serial, user_account
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+        "#,
+        params![
+            c.laptop_serial,
+            c.hostname,
+            c.ip_address,
+            c.logged_in_user,
+            c.timestamp_utc,
+            c.drives_json,
+            c.received_at_utc,
+            c.clock_skew_secs,
+            c.source_ip,
+            c.short_name,
+            c.domain,
+            parse_instant(&c.timestamp_utc).map(|t| t.timestamp_millis()),
+            c.reported_serial,
+            c.logged_in_user.as_deref().and_then(users::account_name)
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    index_checkin(conn, id)?;
+    for address in std::iter::once(c.ip_address.as_str()).chain(c.source_ip.as_deref()) {
+        if let Some(key) = network::parse_key(address) {
+            conn.execute(
+                "INSERT OR IGNORE INTO checkin_addresses (ip_key, checkin_id) VALUES (?1, ?2)",
+                params![key, id],
+            )?;
+        }
+    }
+    Ok(id)
+}
+
+/// Add a stored check-in to `search_index`. Each searchable field is a column, so
+/// a new field needs a column here and a migration rebuilding the index.
+fn index_checkin(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO search_index (
+            rowid, laptop_serial, hostname, logged_in_user, addresses, drive_models, drive_serials
+        )
+        SELECT id, laptop_serial, hostname, logged_in_user,
+            ip_address || coalesce(' ' || source_ip, ''),
+            (SELECT group_concat(json_extract(d.value, '$.model'), ', ')
+             FROM json_each(drives_json) d),
+            (SELECT group_concat(json_extract(d.value, '$.serial_number'), ', ')
+             FROM json_each(drives_json) d)
+        FROM checkins
+        WHERE id = ?1
+        "#,
+        [id],
+    )?;
+    Ok(())
+}
+
+/// Make `c` the device's current state, unless the stored state is from the same
+/// instant or later. Timestamps are compared as parsed instants, so offsets and
+/// fractional seconds don't affect the order. Returns whether the row was written.
+pub fn upsert_laptop_if_newer(conn: &Connection, c: &NewCheckin) -> rusqlite::Result<bool> {
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT last_seen_utc FROM laptops WHERE laptop_serial = ?1",
+            [&c.laptop_serial],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(current) = current {
+        if let (Some(stored), Some(incoming)) =
+            (parse_instant(&current), parse_instant(&c.timestamp_utc))
+        {
+            if incoming <= stored {
+                return Ok(false);
+            }
+        }
+    }
+
+    conn.execute(
+        r#"
+        INSERT INTO laptops (
+            laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json,
+            received_at_utc, clock_skew_secs, source_ip, short_name, domain
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+        ON CONFLICT(laptop_serial) DO UPDATE SET
+            hostname=excluded.hostname,
+            short_name=excluded.short_name,
+            domain=excluded.domain,
+            ip_address=excluded.ip_address,
+            logged_in_user=excluded.logged_in_user,
+            last_seen_utc=excluded.last_seen_utc,
+            drives_json=excluded.drives_json,
+            received_at_utc=excluded.received_at_utc,
+            clock_skew_secs=excluded.clock_skew_secs,
+            source_ip=excluded.source_ip
+        "#,
+        params![
+            c.laptop_serial,
+            c.hostname,
+            c.ip_address,
+            c.logged_in_user,
+            c.timestamp_utc,
+            c.drives_json,
+            c.received_at_utc,
+            c.clock_skew_secs,
+            c.source_ip,
+            c.short_name,
+            c.domain
+        ],
+    )?;
+    Ok(true)
+}
+
+/// A stored idempotency key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyKey {
+    pub laptop_serial: String,
+    /// Row in `checkins` stored under the key
+    pub checkin_id: Option<i64>,
+}
+
+/// Look up `key`, ignoring entries created before `not_before` (an RFC 3339 UTC
+/// timestamp in the server's format)
+pub fn find_idempotency_key(
+    conn: &Connection,
+    key: &str,
+    not_before: &str,
+) -> rusqlite::Result<Option<IdempotencyKey>> {
+    conn.query_row(
+        "SELECT laptop_serial, checkin_id FROM idempotency_keys
+         WHERE key = ?1 AND created_at_utc >= ?2",
+        params![key, not_before],
+        |row| {
+            Ok(IdempotencyKey {
+                laptop_serial: row.get(0)?,
+                checkin_id: row.get(1)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Record `key` for a check-in about to be stored. Returns false when the key is
+/// already taken, in which case nothing should be stored for it.
+pub fn claim_idempotency_key(
+    conn: &Connection,
+    key: &str,
+    laptop_serial: &str,
+    created_at_utc: &str,
+) -> rusqlite::Result<bool> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO idempotency_keys (key, laptop_serial, created_at_utc)
+         VALUES (?1, ?2, ?3)",
+        params![key, laptop_serial, created_at_utc],
+    )?;
+    Ok(inserted == 1)
+}
+
+/// Link a claimed key to the check-in stored under it
+pub fn set_idempotency_checkin(
+    conn: &Connection,
+    key: &str,
+    checkin_id: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE idempotency_keys SET checkin_id = ?2 WHERE key = ?1",
+        params![key, checkin_id],
+    )?;
+    Ok(())
+}
+
+/// Forget keys created before `before`, so they can be used again.
+/// Returns how many were removed.
+pub fn prune_idempotency_keys(conn: &Connection, before: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM idempotency_keys WHERE created_at_utc < ?1",
+        [before],
+    )
+}
+
+/// Rejected check-ins from one source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectionCount {
+    /// Observed source address, or `unknown`
+    pub source: String,
+    pub rejected_count: i64,
+    pub last_rejected_utc: String,
+    /// Code of the most recent rejection, e.g. `invalid_hostname`
+    pub last_error: String,
+    /// Rejections per code, most first. Counted from schema version 14 on, so older
+    /// rejections are only in the total.
+    pub by_code: Vec<(String, i64)>,
+}
+
+/// Add `count` rejected payloads with the code `error` to the running totals for
+/// `source`
+pub fn record_rejected_payloads(
+    conn: &Connection,
+    source: &str,
+    count: i64,
+    at_utc: &str,
+    error: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO rejected_payloads (source, rejected_count, last_rejected_utc, last_error)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(source) DO UPDATE SET
+            rejected_count = rejected_count + excluded.rejected_count,
+            last_rejected_utc = excluded.last_rejected_utc,
+            last_error = excluded.last_error
+        "#,
+        params![source, count, at_utc, error],
+    )?;
+    conn.execute(
+        r#"
+        INSERT INTO rejected_payload_codes (source, code, rejected_count, last_rejected_utc)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(source, code) DO UPDATE SET
+            rejected_count = rejected_count + excluded.rejected_count,
+            last_rejected_utc = excluded.last_rejected_utc
+        "#,
+        params![source, error, count, at_utc],
+    )?;
+    Ok(())
+}
+
+/// Rejection totals per source, most rejections first
+pub fn get_rejection_counts(conn: &Connection) -> Result<Vec<RejectionCount>> {
     let mut stmt = conn.prepare(
-        "SELECT laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json
-         FROM laptops
-         ORDER BY last_seen_utc DESC",
+        "SELECT source, rejected_count, last_rejected_utc, last_error FROM rejected_payloads
+         ORDER BY rejected_count DESC, source",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(RejectionCount {
+            source: row.get(0)?,
+            rejected_count: row.get(1)?,
+            last_rejected_utc: row.get(2)?,
+            last_error: row.get(3)?,
+            by_code: Vec::new(),
+        })
+    })?;
+    let mut counts = rows
+        .collect::<Result<Vec<_>, _>>()
+        .context("fetch rejection counts")?;
+
+    let mut stmt = conn.prepare(
+        "SELECT source, code, rejected_count FROM rejected_payload_codes
+         ORDER BY rejected_count DESC, code",
+    )?;
+    let mut by_source: HashMap<String, Vec<(String, i64)>> = HashMap::new();
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    for row in rows {
+        let (source, code, count) = row.context("fetch rejection counts by code")?;
+        by_source.entry(source).or_default().push((code, count));
+    }
+    for count in &mut counts {
+        count.by_code = by_source.remove(&count.source).unwrap_or_default();
+    }
+    Ok(counts)
+}
+
+/// A rejected check-in to keep for inspection and re-processing
+#[derive(Debug, Clone)]
+pub struct NewQuarantined {
+    pub received_at_utc: String,
+    pub source_ip: Option<String>,
+    /// The payload's serial, when it could be read
+    pub laptop_serial: Option<String>,
+    /// The check-in as received, as JSON
+    pub body: String,
+    pub error: String,
+    /// Failed validation rules as a JSON list of `{field, code}`
+    pub errors_json: String,
+}
+
+/// Add a rejected check-in to the quarantine, then drop the oldest entries beyond
+/// `max_rows`
+pub fn insert_quarantined(
+    conn: &Connection,
+    q: &NewQuarantined,
+    max_rows: usize,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO quarantine (
+            received_at_utc, source_ip, laptop_serial, body, error, errors_json
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        "#,
+        params![
+            q.received_at_utc,
+            q.source_ip,
+            q.laptop_serial,
+            q.body,
+            q.error,
+            q.errors_json
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM quarantine WHERE id NOT IN
+         (SELECT id FROM quarantine ORDER BY id DESC LIMIT ?1)",
+        [i64::try_from(max_rows).unwrap_or(i64::MAX)],
+    )?;
+    Ok(())
+}
+
+/// Replace the errors of an entry that failed again
+pub fn update_quarantined_error(
+    conn: &Connection,
+    id: i64,
+    error: &str,
+    errors_json: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE quarantine SET error = ?2, errors_json = ?3 WHERE id = ?1",
+        params![id, error, errors_json],
+    )?;
+    Ok(())
+}
+
+/// Remove an entry; returns whether it existed
+pub fn delete_quarantined(conn: &Connection, id: i64) -> rusqlite::Result<bool> {
+    Ok(conn.execute("DELETE FROM quarantine WHERE id = ?1", [id])? == 1)
+}
+
+/// Quarantined check-ins, newest first
+#[tracing::instrument(skip(conn))]
+pub fn get_quarantined(conn: &Connection) -> Result<Vec<QuarantineRow>> {
+    query_quarantined(conn, None)
+}
+
+/// One quarantined check-in by ID
+pub fn get_quarantined_by_id(conn: &Connection, id: i64) -> Result<Option<QuarantineRow>> {
+    Ok(query_quarantined(conn, Some(id))?.into_iter().next())
+}
+
+fn query_quarantined(conn: &Connection, id: Option<i64>) -> Result<Vec<QuarantineRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, received_at_utc, source_ip, laptop_serial, body, error, errors_json
+         FROM quarantine
+         WHERE ?1 IS NULL OR id = ?1
+         ORDER BY id DESC",
+    )?;
+
+    let rows = stmt.query_map([id], |row| {
+        Ok(QuarantineRow {
+            id: row.get(0)?,
+            received_at_utc: row.get(1)?,
+            source_ip: row.get(2)?,
+            laptop_serial: row.get(3)?,
+            body: row.get(4)?,
+            error: row.get(5)?,
+            errors_json: row.get(6)?,
+        })
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch quarantined checkins")
+}
+
+/// Devices with check-ins matching an FTS5 `query`, best match first, at most `limit`.
+/// Each row describes the device's latest matching check-in, with matched terms in
+/// its fields wrapped in `start` and `end`.
+#[tracing::instrument(skip(conn))]
+pub fn search_checkins(
+    conn: &Connection,
+    query: &str,
+    start: &str,
+    end: &str,
+    limit: usize,
+) -> Result<Vec<SearchRow>> {
+    let mut stmt = conn.prepare(
+        r#"
+        WITH hits AS MATERIALIZED (
+            SELECT rowid AS checkin_id, rank,
+                highlight(search_index, 0, ?2, ?3) AS laptop_serial,
+                highlight(search_index, 1, ?2, ?3) AS hostname,
+                highlight(search_index, 2, ?2, ?3) AS logged_in_user,
+                highlight(search_index, 3, ?2, ?3) AS addresses,
+                highlight(search_index, 4, ?2, ?3) AS drive_models,
+                highlight(search_index, 5, ?2, ?3) AS drive_serials
+            FROM search_index
+            WHERE search_index MATCH ?1
+        ),
+        ranked AS (
+            SELECT h.*, c.laptop_serial AS serial, c.timestamp_utc, c.timestamp_ms, c.id,
+                row_number() OVER device_order AS n,
+                count(*) OVER device AS matches,
+                first_value(c.timestamp_utc) OVER (
+                    PARTITION BY c.laptop_serial ORDER BY c.timestamp_ms, c.id
+                ) AS first_match_utc,
+                min(h.rank) OVER device AS best
+            FROM hits h
+            JOIN checkins c ON c.id = h.checkin_id
+            WINDOW device AS (PARTITION BY c.laptop_serial),
+                device_order AS (
+                    PARTITION BY c.laptop_serial ORDER BY c.timestamp_ms DESC, c.id DESC
+                )
+        )
+        SELECT r.serial, r.laptop_serial, r.hostname, r.logged_in_user, r.addresses,
+            r.drive_models, r.drive_serials, r.matches, r.first_match_utc, r.timestamp_utc,
+            l.last_seen_utc IS r.timestamp_utc
+        FROM ranked r
+        LEFT JOIN laptops l ON l.laptop_serial = r.serial
+        WHERE r.n = 1
+        ORDER BY r.best, r.timestamp_ms DESC, r.id DESC
+        LIMIT ?4
+        "#,
+    )?;
+
+    let rows = stmt.query_map(params![query, start, end, limit as i64], |row| {
+        Ok(SearchRow {
+            serial: row.get(0)?,
+            laptop_serial: row.get(1)?,
+            hostname: row.get(2)?,
+            logged_in_user: row.get(3)?,
+            addresses: row.get(4)?,
+            drive_models: row.get(5)?,
+            drive_serials: row.get(6)?,
+            matching_checkins: row.get(7)?,
+            first_match_utc: row.get(8)?,
+            last_match_utc: row.get(9)?,
+            current: row.get(10)?,
+        })
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("search checkins")
+}
+
+/// Devices with check-ins whose reported or observed address is in any of `ranges`
+/// (first and last [`network::ip_key`]), with their latest such check-in. Most
+/// recently seen there first.
+#[tracing::instrument(skip(conn))]
+pub fn find_checkins_by_address(
+    conn: &Connection,
+    ranges: &[(String, String)],
+    limit: usize,
+) -> Result<Vec<NetworkRow>> {
+    let ranges = serde_json::to_string(ranges)?;
+    let mut stmt = conn.prepare(
+        r#"
+        WITH hits AS MATERIALIZED (
+            SELECT DISTINCT a.checkin_id
+            FROM json_each(?1) r
+            JOIN checkin_addresses a
+              ON a.ip_key BETWEEN r.value ->> '$[0]' AND r.value ->> '$[1]'
+        ),
+        ranked AS (
+            SELECT c.*,
+                row_number() OVER device_order AS n,
+                count(*) OVER device AS matches,
+                first_value(c.timestamp_utc) OVER (
+                    PARTITION BY c.laptop_serial ORDER BY c.timestamp_ms, c.id
+                ) AS first_seen_utc
+            FROM hits h
+            JOIN checkins c ON c.id = h.checkin_id
+            WINDOW device AS (PARTITION BY c.laptop_serial),
+                device_order AS (
+                    PARTITION BY c.laptop_serial ORDER BY c.timestamp_ms DESC, c.id DESC
+                )
+        )
+        SELECT r.laptop_serial, r.hostname, r.ip_address, r.source_ip, r.logged_in_user,
+            r.matches, r.first_seen_utc, r.timestamp_utc, l.ip_address, l.source_ip
+        FROM ranked r
+        LEFT JOIN laptops l ON l.laptop_serial = r.laptop_serial
+        WHERE r.n = 1
+        ORDER BY r.timestamp_ms DESC, r.id DESC
+        LIMIT ?2
+        "#,
+    )?;
+
+    let rows = stmt.query_map(params![ranges, limit as i64], |row| {
+        Ok(NetworkRow {
+            laptop_serial: row.get(0)?,
+            hostname: row.get(1)?,
+            ip_address: row.get(2)?,
+            source_ip: row.get(3)?,
+            logged_in_user: row.get(4)?,
+            matching_checkins: row.get(5)?,
+            first_seen_utc: row.get(6)?,
+            last_seen_utc: row.get(7)?,
+            current_ip_address: row.get(8)?,
+            current_source_ip: row.get(9)?,
+        })
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("find checkins by address")
+}
+
+/// Every `logged_in_user` value with the devices it was reported from, one row per
+/// user and device. Check-ins without a user are left out. Scans the whole history;
+/// use [`get_account_devices`] for one person.
+#[tracing::instrument(skip(conn))]
+pub fn get_user_devices(conn: &Connection) -> Result<Vec<UserDeviceRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{USER_DEVICES_SELECT}
+         WHERE c.logged_in_user <> '' AND c.timestamp_ms IS NOT NULL
+         GROUP BY c.logged_in_user, c.laptop_serial"
+    ))?;
+    let rows = stmt.query_map([], user_device_row)?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch user devices")
+}
+
+/// Like [`get_user_devices`], for the names whose [`users::account_name`] is
+/// `account` only, found on `idx_checkins_user_account`
+#[tracing::instrument(skip(conn))]
+pub fn get_account_devices(conn: &Connection, account: &str) -> Result<Vec<UserDeviceRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{USER_DEVICES_SELECT}
+         WHERE c.user_account = ?1 AND c.timestamp_ms IS NOT NULL
+         GROUP BY c.logged_in_user, c.laptop_serial"
+    ))?;
+    let rows = stmt.query_map([account], user_device_row)?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch account devices")
+}
+
+/// Columns in the order [`user_device_row`] reads them
+const USER_DEVICES_SELECT: &str =
+    "SELECT c.logged_in_user, c.laptop_serial, l.hostname, l.logged_in_user, COUNT(*),
+            strftime('%Y-%m-%dT%H:%M:%SZ', MIN(c.timestamp_ms) / 1000, 'unixepoch'),
+            strftime('%Y-%m-%dT%H:%M:%SZ', MAX(c.timestamp_ms) / 1000, 'unixepoch')
+     FROM checkins c
+     LEFT JOIN laptops l ON l.laptop_serial = c.laptop_serial";
+
+fn user_device_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<UserDeviceRow> {
+    Ok(UserDeviceRow {
+        logged_in_user: row.get(0)?,
+        laptop_serial: row.get(1)?,
+        hostname: row.get(2)?,
+        current_user: row.get(3)?,
+        checkins: row.get(4)?,
+        first_seen_utc: row.get(5)?,
+        last_seen_utc: row.get(6)?,
+    })
+}
+
+/// Tags, notes and custom field values of one device; empty when none were entered
+pub fn get_device_meta(conn: &Connection, serial: &str) -> Result<DeviceMeta> {
+    Ok(query_device_meta(conn, Some(serial))?
+        .remove(serial)
+        .unwrap_or_default())
+}
+
+/// Tags, notes and custom field values of every device that has any, by serial
+#[tracing::instrument(skip(conn))]
+pub fn get_all_device_meta(conn: &Connection) -> Result<HashMap<String, DeviceMeta>> {
+    query_device_meta(conn, None)
+}
+
+fn query_device_meta(
+    conn: &Connection,
+    serial: Option<&str>,
+) -> Result<HashMap<String, DeviceMeta>> {
+    let mut meta = HashMap::<String, DeviceMeta>::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT laptop_serial, tag FROM device_tags
+         WHERE ?1 IS NULL OR laptop_serial = ?1 ORDER BY laptop_serial, tag",
+    )?;
+    let mut rows = stmt.query([serial])?;
+    while let Some(row) = rows.next()? {
+        meta.entry(row.get(0)?).or_default().tags.push(row.get(1)?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT laptop_serial, notes, updated_at_utc FROM device_notes
+         WHERE ?1 IS NULL OR laptop_serial = ?1",
+    )?;
+    let mut rows = stmt.query([serial])?;
+    while let Some(row) = rows.next()? {
+        let entry = meta.entry(row.get(0)?).or_default();
+        entry.notes = Some(row.get(1)?);
+        entry.notes_updated_utc = Some(row.get(2)?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT laptop_serial, key, value FROM device_fields
+         WHERE ?1 IS NULL OR laptop_serial = ?1",
+    )?;
+    let mut rows = stmt.query([serial])?;
+    while let Some(row) = rows.next()? {
+        meta.entry(row.get(0)?)
+            .or_default()
+            .fields
+            .insert(row.get(1)?, row.get(2)?);
+    }
+
+    Ok(meta)
+}
+
+/// Tag a device; returns false if it already had the tag, in any case
+pub fn add_device_tag(conn: &Connection, serial: &str, tag: &str) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "INSERT OR IGNORE INTO device_tags (laptop_serial, tag) VALUES (?1, ?2)",
+        [serial, tag],
+    )? == 1)
+}
+
+/// Remove a tag, matched without regard to case; returns whether the device had it
+pub fn remove_device_tag(conn: &Connection, serial: &str, tag: &str) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "DELETE FROM device_tags WHERE laptop_serial = ?1 AND tag = ?2",
+        [serial, tag],
+    )? == 1)
+}
+
+/// Replace a device's notes; empty notes remove them
+pub fn set_device_notes(
+    conn: &Connection,
+    serial: &str,
+    notes: &str,
+    at_utc: &str,
+) -> rusqlite::Result<()> {
+    if notes.is_empty() {
+        conn.execute(
+            "DELETE FROM device_notes WHERE laptop_serial = ?1",
+            [serial],
+        )?;
+    } else {
+        conn.execute(
+            "INSERT OR REPLACE INTO device_notes (laptop_serial, notes, updated_at_utc)
+             VALUES (?1, ?2, ?3)",
+            [serial, notes, at_utc],
+        )?;
+    }
+    Ok(())
+}
+
+/// Set or, with `None`, clear a custom field value
+pub fn set_device_field(
+    conn: &Connection,
+    serial: &str,
+    key: &str,
+    value: Option<&str>,
+    at_utc: &str,
+) -> rusqlite::Result<()> {
+    match value {
+        Some(value) => conn.execute(
+            "INSERT INTO device_fields (laptop_serial, key, value, updated_at_utc)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(laptop_serial, key) DO UPDATE SET
+                 value = excluded.value,
+                 updated_at_utc = excluded.updated_at_utc
+             WHERE value <> excluded.value",
+            [serial, key, value, at_utc],
+        )?,
+        None => conn.execute(
+            "DELETE FROM device_fields WHERE laptop_serial = ?1 AND key = ?2",
+            [serial, key],
+        )?,
+    };
+    Ok(())
+}
+
+/// A lifecycle state change to record
+#[derive(Debug, Clone)]
+pub struct NewLifecycleEvent {
+    pub laptop_serial: String,
+    pub state: String,
+    pub changed_at_utc: String,
+    pub changed_at_ms: i64,
+    pub changed_by: String,
+    pub reason: String,
+}
+
+/// Record a state change. It settles any alert about check-ins in the previous state.
+pub fn insert_lifecycle_event(conn: &Connection, e: &NewLifecycleEvent) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO lifecycle_events (
+           laptop_serial, state, changed_at_utc, changed_at_ms, changed_by, reason
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            e.laptop_serial,
+            e.state,
+            e.changed_at_utc,
+            e.changed_at_ms,
+            e.changed_by,
+            e.reason
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    conn.execute(
+        "DELETE FROM lifecycle_alerts WHERE laptop_serial = ?1",
+        [&e.laptop_serial],
+    )?;
+    Ok(id)
+}
+
+const LIFECYCLE_EVENT_SELECT: &str =
+    "SELECT id, laptop_serial, state, changed_at_utc, changed_by, reason FROM lifecycle_events";
+
+fn lifecycle_event_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<LifecycleEventRow> {
+    Ok(LifecycleEventRow {
+        id: row.get(0)?,
+        laptop_serial: row.get(1)?,
+        state: row.get(2)?,
+        changed_at_utc: row.get(3)?,
+        changed_by: row.get(4)?,
+        reason: row.get(5)?,
+    })
+}
+
+/// State changes of one device, latest first, up to `as_of` (Unix milliseconds)
+/// when given
+pub fn get_lifecycle_events(
+    conn: &Connection,
+    serial: &str,
+    as_of_ms: Option<i64>,
+) -> Result<Vec<LifecycleEventRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{LIFECYCLE_EVENT_SELECT}
+         WHERE laptop_serial = ?1 AND (?2 IS NULL OR changed_at_ms <= ?2)
+         ORDER BY changed_at_ms DESC, id DESC"
+    ))?;
+    let rows = stmt.query_map(params![serial, as_of_ms], lifecycle_event_row)?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch lifecycle events")
+}
+
+/// Latest state change of every device that has one, by serial, as of `as_of`
+/// (Unix milliseconds) when given
+#[tracing::instrument(skip(conn))]
+pub fn get_lifecycle_states(
+    conn: &Connection,
+    as_of_ms: Option<i64>,
+) -> Result<HashMap<String, LifecycleEventRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{LIFECYCLE_EVENT_SELECT}
+         WHERE id IN (
+           SELECT (SELECT id FROM lifecycle_events e
+                   WHERE e.laptop_serial = s.laptop_serial
+                     AND (?1 IS NULL OR e.changed_at_ms <= ?1)
+                   ORDER BY e.changed_at_ms DESC, e.id DESC LIMIT 1)
+           FROM (SELECT DISTINCT laptop_serial FROM lifecycle_events) s
+         )"
+    ))?;
+    let rows = stmt.query_map([as_of_ms], lifecycle_event_row)?;
+    rows.map(|r| r.map(|row| (row.laptop_serial.clone(), row)))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .context("fetch lifecycle states")
+}
+
+/// Count a check-in against its device when the device was lost, retired or
+/// disposed of before the check-in's time. Returns the state it was in if so.
+pub fn flag_lifecycle_checkin(
+    conn: &Connection,
+    c: &NewCheckin,
+) -> rusqlite::Result<Option<String>> {
+    let at = parse_instant(&c.timestamp_utc).or_else(|| parse_instant(&c.received_at_utc));
+    let state: Option<String> = conn
+        .query_row(
+            "SELECT state FROM lifecycle_events
+             WHERE laptop_serial = ?1 AND changed_at_ms < ?2
+             ORDER BY changed_at_ms DESC, id DESC LIMIT 1",
+            params![
+                c.laptop_serial,
+                at.map_or(i64::MAX, |t| t.timestamp_millis())
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(state) = state.filter(|s| LifecycleState::from_key(s).flags_checkins()) else {
+        return Ok(None);
+    };
+    conn.execute(
+        "INSERT INTO lifecycle_alerts (
+           laptop_serial, state, checkins, first_received_utc, last_received_utc
+         ) VALUES (?1, ?2, 1, ?3, ?3)
+         ON CONFLICT(laptop_serial) DO UPDATE SET
+           checkins = checkins + 1,
+           last_received_utc = max(last_received_utc, excluded.last_received_utc)",
+        params![c.laptop_serial, state, c.received_at_utc],
+    )?;
+    Ok(Some(state))
+}
+
+/// Unsettled alerts about check-ins from devices out of service, by serial
+pub fn get_lifecycle_alerts(conn: &Connection) -> Result<HashMap<String, LifecycleAlertRow>> {
+    query_lifecycle_alerts(conn, None)
+}
+
+/// The unsettled alert about one device, if any
+pub fn get_lifecycle_alert(conn: &Connection, serial: &str) -> Result<Option<LifecycleAlertRow>> {
+    Ok(query_lifecycle_alerts(conn, Some(serial))?.remove(serial))
+}
+
+fn query_lifecycle_alerts(
+    conn: &Connection,
+    serial: Option<&str>,
+) -> Result<HashMap<String, LifecycleAlertRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT laptop_serial, state, checkins, first_received_utc, last_received_utc
+         FROM lifecycle_alerts WHERE ?1 IS NULL OR laptop_serial = ?1",
+    )?;
+    let rows = stmt.query_map([serial], |row| {
+        Ok(LifecycleAlertRow {
+            laptop_serial: row.get(0)?,
+            state: row.get(1)?,
+            checkins: row.get(2)?,
+            first_received_utc: row.get(3)?,
+            last_received_utc: row.get(4)?,
+        })
+    })?;
+    rows.map(|r| r.map(|row| (row.laptop_serial.clone(), row)))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .context("fetch lifecycle alerts")
+}
+
+/// Device a check-in reporting `serial` from `hostname` is stored under, when
+/// remapped. A remap for the hostname wins over one for any host.
+pub fn find_serial_remap(
+    conn: &Connection,
+    serial: &str,
+    hostname: &str,
+) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT laptop_serial FROM serial_remaps
+         WHERE reported_serial = ?1 AND (hostname = ?2 OR hostname = '')
+         ORDER BY hostname = '' LIMIT 1",
+        params![serial, hostname],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// A remap to record; an empty `hostname` applies to any host
+#[derive(Debug, Clone)]
+pub struct NewSerialRemap {
+    pub reported_serial: String,
+    pub hostname: String,
+    pub laptop_serial: String,
+    pub created_at_utc: String,
+    pub created_by: String,
+    pub reason: String,
+}
+
+fn insert_serial_remap(conn: &Connection, r: &NewSerialRemap) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO serial_remaps (
+           reported_serial, hostname, laptop_serial, created_at_utc, created_by, reason
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            r.reported_serial,
+            r.hostname,
+            r.laptop_serial,
+            r.created_at_utc,
+            r.created_by,
+            r.reason
+        ],
     )?;
+    Ok(())
+}
+
+/// All remaps, by reported serial and hostname
+pub fn get_serial_remaps(conn: &Connection) -> Result<Vec<SerialRemapRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT reported_serial, hostname, laptop_serial, created_at_utc, created_by, reason
+         FROM serial_remaps ORDER BY reported_serial, hostname",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SerialRemapRow {
+            reported_serial: row.get(0)?,
+            hostname: row.get(1)?,
+            laptop_serial: row.get(2)?,
+            created_at_utc: row.get(3)?,
+            created_by: row.get(4)?,
+            reason: row.get(5)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch serial remaps")
+}
+
+/// Stop remapping a serial; returns whether there was such a remap
+pub fn delete_serial_remap(
+    conn: &Connection,
+    reported_serial: &str,
+    hostname: &str,
+) -> rusqlite::Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM serial_remaps WHERE reported_serial = ?1 AND hostname = ?2",
+        params![reported_serial, hostname],
+    )?;
+    Ok(deleted > 0)
+}
+
+/// Hostname and drives JSON of a device's current state
+pub fn get_laptop_identity(
+    conn: &Connection,
+    serial: &str,
+) -> rusqlite::Result<Option<(String, String)>> {
+    conn.query_row(
+        "SELECT hostname, drives_json FROM laptops WHERE laptop_serial = ?1",
+        [serial],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+/// Note a check-in under `serial` that looks like it came from another machine
+/// than the device's current state
+pub fn record_serial_collision(
+    conn: &Connection,
+    serial: &str,
+    hostname: &str,
+    detected_at_utc: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO serial_collisions (
+           laptop_serial, detections, first_detected_utc, last_detected_utc, last_hostname
+         ) VALUES (?1, 1, ?2, ?2, ?3)
+         ON CONFLICT(laptop_serial) DO UPDATE SET
+           detections = detections + 1,
+           last_detected_utc = max(last_detected_utc, excluded.last_detected_utc),
+           last_hostname = excluded.last_hostname",
+        params![serial, detected_at_utc, hostname],
+    )?;
+    Ok(())
+}
+
+/// Serials that check-ins from different machines were stored under, latest first
+pub fn get_serial_collisions(conn: &Connection) -> Result<Vec<SerialCollisionRow>> {
+    query_serial_collisions(conn, None)
+}
+
+/// The collision noted for one serial, if any
+pub fn get_serial_collision(conn: &Connection, serial: &str) -> Result<Option<SerialCollisionRow>> {
+    Ok(query_serial_collisions(conn, Some(serial))?.pop())
+}
+
+fn query_serial_collisions(
+    conn: &Connection,
+    serial: Option<&str>,
+) -> Result<Vec<SerialCollisionRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT laptop_serial, detections, first_detected_utc, last_detected_utc, last_hostname
+         FROM serial_collisions WHERE ?1 IS NULL OR laptop_serial = ?1
+         ORDER BY last_detected_utc DESC",
+    )?;
+    let rows = stmt.query_map([serial], |row| {
+        Ok(SerialCollisionRow {
+            laptop_serial: row.get(0)?,
+            detections: row.get(1)?,
+            first_detected_utc: row.get(2)?,
+            last_detected_utc: row.get(3)?,
+            last_hostname: row.get(4)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch serial collisions")
+}
+
+/// Forget a collision, e.g. once the admin has looked at it; returns whether there
+/// was one
+pub fn delete_serial_collision(conn: &Connection, serial: &str) -> rusqlite::Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM serial_collisions WHERE laptop_serial = ?1",
+        [serial],
+    )?;
+    Ok(deleted > 0)
+}
+
+/// Hostnames in a device's check-in history, with how often and when each was seen,
+/// latest first. Check-ins are ordered by instant, and each hostname is shown as last
+/// reported.
+pub fn get_device_hostnames(conn: &Connection, serial: &str) -> Result<Vec<HostnameCount>> {
+    let mut stmt = conn.prepare(
+        r#"
+        WITH ranked AS (
+            SELECT hostname, timestamp_utc, timestamp_ms, id,
+                row_number() OVER host_order AS n,
+                count(*) OVER host AS checkins,
+                first_value(timestamp_utc) OVER (
+                    PARTITION BY hostname COLLATE NOCASE ORDER BY timestamp_ms, id
+                ) AS first_seen_utc
+            FROM checkins
+            WHERE laptop_serial = ?1
+            WINDOW host AS (PARTITION BY hostname COLLATE NOCASE),
+                host_order AS (
+                    PARTITION BY hostname COLLATE NOCASE ORDER BY timestamp_ms DESC, id DESC
+                )
+        )
+        SELECT hostname, checkins, first_seen_utc, timestamp_utc
+        FROM ranked
+        WHERE n = 1
+        ORDER BY timestamp_ms DESC, id DESC
+        "#,
+    )?;
+    let rows = stmt.query_map([serial], |row| {
+        Ok(HostnameCount {
+            hostname: row.get(0)?,
+            checkins: row.get(1)?,
+            first_seen_utc: row.get(2)?,
+            last_seen_utc: row.get(3)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch device hostnames")
+}
+
+/// Drives JSON of the latest check-in from `hostname` under `serial`
+pub fn latest_drives_for_hostname(
+    conn: &Connection,
+    serial: &str,
+    hostname: &str,
+) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        &format!(
+            "SELECT drives_json FROM checkins
+             WHERE laptop_serial = ?1 AND hostname = ?2 COLLATE NOCASE
+             ORDER BY {CURRENT_STATE_ORDER} LIMIT 1"
+        ),
+        params![serial, hostname],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Move check-ins, by ID, to the device `target`, keeping the serial each was
+/// reported with
+fn move_checkins(conn: &Connection, ids: &[i64], target: &str) -> rusqlite::Result<()> {
+    let mut checkins = conn.prepare(
+        "UPDATE checkins
+         SET reported_serial = coalesce(reported_serial, laptop_serial), laptop_serial = ?2
+         WHERE id = ?1",
+    )?;
+    let mut search = conn.prepare("UPDATE search_index SET laptop_serial = ?2 WHERE rowid = ?1")?;
+    let mut keys =
+        conn.prepare("UPDATE idempotency_keys SET laptop_serial = ?2 WHERE checkin_id = ?1")?;
+    for id in ids {
+        checkins.execute(params![id, target])?;
+        search.execute(params![id, target])?;
+        keys.execute(params![id, target])?;
+    }
+    Ok(())
+}
+
+fn checkin_ids(
+    conn: &Connection,
+    serial: &str,
+    hostname: Option<&str>,
+) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM checkins
+         WHERE laptop_serial = ?1 AND (?2 IS NULL OR hostname = ?2 COLLATE NOCASE)",
+    )?;
+    let rows = stmt.query_map(params![serial, hostname], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Rebuild a device's current state from its latest check-in by
+/// [`CURRENT_STATE_ORDER`], as ingest would have left it, or remove it when it has
+/// none left
+fn refresh_laptop(conn: &Connection, serial: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM laptops WHERE laptop_serial = ?1", [serial])?;
+    conn.execute(
+        &format!(
+            "INSERT INTO laptops (
+           laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json,
+           received_at_utc, clock_skew_secs, source_ip, short_name, domain
+         )
+         SELECT laptop_serial, hostname, ip_address, logged_in_user, timestamp_utc, drives_json,
+           received_at_utc, clock_skew_secs, source_ip, short_name, domain
+         FROM checkins WHERE laptop_serial = ?1
+         ORDER BY {CURRENT_STATE_ORDER} LIMIT 1"
+        ),
+        [serial],
+    )?;
+    Ok(())
+}
+
+/// Merge the device `source` into `target`: its check-ins, tags, notes, custom
+/// fields and lifecycle history move over, and later check-ins reporting `source`
+/// are stored under `target`. Values `target` already has are kept. Run inside a
+/// transaction; returns the number of check-ins moved.
+pub fn merge_devices(conn: &Connection, remap: &NewSerialRemap) -> rusqlite::Result<usize> {
+    let (source, target) = (&remap.reported_serial, &remap.laptop_serial);
+    let ids = checkin_ids(conn, source, None)?;
+    move_checkins(conn, &ids, target)?;
+    conn.execute(
+        "UPDATE idempotency_keys SET laptop_serial = ?2 WHERE laptop_serial = ?1",
+        params![source, target],
+    )?;
+
+    conn.execute(
+        "INSERT OR IGNORE INTO device_tags (laptop_serial, tag)
+         SELECT ?2, tag FROM device_tags WHERE laptop_serial = ?1",
+        params![source, target],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO device_fields (laptop_serial, key, value, updated_at_utc)
+         SELECT ?2, key, value, updated_at_utc FROM device_fields WHERE laptop_serial = ?1",
+        params![source, target],
+    )?;
+    conn.execute(
+        "INSERT INTO device_notes (laptop_serial, notes, updated_at_utc)
+         SELECT ?2, notes, updated_at_utc FROM device_notes WHERE laptop_serial = ?1
+         ON CONFLICT(laptop_serial) DO UPDATE SET
+           notes = notes || char(10) || char(10) || excluded.notes,
+           updated_at_utc = max(updated_at_utc, excluded.updated_at_utc)",
+        params![source, target],
+    )?;
+    for table in [
+        "device_tags",
+        "device_fields",
+        "device_notes",
+        "lifecycle_alerts",
+        "serial_collisions",
+    ] {
+        conn.execute(
+            &format!("DELETE FROM {table} WHERE laptop_serial = ?1"),
+            [source],
+        )?;
+    }
+    conn.execute(
+        "UPDATE lifecycle_events SET laptop_serial = ?2 WHERE laptop_serial = ?1",
+        params![source, target],
+    )?;
+
+    // Remaps to the source now lead to the target, and none may lead back to itself
+    conn.execute(
+        "UPDATE serial_remaps SET laptop_serial = ?2 WHERE laptop_serial = ?1",
+        params![source, target],
+    )?;
+    insert_serial_remap(conn, remap)?;
+    conn.execute(
+        "DELETE FROM serial_remaps WHERE reported_serial = laptop_serial",
+        [],
+    )?;
+
+    refresh_laptop(conn, source)?;
+    refresh_laptop(conn, target)?;
+    Ok(ids.len())
+}
+
+/// Split the check-ins from `remap.hostname` off the device `remap.reported_serial`
+/// into the device `remap.laptop_serial`, and store later check-ins from that host
+/// reporting the serial there too. Tags, notes and other details stay with the
+/// original device. Run inside a transaction; returns the number of check-ins moved.
+pub fn split_device(conn: &Connection, remap: &NewSerialRemap) -> rusqlite::Result<usize> {
+    let ids = checkin_ids(conn, &remap.reported_serial, Some(&remap.hostname))?;
+    move_checkins(conn, &ids, &remap.laptop_serial)?;
+    insert_serial_remap(conn, remap)?;
+    conn.execute(
+        "DELETE FROM serial_collisions WHERE laptop_serial = ?1",
+        [&remap.reported_serial],
+    )?;
+    refresh_laptop(conn, &remap.reported_serial)?;
+    refresh_laptop(conn, &remap.laptop_serial)?;
+    Ok(ids.len())
+}
+
+/// Parse a stored RFC 3339 timestamp; `None` for values that don't parse
+pub fn parse_instant(ts: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(ts)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Fetch all laptops, most recently seen first. Ordered by the instant of each one's
+/// current check-in, as [`get_all_laptops_as_of`] orders its snapshot, since
+/// `last_seen_utc` keeps the agent's offset and doesn't sort as text; a laptop with
+/// no check-ins sorts by the instant `last_seen_utc` names.
+#[tracing::instrument(skip(conn))]
+pub fn get_all_laptops(conn: &Connection) -> Result<Vec<LaptopRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.laptop_serial, l.hostname, l.ip_address, l.logged_in_user, l.last_seen_utc,
+                l.drives_json, l.received_at_utc, l.clock_skew_secs, l.source_ip, l.short_name,
+                l.domain
+         FROM laptops l
+         LEFT JOIN checkins c ON c.id = ({})
+         ORDER BY coalesce(c.timestamp_ms, unixepoch(l.last_seen_utc, 'subsec') * 1000) DESC,
+                  c.id ASC",
+        current_checkin()
+    ))?;
 
     let rows = stmt.query_map([], |row| {
         Ok(LaptopRow {
@@ -56,6 +1520,11 @@ pub fn get_all_laptops(conn: &Connection) -> Result<Vec<LaptopRow>> {
             logged_in_user: row.get(3)?,
             last_seen_utc: row.get(4)?,
             drives_json: row.get(5)?,
+            received_at_utc: row.get(6)?,
+            clock_skew_secs: row.get(7)?,
+            source_ip: row.get(8)?,
+            short_name: row.get(9)?,
+            domain: row.get(10)?,
         })
     })?;
 
@@ -64,9 +1533,11 @@ pub fn get_all_laptops(conn: &Connection) -> Result<Vec<LaptopRow>> {
 }
 
 /// Fetch a single laptop by serial number
+#[tracing::instrument(skip(conn))]
 pub fn get_laptop_by_serial(conn: &Connection, serial: &str) -> Result<Option<LaptopRow>> {
     let mut stmt = conn.prepare(
-        "SELECT laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json
+        "SELECT laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json,
+                received_at_utc, clock_skew_secs, source_ip, short_name, domain
          FROM laptops
          WHERE laptop_serial = ?1",
     )?;
@@ -79,6 +1550,11 @@ pub fn get_laptop_by_serial(conn: &Connection, serial: &str) -> Result<Option<La
             logged_in_user: row.get(3)?,
             last_seen_utc: row.get(4)?,
             drives_json: row.get(5)?,
+            received_at_utc: row.get(6)?,
+            clock_skew_secs: row.get(7)?,
+            source_ip: row.get(8)?,
+            short_name: row.get(9)?,
+            domain: row.get(10)?,
         })
     })?;
 
@@ -88,24 +1564,141 @@ pub fn get_laptop_by_serial(conn: &Connection, serial: &str) -> Result<Option<La
     }
 }
 
-/// Fetch check-in history for a specific laptop, ordered by timestamp descending
+/// Each device's state as of `as_of`: the fields of its latest check-in at or before
+/// that instant, most recent first. Devices that hadn't checked in yet are left out.
+/// One index seek per device on `idx_checkins_serial_time`, so a whole-fleet
+/// snapshot costs about as much as listing the current fleet.
+#[tracing::instrument(skip(conn))]
+pub fn get_all_laptops_as_of(conn: &Connection, as_of: DateTime<Utc>) -> Result<Vec<LaptopRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{LAPTOP_AS_OF_SELECT}
+         FROM laptops l
+         JOIN checkins c ON c.id = ({})
+         ORDER BY c.timestamp_ms DESC, c.id ASC",
+        latest_checkin_as_of()
+    ))?;
+
+    let rows = stmt.query_map([as_of.timestamp_millis()], laptop_as_of_row)?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch laptops as of")
+}
+
+/// A single device's state as of `as_of`; `None` if it hadn't checked in by then
+#[tracing::instrument(skip(conn))]
+pub fn get_laptop_by_serial_as_of(
+    conn: &Connection,
+    serial: &str,
+    as_of: DateTime<Utc>,
+) -> Result<Option<LaptopRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "{LAPTOP_AS_OF_SELECT}
+         FROM laptops l
+         JOIN checkins c ON c.id = ({})
+         WHERE l.laptop_serial = ?2",
+        latest_checkin_as_of()
+    ))?;
+
+    stmt.query_row(params![as_of.timestamp_millis(), serial], laptop_as_of_row)
+        .optional()
+        .context("fetch laptop as of")
+}
+
+/// Check-in columns in the order [`laptop_as_of_row`] reads them
+const LAPTOP_AS_OF_SELECT: &str =
+    "SELECT c.laptop_serial, c.hostname, c.ip_address, c.logged_in_user, c.timestamp_utc,
+            c.drives_json, c.received_at_utc, c.clock_skew_secs, c.source_ip, c.short_name,
+            c.domain";
+
+/// Order of a device's check-ins with the one that is its current state first. Ties
+/// on the instant go to the check-in stored first, as [`upsert_laptop_if_newer`] only
+/// replaces the state with a strictly later check-in.
+const CURRENT_STATE_ORDER: &str = "timestamp_ms DESC, id ASC";
+
+/// Subquery for the ID of the latest check-in of `l.laptop_serial` at or before `?1`
+/// (Unix ms), by [`CURRENT_STATE_ORDER`]
+fn latest_checkin_as_of() -> String {
+    format!(
+        "SELECT id FROM checkins
+         WHERE laptop_serial = l.laptop_serial AND timestamp_ms <= ?1
+         ORDER BY {CURRENT_STATE_ORDER}
+         LIMIT 1"
+    )
+}
+
+/// Subquery for the ID of the check-in `l.laptop_serial`'s current state came from
+fn current_checkin() -> String {
+    format!(
+        "SELECT id FROM checkins
+         WHERE laptop_serial = l.laptop_serial
+         ORDER BY {CURRENT_STATE_ORDER}
+         LIMIT 1"
+    )
+}
+
+fn laptop_as_of_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<LaptopRow> {
+    Ok(LaptopRow {
+        laptop_serial: row.get(0)?,
+        hostname: row.get(1)?,
+        ip_address: row.get(2)?,
+        logged_in_user: row.get(3)?,
+        last_seen_utc: row.get(4)?,
+        drives_json: row.get(5)?,
+        received_at_utc: row.get(6)?,
+        clock_skew_secs: row.get(7)?,
+        source_ip: row.get(8)?,
+        short_name: row.get(9)?,
+        domain: row.get(10)?,
+    })
+}
+
+/// Check-ins of a laptop at or before `as_of`, ordered by timestamp descending
+#[tracing::instrument(skip(conn))]
+pub fn get_checkins_by_serial_as_of(
+    conn: &Connection,
+    serial: &str,
+    as_of: DateTime<Utc>,
+) -> Result<Vec<CheckinRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT hostname, ip_address, logged_in_user, timestamp_utc, received_at_utc, clock_skew_secs,
+                source_ip, reported_serial
+         FROM checkins
+         WHERE laptop_serial = ?1 AND timestamp_ms <= ?2
+         ORDER BY timestamp_ms DESC, id DESC",
+    )?;
+
+    let rows = stmt.query_map(params![serial, as_of.timestamp_millis()], checkin_row)?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .context("fetch checkins by serial as of")
+}
+
+/// Fetch check-in history for a specific laptop, latest instant first
+#[tracing::instrument(skip(conn))]
 pub fn get_checkins_by_serial(conn: &Connection, serial: &str) -> Result<Vec<CheckinRow>> {
     let mut stmt = conn.prepare(
-        "SELECT hostname, ip_address, logged_in_user, timestamp_utc
+        "SELECT hostname, ip_address, logged_in_user, timestamp_utc, received_at_utc, clock_skew_secs,
+                source_ip, reported_serial
          FROM checkins
          WHERE laptop_serial = ?1
-         ORDER BY timestamp_utc DESC",
+         ORDER BY timestamp_ms DESC, id DESC",
     )?;
 
-    let rows = stmt.query_map([serial], |row| {
-        Ok(CheckinRow {
-            hostname: row.get(0)?,
-            ip_address: row.get(1)?,
-            logged_in_user: row.get(2)?,
-            timestamp_utc: row.get(3)?,
-        })
-    })?;
+    let rows = stmt.query_map([serial], checkin_row)?;
 
     rows.collect::<Result<Vec<_>, _>>()
         .context("fetch checkins by serial")
 }
+
+fn checkin_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<CheckinRow> {
+    Ok(CheckinRow {
+        hostname: row.get(0)?,
+        ip_address: row.get(1)?,
+        logged_in_user: row.get(2)?,
+        timestamp_utc: row.get(3)?,
+        received_at_utc: row.get(4)?,
+        clock_skew_secs: row.get(5)?,
+        source_ip: row.get(6)?,
+        reported_serial: row.get(7)?,
+    })
+}
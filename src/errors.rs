@@ -1,17 +1,75 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
+use serde::Serialize;
+
+use crate::{
+    rate_limit::Rejected,
+    request_id,
+    schema::PayloadError,
+    validation::{self, FieldError},
+};
+
+/// JSON body returned for check-in failures. Carries the request ID so agents can
+/// report it and operators can find the matching server log entries.
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub error: &'static str,
+    /// Specifics the client can act on, e.g. which versions are supported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Each failed validation rule, when `validation.detailed_errors` is enabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorBody {
+    fn response(status: StatusCode, error: &'static str) -> Response {
+        Self::detailed(status, error, None)
+    }
+
+    pub(crate) fn detailed(
+        status: StatusCode,
+        error: &'static str,
+        detail: Option<String>,
+    ) -> Response {
+        let body = ErrorBody {
+            error,
+            detail,
+            errors: Vec::new(),
+            request_id: request_id::current(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
 
 /// Error types for the checkin endpoint
 #[derive(Debug)]
 pub enum CheckInError {
     /// Validation failed on input data
     ValidationFailed(validator::ValidationErrors),
+    /// Validation failed, and the client is told which fields failed
+    ValidationDetailed(validator::ValidationErrors),
     /// Database operation error
     DatabaseError(rusqlite::Error),
     /// JSON serialization error
     SerializationError(serde_json::Error),
+    /// Batch body that couldn't be split into items, or has too many
+    InvalidBatch(&'static str),
+    /// `timestamp_utc` outside the clock skew window, with the skew in seconds
+    ClockSkew(i64),
+    /// Refused by a rate limit (already counted and logged by the limiter)
+    RateLimited(Rejected),
+    /// Body that can't be read as a check-in of a supported schema version
+    InvalidPayload(PayloadError),
+    /// Malformed `Idempotency-Key` header
+    InvalidIdempotencyKey,
+    /// Idempotency key already stored for a different `laptop_serial`
+    IdempotencyConflict,
 }
 
 impl IntoResponse for CheckInError {
@@ -24,7 +82,20 @@ impl IntoResponse for CheckInError {
                     "Input validation failed"
                 );
                 // Return generic error to client
-                (StatusCode::BAD_REQUEST, "Invalid input data").into_response()
+                ErrorBody::response(StatusCode::BAD_REQUEST, "Invalid input data")
+            }
+            Self::ValidationDetailed(e) => {
+                tracing::warn!(
+                    validation_errors = ?e,
+                    "Input validation failed"
+                );
+                let body = ErrorBody {
+                    error: "Invalid input data",
+                    detail: None,
+                    errors: validation::field_errors(&e),
+                    request_id: request_id::current(),
+                };
+                (StatusCode::BAD_REQUEST, Json(body)).into_response()
             }
             Self::DatabaseError(e) => {
                 // Log detailed database error internally
@@ -33,7 +104,7 @@ impl IntoResponse for CheckInError {
                     "Database operation failed"
                 );
                 // Return generic error to client
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+                ErrorBody::response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
             }
             Self::SerializationError(e) => {
                 // Log detailed serialization error internally
@@ -42,8 +113,76 @@ impl IntoResponse for CheckInError {
                     "JSON serialization failed"
                 );
                 // Return generic error to client
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+                ErrorBody::response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            Self::InvalidBatch(reason) => {
+                tracing::warn!(reason, "Batch check-in rejected");
+                ErrorBody::response(StatusCode::BAD_REQUEST, reason)
             }
+            Self::ClockSkew(skew_secs) => {
+                tracing::warn!(skew_secs, "Check-in timestamp outside clock skew window");
+                ErrorBody::response(
+                    StatusCode::BAD_REQUEST,
+                    "timestamp_utc is outside the allowed clock skew",
+                )
+            }
+            Self::RateLimited(rejected) => {
+                let mut response =
+                    ErrorBody::response(StatusCode::TOO_MANY_REQUESTS, "Too many requests");
+                // Whole seconds, rounded up so a client honoring it isn't refused again
+                let secs = rejected.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+                response
+            }
+            Self::InvalidPayload(e @ PayloadError::UnsupportedVersion(_)) => {
+                tracing::warn!(error = %e, "Check-in uses an unsupported schema version");
+                ErrorBody::detailed(
+                    StatusCode::BAD_REQUEST,
+                    "Unsupported schema_version",
+                    Some(e.to_string()),
+                )
+            }
+            Self::InvalidPayload(e @ PayloadError::Invalid(_)) => {
+                tracing::warn!(error = %e, "Check-in payload could not be read");
+                // Same status axum's JSON extractor uses for a body of the wrong shape
+                ErrorBody::detailed(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Invalid check-in payload",
+                    Some(e.to_string()),
+                )
+            }
+            Self::InvalidIdempotencyKey => {
+                tracing::warn!("Check-in rejected: invalid Idempotency-Key header");
+                ErrorBody::response(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key header")
+            }
+            Self::IdempotencyConflict => {
+                tracing::warn!("Check-in rejected: idempotency key reused for another device");
+                ErrorBody::response(
+                    StatusCode::CONFLICT,
+                    "Idempotency key was already used for a different device",
+                )
+            }
+        }
+    }
+}
+
+impl CheckInError {
+    /// Code recorded against the source when the payload itself was refused, e.g.
+    /// `invalid_hostname`; `None` for limits and server errors
+    pub fn rejection_code(&self) -> Option<String> {
+        match self {
+            Self::ValidationFailed(e) | Self::ValidationDetailed(e) => Some(
+                validation::field_errors(e)
+                    .into_iter()
+                    .next()
+                    .map(|f| f.code)
+                    .unwrap_or_else(|| "invalid_input".to_string()),
+            ),
+            Self::InvalidPayload(e) => Some(e.code().to_string()),
+            Self::ClockSkew(_) => Some("clock_skew".to_string()),
+            _ => None,
         }
     }
 }
@@ -66,3 +205,9 @@ impl From<serde_json::Error> for CheckInError {
         CheckInError::SerializationError(e)
     }
 }
+
+impl From<PayloadError> for CheckInError {
+    fn from(e: PayloadError) -> Self {
+        CheckInError::InvalidPayload(e)
+    }
+}
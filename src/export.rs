@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    handlers::{self, IndexQuery},
+    metadata,
+    models::IndexLaptopRow,
+    AppState,
+};
+
+/// GET /export/devices.csv - The devices listed on the index, with the same
+/// filters, as CSV with their tags, notes, lifecycle state and custom fields
+#[tracing::instrument(skip_all)]
+pub async fn devices_csv(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<IndexQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let settings = state.settings();
+    let fleet = handlers::load_fleet(&state, &settings, query)?;
+
+    let mut heading: Vec<String> = [
+        "Serial",
+        "Hostname",
+        "Domain",
+        "IP Address",
+        "Location",
+        "Logged In User",
+        "Last Seen (UTC)",
+        "Drive Serials",
+        "Tags",
+        "Notes",
+        "State",
+        "Check-ins While Out of Service",
+    ]
+    .map(str::to_string)
+    .into();
+    heading.extend(
+        settings
+            .custom_fields
+            .iter()
+            .map(|(key, field)| metadata::label(key, field).to_string()),
+    );
+
+    let mut csv = String::new();
+    push_record(&mut csv, &heading);
+    for laptop in &fleet.laptops {
+        push_record(&mut csv, &record(laptop, &settings.custom_fields));
+    }
+    tracing::info!(devices = fleet.laptops.len(), "Exported devices as CSV");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"devices.csv\"",
+            ),
+        ],
+        csv,
+    )
+        .into_response())
+}
+
+fn record(laptop: &IndexLaptopRow, fields: &metadata::CustomFields) -> Vec<String> {
+    let mut record = vec![
+        laptop.laptop_serial.clone(),
+        laptop.hostname.clone(),
+        laptop.domain.clone().unwrap_or_default(),
+        laptop.ip_address.clone(),
+        laptop.location.clone().unwrap_or_default(),
+        laptop.logged_in_user.clone().unwrap_or_default(),
+        laptop.last_seen_utc.clone(),
+        laptop.drive_serials.join("; "),
+        laptop.meta.tags.join("; "),
+        laptop.meta.notes.clone().unwrap_or_default(),
+        laptop.lifecycle_state.label().to_string(),
+        laptop
+            .lifecycle_alert
+            .as_ref()
+            .map(|a| a.checkins.to_string())
+            .unwrap_or_default(),
+    ];
+    record.extend(fields.iter().map(|(key, field)| {
+        laptop
+            .meta
+            .fields
+            .get(key)
+            .map(|v| metadata::display_value(field.kind, v))
+            .unwrap_or_default()
+    }));
+    record
+}
+
+/// Append one CSV line. Cells are quoted when needed, and text a spreadsheet would
+/// run as a formula is prefixed with `'`.
+fn push_record(csv: &mut String, cells: &[String]) {
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        let formula =
+            cell.starts_with(['=', '+', '-', '@', '\t', '\r']) && cell.parse::<f64>().is_err();
+        let cell = if formula {
+            format!("'{cell}")
+        } else {
+            cell.clone()
+        };
+        if cell.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&cell.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(&cell);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(cells: &[&str]) -> String {
+        let mut csv = String::new();
+        push_record(
+            &mut csv,
+            &cells.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        );
+        csv
+    }
+
+    #[test]
+    fn test_push_record_quotes() {
+        assert_eq!(line(&["SN1", "", "a b"]), "SN1,,a b\r\n");
+        assert_eq!(
+            line(&["a,b", "say \"hi\"", "two\nlines"]),
+            "\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_push_record_defuses_formulas() {
+        assert_eq!(
+            line(&["=1+1", "@SUM(A1)", "-3.5"]),
+            "'=1+1,'@SUM(A1),-3.5\r\n"
+        );
+        assert_eq!(line(&["+x,y"]), "\"'+x,y\"\r\n");
+    }
+}
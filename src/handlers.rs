@@ -1,18 +1,34 @@
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use rusqlite::params;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::sync::Arc;
-use validator::Validate;
+use std::time::Duration;
 
 use crate::{
-    db,
+    client_ip::ClientIp,
+    clock_skew, db,
     errors::CheckInError,
-    models::{CheckIn, CheckinRow, Drive, IndexLaptopRow, LaptopRow},
-    AppState,
+    hostname, idempotency, identity,
+    lifecycle::{self, LifecycleState, StateFilter},
+    logging,
+    metadata::{self, FieldValue, TagCount},
+    models::{
+        BatchItemResult, BatchResponse, CheckIn, CheckinRow, DeviceMeta, Drive, IndexLaptopRow,
+        LaptopRow, LifecycleAlertRow, LifecycleEventRow, SerialCollisionRow,
+    },
+    network, quarantine, schema,
+    snapshot::{AsOf, AsOfQuery},
+    users,
+    validation::{self, FieldError},
+    AppState, Settings,
 };
 
 // ============== Template Structs ==============
@@ -21,109 +37,520 @@ use crate::{
 #[template(path = "index.html")]
 pub struct IndexTemplate {
     pub laptops: Vec<IndexLaptopRow>,
+    /// Devices per domain across the fleet, empty when no device reported a domain
+    pub domains: Vec<DomainCount>,
+    /// Domain the list is narrowed to, from `?domain=`
+    pub domain_filter: Option<String>,
+    /// Devices per tag across the fleet
+    pub tags: Vec<TagCount>,
+    /// Tag the list is narrowed to, from `?tag=`
+    pub tag_filter: Option<String>,
+    /// Custom field the list is narrowed to, from `?field=` and `?value=`
+    pub field_filter: Option<FieldFilter>,
+    /// Keys and labels of the configured custom fields
+    pub fields: Vec<(String, String)>,
+    /// Devices per lifecycle state across the fleet, then the flagged ones
+    pub states: Vec<StateCount>,
+    /// Lifecycle states listed, from `?state=`
+    pub state_filter: StateFilter,
+    /// Retired and disposed devices left out of the default list
+    pub hidden: usize,
+    /// Instant the fleet is shown as of, from `?as_of=`; `None` for the current state
+    pub as_of: Option<AsOf>,
+    /// Whether subnets are configured, so devices have a location
+    pub show_location: bool,
 }
 
-#[derive(Template)]
-#[template(path = "device.html")]
-pub struct DeviceTemplate {
-    pub laptop: LaptopRow,
-    pub drives: Vec<Drive>,
-    pub checkins: Vec<CheckinRow>,
+impl IndexTemplate {
+    /// `&as_of=...` to append to links, empty for the current state
+    pub fn as_of_suffix(&self) -> String {
+        as_of_suffix(self.as_of.as_ref(), '&')
+    }
+
+    /// `?as_of=...` to append to device links, empty for the current state
+    pub fn device_suffix(&self) -> String {
+        as_of_suffix(self.as_of.as_ref(), '?')
+    }
+
+    /// Whether the list is narrowed by the custom field `key`
+    pub fn is_field_filter(&self, key: &str) -> bool {
+        self.field_filter.as_ref().is_some_and(|f| f.key == key)
+    }
+
+    /// Lifecycle states listed, for the heading; `None` for the default
+    pub fn state_label(&self) -> Option<&'static str> {
+        match self.state_filter {
+            StateFilter::Active => None,
+            StateFilter::All => Some("including retired and disposed"),
+            StateFilter::Only(state) => Some(state.label()),
+            StateFilter::Flagged => Some("checked in while out of service"),
+        }
+    }
+
+    /// Whether the list is narrowed to the group with `?state=value`
+    pub fn is_state_filter(&self, value: &str) -> bool {
+        self.state_filter.param() == Some(value)
+    }
+
+    /// Query string selecting the devices listed, for the export link
+    pub fn filter_query(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(domain) = &self.domain_filter {
+            params.push(("domain", domain.as_str()));
+        }
+        if let Some(tag) = &self.tag_filter {
+            params.push(("tag", tag.as_str()));
+        }
+        params.extend(self.state_filter.param().map(|s| ("state", s)));
+        if let Some(filter) = &self.field_filter {
+            params.push(("field", filter.key.as_str()));
+            params.extend(filter.value.as_deref().map(|v| ("value", v)));
+        }
+        let as_of = self.as_of.as_ref().map(AsOf::param);
+        params.extend(as_of.as_deref().map(|a| ("as_of", a)));
+
+        params
+            .iter()
+            .enumerate()
+            .map(|(i, (name, value))| {
+                let sep = if i == 0 { '?' } else { '&' };
+                format!("{sep}{name}={}", metadata::encode(value))
+            })
+            .collect()
+    }
 }
 
-// ============== Web Handlers ==============
+/// Query string carrying `as_of` over to another page, starting with `sep`
+fn as_of_suffix(as_of: Option<&AsOf>, sep: char) -> String {
+    as_of.map_or_else(String::new, |a| {
+        // Only digits, '-', ':', '.', 'T' and 'Z' occur, none needing encoding
+        format!("{sep}as_of={}", a.param())
+    })
+}
 
-/// GET / - Display all laptops
-pub async fn index(
-    State(state): State<Arc<AppState>>,
-) -> Result<IndexTemplate, (StatusCode, String)> {
+/// Number of devices in one domain, `None` for devices reporting a bare name
+pub struct DomainCount {
+    pub domain: Option<String>,
+    pub count: usize,
+}
+
+impl DomainCount {
+    /// `?domain=` value selecting this group; `-` can't be a domain
+    pub fn filter_value(&self) -> &str {
+        self.domain.as_deref().unwrap_or(NO_DOMAIN)
+    }
+}
+
+/// `?domain=` value for devices without a domain
+const NO_DOMAIN: &str = "-";
+
+/// Devices whose custom field `key` has `value`, or any value when `None`
+#[derive(Debug, Clone)]
+pub struct FieldFilter {
+    pub key: String,
+    pub label: String,
+    /// Value as stored
+    pub value: Option<String>,
+    /// Value as shown
+    pub display: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexQuery {
+    domain: Option<String>,
+    tag: Option<String>,
+    field: Option<String>,
+    value: Option<String>,
+    state: Option<String>,
+    as_of: Option<String>,
+}
+
+/// The devices selected by an [`IndexQuery`], as listed on the index and exported
+pub(crate) struct Fleet {
+    pub laptops: Vec<IndexLaptopRow>,
+    pub domains: Vec<DomainCount>,
+    pub domain_filter: Option<String>,
+    pub tags: Vec<TagCount>,
+    pub tag_filter: Option<String>,
+    pub field_filter: Option<FieldFilter>,
+    pub states: Vec<StateCount>,
+    pub state_filter: StateFilter,
+    /// Devices left out by the default filter because they are retired or disposed
+    pub hidden: usize,
+    pub as_of: Option<AsOf>,
+}
+
+/// Number of devices in one lifecycle state, or flagged for checking in while
+/// out of service when `state` is `None`
+pub struct StateCount {
+    pub state: Option<LifecycleState>,
+    pub count: usize,
+}
+
+impl StateCount {
+    /// `?state=` value selecting this group
+    pub fn filter_value(&self) -> &'static str {
+        self.state.map_or("flagged", LifecycleState::key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.state
+            .map_or("Checked in while out of service", LifecycleState::label)
+    }
+}
+
+/// Load the fleet, current or as of `?as_of=`, narrowed by the query's filters
+pub(crate) fn load_fleet(
+    state: &AppState,
+    settings: &Settings,
+    query: IndexQuery,
+) -> Result<Fleet, (StatusCode, String)> {
+    let as_of =
+        AsOf::from_param(query.as_of.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let field_filter = match query.field.as_deref().filter(|f| !f.is_empty()) {
+        Some(key) => Some(field_filter(key, query.value.as_deref(), settings)?),
+        None => None,
+    };
+    let state_filter = StateFilter::from_param(query.state.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
     let conn = rusqlite::Connection::open(&state.db_path)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))?;
 
-    let laptop_rows = db::get_all_laptops(&conn).map_err(|e| {
+    let laptop_rows = match &as_of {
+        Some(as_of) => db::get_all_laptops_as_of(&conn, as_of.instant),
+        None => db::get_all_laptops(&conn),
+    }
+    .map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("query laptops: {e}"),
         )
     })?;
+    let mut metas = db::get_all_device_meta(&conn).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("query device metadata: {e}"),
+        )
+    })?;
+
+    // States as of the same instant as the devices; alerts are about check-ins
+    // received up to now
+    let lifecycle_states =
+        db::get_lifecycle_states(&conn, as_of.as_ref().map(|a| a.instant.timestamp_millis()))
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("query lifecycle states: {e}"),
+                )
+            })?;
+    let mut alerts = if as_of.is_some() {
+        Default::default()
+    } else {
+        db::get_lifecycle_alerts(&conn).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("query lifecycle alerts: {e}"),
+            )
+        })?
+    };
+    let state_of = |serial: &str| {
+        lifecycle_states
+            .get(serial)
+            .map_or_else(LifecycleState::default, |e| {
+                LifecycleState::from_key(&e.state)
+            })
+    };
+
+    let mut state_counts = BTreeMap::<LifecycleState, usize>::new();
+    for row in &laptop_rows {
+        *state_counts
+            .entry(state_of(&row.laptop_serial))
+            .or_default() += 1;
+    }
+    let mut states: Vec<StateCount> = state_counts
+        .into_iter()
+        .map(|(state, count)| StateCount {
+            state: Some(state),
+            count,
+        })
+        .collect();
+    let flagged = laptop_rows
+        .iter()
+        .filter(|row| alerts.contains_key(&row.laptop_serial))
+        .count();
+    if flagged > 0 {
+        states.push(StateCount {
+            state: None,
+            count: flagged,
+        });
+    }
+    // Domain and tag counts cover the devices in the selected states
+    let (laptop_rows, left_out): (Vec<_>, Vec<_>) = laptop_rows.into_iter().partition(|row| {
+        state_filter.matches(
+            state_of(&row.laptop_serial),
+            alerts.contains_key(&row.laptop_serial),
+        )
+    });
+    let hidden = if state_filter == StateFilter::Active {
+        left_out.len()
+    } else {
+        0
+    };
+
+    let mut counts = BTreeMap::<Option<String>, usize>::new();
+    for row in &laptop_rows {
+        *counts.entry(row.domain.clone()).or_default() += 1;
+    }
+    // Named domains alphabetically, then devices without one
+    let (mut domains, bare): (Vec<_>, Vec<_>) = counts
+        .into_iter()
+        .map(|(domain, count)| DomainCount { domain, count })
+        .partition(|d| d.domain.is_some());
+    if !domains.is_empty() {
+        domains.extend(bare);
+    }
+    let tags = metadata::tag_counts(
+        laptop_rows
+            .iter()
+            .filter_map(|row| metas.get(&row.laptop_serial)),
+    );
+
+    let domain_filter = query.domain.filter(|d| !d.is_empty());
+    let wanted = domain_filter
+        .as_deref()
+        .map(|d| (d != NO_DOMAIN).then(|| d.to_ascii_lowercase()));
+    let tag_filter = query
+        .tag
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    let wanted_tag = tag_filter.as_deref().map(str::to_lowercase);
 
     // Convert LaptopRow to IndexLaptopRow with parsed drives
     let laptops: Vec<IndexLaptopRow> = laptop_rows
         .into_iter()
+        .filter(|row| wanted.as_ref().is_none_or(|w| *w == row.domain))
         .map(|row| {
-            let drives: Vec<Drive> = serde_json::from_str::<Vec<Drive>>(&row.drives_json)
+            let meta = metas.remove(&row.laptop_serial).unwrap_or_default();
+            (row, meta)
+        })
+        .filter(|(_, meta)| {
+            wanted_tag
+                .as_ref()
+                .is_none_or(|w| meta.tags.iter().any(|t| t.to_lowercase() == *w))
+        })
+        .filter(|(_, meta)| {
+            field_filter.as_ref().is_none_or(|f| {
+                let value = meta.fields.get(&f.key);
+                f.value
+                    .as_ref()
+                    .map_or(value.is_some(), |v| value == Some(v))
+            })
+        })
+        .map(|(row, meta)| {
+            let drive_serials = serde_json::from_str::<Vec<Drive>>(&row.drives_json)
                 .unwrap_or_default()
                 .into_iter()
-                .map(|mut d| {
-                    d.device_id = d.device_id.trim_start_matches("\\\\.\\").to_string();
-                    d
-                })
+                .filter_map(|d| d.serial_number)
                 .collect();
-            let drive_serials_display = {
-                let serials: Vec<&str> = drives
-                    .iter()
-                    .filter_map(|d| d.serial_number.as_deref())
-                    .collect();
-                if serials.is_empty() {
-                    "-".to_string()
-                } else {
-                    serials.join("<br>")
-                }
-            };
+            let clock_skew_warning = skew_warning(row.clock_skew_secs, settings);
+            let addresses =
+                std::iter::once(row.ip_address.as_str()).chain(row.source_ip.as_deref());
+            let location = network::location(addresses, &settings.subnets).map(str::to_string);
             IndexLaptopRow {
+                location,
+                clock_skew_warning,
+                meta,
+                lifecycle_state: state_of(&row.laptop_serial),
+                lifecycle_alert: alerts.remove(&row.laptop_serial),
                 laptop_serial: row.laptop_serial,
+                short_name: row.short_name.unwrap_or_else(|| row.hostname.clone()),
+                domain: row.domain,
                 hostname: row.hostname,
                 ip_address: row.ip_address,
                 logged_in_user: row.logged_in_user,
                 last_seen_utc: row.last_seen_utc,
-                drive_serials_display,
+                drive_serials,
             }
         })
         .collect();
 
-    Ok(IndexTemplate { laptops })
+    Ok(Fleet {
+        laptops,
+        domains,
+        domain_filter,
+        tags,
+        tag_filter,
+        field_filter,
+        states,
+        state_filter,
+        hidden,
+        as_of,
+    })
+}
+
+/// Filter for `?field=key&value=...`, the value read as the field's type
+fn field_filter(
+    key: &str,
+    value: Option<&str>,
+    settings: &Settings,
+) -> Result<FieldFilter, (StatusCode, String)> {
+    let field = settings
+        .custom_fields
+        .get(key)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown field: {key}")))?;
+    let label = metadata::label(key, field).to_string();
+    let value = metadata::parse_value(field, value.unwrap_or_default())
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{label}: {e}")))?;
+    Ok(FieldFilter {
+        key: key.to_string(),
+        display: value
+            .as_deref()
+            .map(|v| metadata::display_value(field.kind, v)),
+        label,
+        value,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "device.html")]
+pub struct DeviceTemplate {
+    pub laptop: LaptopRow,
+    pub drives: Vec<Drive>,
+    pub checkins: Vec<CheckinRow>,
+    /// Set when the latest check-in's clock skew is outside the configured window
+    pub clock_skew_warning: Option<String>,
+    /// Instant the device is shown as of; `None` for its current state
+    pub as_of: Option<AsOf>,
+    /// Named subnet the device's address is in
+    pub location: Option<String>,
+    /// Tags and notes, always the current ones
+    pub meta: DeviceMeta,
+    /// Configured custom fields and the device's values
+    pub fields: Vec<FieldValue>,
+    /// State as of the same instant as the device
+    pub lifecycle_state: LifecycleState,
+    /// State changes up to then, latest first
+    pub lifecycle_events: Vec<LifecycleEventRow>,
+    /// Check-ins since the device was marked lost, retired or disposed; current
+    /// state only
+    pub lifecycle_alert: Option<LifecycleAlertRow>,
+    /// Check-ins from what looked like another machine; current state only
+    pub collision: Option<SerialCollisionRow>,
+    /// For the forms that edit tags, notes, fields and the lifecycle state
+    pub csrf_token: String,
+}
+
+impl DeviceTemplate {
+    /// `?as_of=...` for links back to the fleet at the same instant
+    pub fn as_of_suffix(&self) -> String {
+        as_of_suffix(self.as_of.as_ref(), '?')
+    }
+
+    /// Path the edit forms post to, below the device's page
+    pub fn edit_path(&self) -> String {
+        metadata::device_path(&self.laptop.laptop_serial)
+    }
+
+    /// Admin page for splitting this device's history or merging it into another
+    pub fn identity_path(&self) -> String {
+        identity::split_path(&self.laptop.laptop_serial)
+    }
+
+    /// Page of a user seen on this device, by the name that finds them from anywhere
+    pub fn user_path(&self, user: &str) -> String {
+        let name = users::qualified_name(user, &self.laptop.hostname);
+        format!("/user/{}", metadata::encode(&name))
+    }
+
+    /// States to choose from when changing it
+    pub fn states(&self) -> [LifecycleState; 6] {
+        LifecycleState::ALL
+    }
+
+    /// Label of a stored state key
+    pub fn state_label(&self, key: &str) -> &'static str {
+        LifecycleState::from_key(key).label()
+    }
 }
 
-/// GET /device/:serial - Display device details and check-in history
+// ============== Web Handlers ==============
+
+/// GET / - Display all laptops, or the fleet as it was at `?as_of=`, optionally
+/// narrowed to a domain, tag or custom field value
+#[tracing::instrument(skip_all)]
+pub async fn index(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<IndexQuery>,
+) -> Result<IndexTemplate, (StatusCode, String)> {
+    let settings = state.settings();
+    let fleet = load_fleet(&state, &settings, query)?;
+    let fields = settings
+        .custom_fields
+        .iter()
+        .map(|(key, field)| (key.clone(), metadata::label(key, field).to_string()))
+        .collect();
+
+    Ok(IndexTemplate {
+        laptops: fleet.laptops,
+        domains: fleet.domains,
+        domain_filter: fleet.domain_filter,
+        tags: fleet.tags,
+        tag_filter: fleet.tag_filter,
+        field_filter: fleet.field_filter,
+        fields,
+        states: fleet.states,
+        state_filter: fleet.state_filter,
+        hidden: fleet.hidden,
+        as_of: fleet.as_of,
+        show_location: !settings.subnets.is_empty(),
+    })
+}
+
+/// GET /device/:serial - Display device details and check-in history, or the
+/// device as it was at `?as_of=` with the check-ins up to then
+#[tracing::instrument(skip(state))]
 pub async fn device_detail(
     State(state): State<Arc<AppState>>,
     Path(serial): Path<String>,
+    Query(query): Query<AsOfQuery>,
 ) -> Result<DeviceTemplate, (StatusCode, String)> {
-    if state.debug_mode {
-        println!(
-            "[DEBUG] device_detail called with serial: {:?} (len={})",
-            serial,
-            serial.len()
-        );
-        println!("[DEBUG] db_path: {}", state.db_path);
+    let settings = state.settings();
+    let as_of =
+        AsOf::from_param(query.as_of.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if settings.debug_mode {
+        tracing::debug!(serial = ?serial, serial_len = serial.len(), "device_detail called");
     }
 
     let conn = rusqlite::Connection::open(&state.db_path)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))?;
 
-    // Debug: list all serials in DB
-    if state.debug_mode {
+    // Debug: list all serials in DB to diagnose lookups that miss
+    if settings.debug_mode {
         if let Ok(laptops) = db::get_all_laptops(&conn) {
-            println!("[DEBUG] All laptops in DB:");
-            for l in &laptops {
-                println!(
-                    "[DEBUG]   serial: {:?} (len={})",
-                    l.laptop_serial,
-                    l.laptop_serial.len()
-                );
-            }
+            let serials: Vec<&str> = laptops.iter().map(|l| l.laptop_serial.as_str()).collect();
+            tracing::debug!(known_serials = ?serials, "Serials present in database");
         }
     }
 
     // Fetch laptop
-    let laptop = db::get_laptop_by_serial(&conn, &serial)
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("query laptop: {e}"),
-            )
-        })?
-        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Device not found: {serial}")))?;
+    let laptop = match &as_of {
+        Some(as_of) => db::get_laptop_by_serial_as_of(&conn, &serial, as_of.instant),
+        None => db::get_laptop_by_serial(&conn, &serial),
+    }
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("query laptop: {e}"),
+        )
+    })?
+    .ok_or_else(|| match &as_of {
+        Some(as_of) => (
+            StatusCode::NOT_FOUND,
+            format!("Device {serial} had not checked in by {}", as_of.label()),
+        ),
+        None => (StatusCode::NOT_FOUND, format!("Device not found: {serial}")),
+    })?;
 
     // Parse drives from JSON and clean up device_id (remove \\.\  prefix)
     let drives: Vec<Drive> = serde_json::from_str::<Vec<Drive>>(&laptop.drives_json)
@@ -136,47 +563,208 @@ pub async fn device_detail(
         .collect();
 
     // Fetch check-in history
-    let checkins = db::get_checkins_by_serial(&conn, &serial).map_err(|e| {
+    let checkins = match &as_of {
+        Some(as_of) => db::get_checkins_by_serial_as_of(&conn, &serial, as_of.instant),
+        None => db::get_checkins_by_serial(&conn, &serial),
+    }
+    .map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("query checkins: {e}"),
         )
     })?;
 
+    let meta = db::get_device_meta(&conn, &serial).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("query device metadata: {e}"),
+        )
+    })?;
+
+    let lifecycle_events = db::get_lifecycle_events(
+        &conn,
+        &serial,
+        as_of.as_ref().map(|a| a.instant.timestamp_millis()),
+    )
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("query lifecycle events: {e}"),
+        )
+    })?;
+    let lifecycle_alert = if as_of.is_some() {
+        None
+    } else {
+        db::get_lifecycle_alert(&conn, &serial).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("query lifecycle alert: {e}"),
+            )
+        })?
+    };
+    let collision = if as_of.is_some() {
+        None
+    } else {
+        db::get_serial_collision(&conn, &serial).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("query serial collision: {e}"),
+            )
+        })?
+    };
+
+    let clock_skew_warning = skew_warning(laptop.clock_skew_secs, &settings);
+    let addresses = std::iter::once(laptop.ip_address.as_str()).chain(laptop.source_ip.as_deref());
+    let location = network::location(addresses, &settings.subnets).map(str::to_string);
+
     Ok(DeviceTemplate {
         laptop,
         drives,
         checkins,
+        clock_skew_warning,
+        as_of,
+        location,
+        fields: metadata::field_values(&settings.custom_fields, &meta),
+        meta,
+        lifecycle_state: lifecycle_events
+            .first()
+            .map_or_else(LifecycleState::default, |e| {
+                LifecycleState::from_key(&e.state)
+            }),
+        lifecycle_events,
+        lifecycle_alert,
+        collision,
+        csrf_token: state.csrf_token.as_str().to_string(),
     })
 }
 
+/// Describe a recorded skew when it is outside the window in `settings`
+fn skew_warning(skew_secs: Option<i64>, settings: &Settings) -> Option<String> {
+    skew_secs
+        .filter(|s| clock_skew::outside_window(*s, &settings.clock_skew))
+        .map(|s| format!("Agent clock {}", clock_skew::describe(s)))
+}
+
 // ============== API Handlers ==============
 
+#[tracing::instrument(skip_all, fields(laptop_serial))]
 pub async fn checkin(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CheckIn>,
-) -> Result<StatusCode, CheckInError> {
+    ClientIp(source_ip): ClientIp,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Response, CheckInError> {
+    let received = chrono::Utc::now();
+    let result = accept_checkin(&state, source_ip, &headers, body.clone(), received);
+    if let Err(e) = &result {
+        if let Some(code) = e.rejection_code() {
+            count_rejected(&state, source_ip, 1, &code);
+        }
+        if let Some((error, errors)) = quarantine::reason(e) {
+            let serial = body
+                .get("laptop_serial")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let entry = quarantine::entry(
+                received,
+                source_ip,
+                serial,
+                body.to_string(),
+                &error,
+                &errors,
+            );
+            quarantine::store(&state, &state.settings(), &[entry]);
+        }
+    }
+    result
+}
+
+/// Parse, validate and store one check-in
+fn accept_checkin(
+    state: &AppState,
+    source_ip: Option<IpAddr>,
+    headers: &HeaderMap,
+    body: serde_json::Value,
+    received: chrono::DateTime<chrono::Utc>,
+) -> Result<Response, CheckInError> {
+    let settings = state.settings();
+
+    // Older payload versions are upgraded to the current model before validation
+    let payload = schema::parse_checkin(body)?;
+    tracing::Span::current().record("laptop_serial", payload.laptop_serial.as_str());
+
     // Validate input data
-    payload.validate()?;
-
-    if state.debug_mode {
-        println!(
-            "[DEBUG] Checkin received: hostname={}, serial={}, ip={}, user={}, timestamp={}",
-            payload.hostname,
-            payload.laptop_serial,
-            payload.ip_address,
-            payload.logged_in_user.as_deref().unwrap_or("(none)"),
-            payload.timestamp_utc
+    let idempotency_key = idempotency::from_headers(headers)?.or(payload.checkin_id.clone());
+    payload
+        .validate_with(&settings.validation)
+        .map_err(|e| validation_failed(e, &settings))?;
+
+    let key_cutoff = idempotency::cutoff(received, settings.idempotency_key_retention_hours);
+
+    // The device the check-in belongs to, which differs from the reported serial for
+    // placeholders and remapped serials
+    let laptop_serial = {
+        let conn = rusqlite::Connection::open(&state.db_path)?;
+        let laptop_serial = identity::resolve(&conn, &payload, &settings.identity)?;
+
+        // A retry of a stored check-in gets the original result before any limit
+        // applies, so an agent that lost the first response isn't refused for checking
+        // in too often
+        if let Some(key) = &idempotency_key {
+            if let Some(stored) = db::find_idempotency_key(&conn, key, &key_cutoff)? {
+                return replayed(&stored, &laptop_serial);
+            }
+        }
+        laptop_serial
+    };
+
+    let assessed = clock_skew::assess(&payload.timestamp_utc, received, &settings.clock_skew)
+        .map_err(CheckInError::ClockSkew)?;
+    if assessed.clamped {
+        tracing::warn!(
+            laptop_serial = %payload.laptop_serial,
+            timestamp_utc = %payload.timestamp_utc,
+            skew_secs = assessed.skew_secs,
+            "Check-in timestamp outside clock skew window, using receive time"
+        );
+    }
+    state
+        .limiter
+        .check_device(
+            &laptop_serial,
+            settings.limits.per_serial_per_minute,
+            Duration::from_secs(settings.limits.min_checkin_interval_secs),
+        )
+        .map_err(CheckInError::RateLimited)?;
+    if settings.debug_mode {
+        tracing::debug!(
+            hostname = %payload.hostname,
+            laptop_serial = %payload.laptop_serial,
+            ip_address = %payload.ip_address,
+            source_ip = ?source_ip,
+            logged_in_user = %logging::user_for_log(
+                payload.logged_in_user.as_deref(),
+                settings.redact_user_names
+            ),
+            timestamp_utc = %payload.timestamp_utc,
+            drives = ?payload.drives,
+            "Checkin received"
         );
-        println!("[DEBUG] Drives: {:?}", payload.drives);
     }
 
-    let drives_json = serde_json::to_string(&payload.drives)?;
+    // Stored under the serial before remaps; storing applies them, as for batches
+    let device_serial = identity::device_serial(&payload, &settings.identity);
+    let mut checkin = new_checkin(
+        payload,
+        assessed,
+        source_ip.map(|ip| ip.to_string()),
+        device_serial,
+    )?;
 
     // One connection per request is fine for SQLite WAL at this scale.
     let mut conn = rusqlite::Connection::open(&state.db_path).map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
+            laptop_serial = %checkin.laptop_serial,
             error = ?e,
             "Failed to open database connection"
         );
@@ -189,86 +777,440 @@ pub async fn checkin(
     )
     .map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
+            laptop_serial = %checkin.laptop_serial,
             error = ?e,
             "Failed to set database pragmas"
         );
         CheckInError::DatabaseError(e)
     })?;
 
+    let _db_span = tracing::info_span!("record_checkin").entered();
+
     let tx = conn.transaction().map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
+            laptop_serial = %checkin.laptop_serial,
             error = ?e,
             "Failed to begin transaction"
         );
         CheckInError::DatabaseError(e)
     })?;
 
-    tx.execute(
-        r#"
-        INSERT INTO checkins (
-            laptop_serial, hostname, ip_address, logged_in_user, timestamp_utc, drives_json
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-        "#,
-        params![
-            payload.laptop_serial,
-            payload.hostname,
-            payload.ip_address,
-            payload.logged_in_user,
-            payload.timestamp_utc,
-            drives_json
-        ],
-    )
-    .map_err(|e| {
+    if idempotency_key.is_some() {
+        db::prune_idempotency_keys(&tx, &key_cutoff)?;
+    }
+    let current = match store_prepared(&tx, &mut checkin, idempotency_key.as_deref(), &key_cutoff)?
+    {
+        Stored::Inserted { current } => current,
+        // Stored by a concurrent request since the lookup above
+        Stored::Replayed => {
+            drop(tx);
+            tracing::info!(
+                laptop_serial = %checkin.laptop_serial,
+                "Replayed check-in, not stored again"
+            );
+            return Ok(replay_response());
+        }
+        Stored::Conflict => return Err(CheckInError::IdempotencyConflict),
+    };
+    if !current {
+        tracing::info!(
+            laptop_serial = %checkin.laptop_serial,
+            timestamp_utc = %checkin.timestamp_utc,
+            "Check-in older than current state, recorded in history only"
+        );
+    }
+
+    tx.commit().map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
-            hostname = %payload.hostname,
+            laptop_serial = %checkin.laptop_serial,
             error = ?e,
-            "Failed to insert checkin record"
+            "Failed to commit transaction"
         );
         CheckInError::DatabaseError(e)
     })?;
 
-    tx.execute(
-        r#"
-        INSERT INTO laptops (
-            laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-        ON CONFLICT(laptop_serial) DO UPDATE SET
-            hostname=excluded.hostname,
-            ip_address=excluded.ip_address,
-            logged_in_user=excluded.logged_in_user,
-            last_seen_utc=excluded.last_seen_utc,
-            drives_json=excluded.drives_json
-        "#,
-        params![
-            payload.laptop_serial,
-            payload.hostname,
-            payload.ip_address,
-            payload.logged_in_user,
-            payload.timestamp_utc,
-            drives_json
-        ],
+    state.limiter.record_accepted(&checkin.laptop_serial);
+
+    Ok(StatusCode::OK.into_response())
+}
+
+/// Validation error for the response, detailed when the settings allow it
+fn validation_failed(errors: validator::ValidationErrors, settings: &Settings) -> CheckInError {
+    if settings.validation.detailed_errors {
+        CheckInError::ValidationDetailed(errors)
+    } else {
+        CheckInError::ValidationFailed(errors)
+    }
+}
+
+/// Add `count` rejected payloads from `source_ip` to the stored totals. A failure
+/// is logged and otherwise ignored, so it never changes the response.
+fn count_rejected(state: &AppState, source_ip: Option<IpAddr>, count: i64, code: &str) {
+    let source = source_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    let at = clock_skew::format_utc(chrono::Utc::now());
+    let result = rusqlite::Connection::open(&state.db_path)
+        .and_then(|conn| db::record_rejected_payloads(&conn, &source, count, &at, code));
+    if let Err(e) = result {
+        tracing::error!(source, error = ?e, "Failed to record rejected check-in");
+    }
+}
+
+/// Response for a check-in whose idempotency key is already stored: the original
+/// result, or a conflict when the key belongs to another device
+fn replayed(stored: &db::IdempotencyKey, laptop_serial: &str) -> Result<Response, CheckInError> {
+    if stored.laptop_serial != laptop_serial {
+        return Err(CheckInError::IdempotencyConflict);
+    }
+    tracing::info!(
+        laptop_serial,
+        checkin_id = ?stored.checkin_id,
+        "Replayed check-in, not stored again"
+    );
+    Ok(replay_response())
+}
+
+/// Success response marked as a replay of a stored check-in
+fn replay_response() -> Response {
+    (
+        StatusCode::OK,
+        [(idempotency::IDEMPOTENT_REPLAYED.clone(), "true")],
     )
-    .map_err(|e| {
+        .into_response()
+}
+
+/// POST /checkin/batch - Store check-ins queued by offline agents or relay hosts.
+///
+/// The body is a JSON array of check-ins, or one check-in per line with an NDJSON
+/// content type. Items are validated independently and the valid ones are stored
+/// oldest first in a single transaction, so an older snapshot never replaces a newer
+/// current state. Per-device rate limits don't apply to individual items, and items
+/// whose `checkin_id` is already stored are reported as replayed without being stored.
+#[tracing::instrument(skip_all)]
+pub async fn checkin_batch(
+    State(state): State<Arc<AppState>>,
+    ClientIp(source_ip): ClientIp,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<BatchResponse>, CheckInError> {
+    let received = chrono::Utc::now();
+    let settings = state.settings();
+
+    let items = split_batch(&headers, &body)?;
+    if items.len() > settings.limits.max_batch_items {
+        return Err(CheckInError::InvalidBatch("Too many items in batch"));
+    }
+
+    let client = source_ip;
+    let source_ip = source_ip.map(|ip| ip.to_string());
+    let mut results: Vec<Option<BatchItemResult>> = Vec::with_capacity(items.len());
+    let mut ready = Vec::new();
+    // Rejected items per code, counted against the source once per code
+    let mut rejected: BTreeMap<String, i64> = BTreeMap::new();
+    let mut last_code = None;
+    let mut quarantined = Vec::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        match prepare_batch_item(item, received, &settings, source_ip.clone()) {
+            Ok((checkin, key)) => {
+                let at = db::parse_instant(&checkin.timestamp_utc).unwrap_or(received);
+                ready.push((index, at, checkin, key));
+                results.push(None);
+            }
+            Err(rejection) => {
+                if let Some(body) = &rejection.body {
+                    quarantined.push(quarantine::entry(
+                        received,
+                        client,
+                        rejection.laptop_serial.clone(),
+                        body.clone(),
+                        &rejection.error,
+                        &rejection.errors,
+                    ));
+                }
+                *rejected.entry(rejection.code.clone()).or_default() += 1;
+                last_code = Some(rejection.code);
+                results.push(Some(BatchItemResult {
+                    index,
+                    laptop_serial: rejection.laptop_serial,
+                    status: "rejected".to_string(),
+                    replayed: false,
+                    current: None,
+                    error: Some(rejection.error),
+                    errors: if settings.validation.detailed_errors {
+                        rejection.errors
+                    } else {
+                        Vec::new()
+                    },
+                }));
+            }
+        }
+    }
+    if let Some(last_code) = last_code {
+        // The last item's code goes last, so it is kept as the latest error
+        for (code, count) in rejected.iter().filter(|(code, _)| **code != last_code) {
+            count_rejected(&state, client, *count, code);
+        }
+        count_rejected(&state, client, rejected[&last_code], &last_code);
+        quarantine::store(&state, &settings, &quarantined);
+    }
+
+    // Oldest first, keeping request order for equal timestamps
+    ready.sort_by_key(|(_, at, _, _)| *at);
+
+    let mut conn = rusqlite::Connection::open(&state.db_path).map_err(|e| {
+        tracing::error!(error = ?e, "Failed to open database connection");
+        CheckInError::DatabaseError(e)
+    })?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON;",
+    )?;
+
+    let _db_span = tracing::info_span!("record_checkin_batch", items = ready.len()).entered();
+
+    let key_cutoff = idempotency::cutoff(received, settings.idempotency_key_retention_hours);
+    let mut stored = 0;
+
+    let tx = conn.transaction()?;
+    db::prune_idempotency_keys(&tx, &key_cutoff)?;
+    for (index, _, checkin, key) in &mut ready {
+        let outcome = store_prepared(&tx, checkin, key.as_deref(), &key_cutoff)?;
+        if matches!(outcome, Stored::Inserted { .. }) {
+            stored += 1;
+        }
+        results[*index] = Some(BatchItemResult {
+            index: *index,
+            laptop_serial: Some(checkin.laptop_serial.clone()),
+            status: match outcome {
+                Stored::Conflict => "rejected",
+                _ => "accepted",
+            }
+            .to_string(),
+            replayed: matches!(outcome, Stored::Replayed),
+            current: match outcome {
+                Stored::Inserted { current } => Some(current),
+                _ => None,
+            },
+            error: matches!(outcome, Stored::Conflict)
+                .then(|| "checkin_id was already used for a different device".to_string()),
+            errors: Vec::new(),
+        });
+    }
+    tx.commit().map_err(|e| {
+        tracing::error!(error = ?e, "Failed to commit transaction");
+        CheckInError::DatabaseError(e)
+    })?;
+
+    let results: Vec<BatchItemResult> = results.into_iter().flatten().collect();
+    let accepted = results.iter().filter(|r| r.status == "accepted").count();
+    let rejected = results.len() - accepted;
+    tracing::info!(accepted, rejected, stored, "Batch check-in stored");
+
+    Ok(Json(BatchResponse {
+        accepted,
+        rejected,
+        results,
+    }))
+}
+
+/// What [`store_prepared`] did with a check-in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stored {
+    /// Added to the history; `current` when it also became the device's state
+    Inserted { current: bool },
+    /// Its key was already stored for the same device, so nothing was written
+    Replayed,
+    /// Its key was already stored for another device
+    Conflict,
+}
+
+/// Store a validated check-in inside the caller's transaction, claiming its
+/// idempotency key first. Expired keys must already be pruned up to `key_cutoff`.
+/// The check-in moves to another device first if its serial is remapped. This is the
+/// one storage path for single, batch and re-processed check-ins.
+pub(crate) fn store_prepared(
+    tx: &rusqlite::Connection,
+    checkin: &mut db::NewCheckin,
+    key: Option<&str>,
+    key_cutoff: &str,
+) -> Result<Stored, CheckInError> {
+    identity::remap(tx, checkin)?;
+    if let Some(key) = key {
+        if !db::claim_idempotency_key(tx, key, &checkin.laptop_serial, &checkin.received_at_utc)? {
+            // Sent before, possibly earlier in the same batch
+            let conflict = db::find_idempotency_key(tx, key, key_cutoff)?
+                .is_some_and(|k| k.laptop_serial != checkin.laptop_serial);
+            return Ok(if conflict {
+                Stored::Conflict
+            } else {
+                Stored::Replayed
+            });
+        }
+    }
+
+    let checkin_id = db::insert_checkin(tx, checkin).map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
-            hostname = %payload.hostname,
+            laptop_serial = %checkin.laptop_serial,
+            hostname = %checkin.hostname,
             error = ?e,
-            "Failed to upsert laptop record"
+            "Failed to insert checkin record"
         );
         CheckInError::DatabaseError(e)
     })?;
+    lifecycle::flag_checkin(tx, checkin)?;
+    identity::detect_collision(tx, checkin)?;
 
-    tx.commit().map_err(|e| {
+    // A late or repeated delivery is kept in the history but must not roll back
+    // the current state to an older snapshot
+    let current = db::upsert_laptop_if_newer(tx, checkin).map_err(|e| {
         tracing::error!(
-            laptop_serial = %payload.laptop_serial,
+            laptop_serial = %checkin.laptop_serial,
+            hostname = %checkin.hostname,
             error = ?e,
-            "Failed to commit transaction"
+            "Failed to upsert laptop record"
         );
         CheckInError::DatabaseError(e)
     })?;
+    if let Some(key) = key {
+        db::set_idempotency_checkin(tx, key, checkin_id)?;
+    }
+    Ok(Stored::Inserted { current })
+}
+
+/// Split a batch body into its items. A JSON array must parse as a whole; NDJSON
+/// lines that aren't valid JSON are kept as errors so they fail individually.
+fn split_batch(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Vec<Result<serde_json::Value, String>>, CheckInError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let ndjson = [
+        "application/x-ndjson",
+        "application/ndjson",
+        "application/jsonl",
+    ]
+    .iter()
+    .any(|t| content_type.starts_with(t));
+
+    if ndjson {
+        let text = std::str::from_utf8(body)
+            .map_err(|_| CheckInError::InvalidBatch("Request body must be UTF-8"))?;
+        Ok(text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("invalid JSON: {e}")))
+            .collect())
+    } else {
+        let items: Vec<serde_json::Value> = serde_json::from_slice(body).map_err(|_| {
+            CheckInError::InvalidBatch("Request body must be a JSON array or NDJSON")
+        })?;
+        Ok(items.into_iter().map(Ok).collect())
+    }
+}
+
+/// Parse, validate and clock-check one batch item. On failure returns the serial
+/// (when it could be read) and a message for the item's result.
+pub(crate) fn prepare_batch_item(
+    item: Result<serde_json::Value, String>,
+    received: chrono::DateTime<chrono::Utc>,
+    settings: &Settings,
+    source_ip: Option<String>,
+) -> Result<(db::NewCheckin, Option<String>), ItemRejection> {
+    let value = item.map_err(|error| ItemRejection::new(None, error, "invalid_json"))?;
+    let serial = value
+        .get("laptop_serial")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let raw = value.to_string();
 
-    Ok(StatusCode::OK)
+    let payload = schema::parse_checkin(value).map_err(|e| {
+        ItemRejection::new(serial.clone(), e.to_string(), e.code()).quarantine(&raw)
+    })?;
+    payload.validate_with(&settings.validation).map_err(|e| {
+        tracing::warn!(validation_errors = ?e, "Batch item validation failed");
+        let fields = validation::field_errors(&e);
+        let mut rejection = ItemRejection::new(
+            serial.clone(),
+            "Invalid input data".to_string(),
+            fields.first().map_or("invalid_input", |f| f.code.as_str()),
+        );
+        rejection.errors = fields;
+        rejection.quarantine(&raw)
+    })?;
+    let assessed = clock_skew::assess(&payload.timestamp_utc, received, &settings.clock_skew)
+        .map_err(|_| {
+            ItemRejection::new(
+                serial.clone(),
+                "timestamp_utc is outside the allowed clock skew".to_string(),
+                "clock_skew",
+            )
+        })?;
+
+    let key = payload.checkin_id.clone();
+    let laptop_serial = identity::device_serial(&payload, &settings.identity);
+    new_checkin(payload, assessed, source_ip, laptop_serial)
+        .map(|checkin| (checkin, key))
+        .map_err(|e| ItemRejection::new(serial, e.to_string(), "invalid_payload"))
+}
+
+/// Why a batch item was not stored
+pub(crate) struct ItemRejection {
+    /// The item's serial, when it could be read
+    pub laptop_serial: Option<String>,
+    /// Message for the item's result
+    pub error: String,
+    /// Code counted against the source, as for [`CheckInError::rejection_code`]
+    pub code: String,
+    /// Failed validation rules
+    pub errors: Vec<FieldError>,
+    /// The item as JSON, when it failed validation and belongs in the quarantine
+    pub body: Option<String>,
+}
+
+impl ItemRejection {
+    fn new(laptop_serial: Option<String>, error: String, code: &str) -> Self {
+        Self {
+            laptop_serial,
+            error,
+            code: code.to_string(),
+            errors: Vec::new(),
+            body: None,
+        }
+    }
+
+    fn quarantine(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+}
+
+/// GET /api/v1/schema - JSON Schema of the check-in payload accepted by this server
+pub async fn checkin_schema(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(schema::json_schema(&state.settings().validation))
+}
+
+/// Row values for a validated check-in, stored under the device `laptop_serial`
+pub(crate) fn new_checkin(
+    payload: CheckIn,
+    assessed: clock_skew::Assessed,
+    source_ip: Option<String>,
+    laptop_serial: String,
+) -> Result<db::NewCheckin, serde_json::Error> {
+    let (short_name, domain) = hostname::split(&payload.hostname);
+    Ok(db::NewCheckin {
+        drives_json: serde_json::to_string(&payload.drives)?,
+        short_name,
+        domain,
+        reported_serial: (laptop_serial != payload.laptop_serial).then_some(payload.laptop_serial),
+        laptop_serial,
+        hostname: payload.hostname,
+        ip_address: payload.ip_address,
+        logged_in_user: payload.logged_in_user,
+        timestamp_utc: assessed.timestamp_utc,
+        received_at_utc: assessed.received_at_utc,
+        clock_skew_secs: assessed.skew_secs,
+        source_ip,
+    })
 }
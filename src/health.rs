@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{config::ReadinessConfig, db, AppState};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Body returned by `/healthz`
+#[derive(Debug, Serialize)]
+pub struct LivenessResponse {
+    pub status: &'static str,
+}
+
+/// Body returned by `/readyz`
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: &'static str,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+/// Outcome of a single readiness probe
+#[derive(Debug, Serialize)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl ReadinessCheck {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// GET /healthz - The process is up and serving requests
+pub async fn healthz() -> Json<LivenessResponse> {
+    Json(LivenessResponse { status: "ok" })
+}
+
+/// GET /readyz - The database is writable and within operating thresholds
+pub async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let checks = run_checks(&state.db_path, &state.settings().readiness);
+    let ready = checks.iter().all(|c| c.ok);
+
+    if !ready {
+        tracing::warn!(
+            failed = ?checks.iter().filter(|c| !c.ok).map(|c| c.name).collect::<Vec<_>>(),
+            "Readiness check failed"
+        );
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" },
+        checks,
+    };
+
+    (status, Json(body))
+}
+
+/// Run every readiness probe against the database at `db_path`
+pub fn run_checks(db_path: &str, thresholds: &ReadinessConfig) -> Vec<ReadinessCheck> {
+    let mut checks = Vec::with_capacity(4);
+
+    match rusqlite::Connection::open(db_path) {
+        Ok(conn) => {
+            checks.push(check_writable(&conn));
+            checks.push(check_schema_version(&conn));
+        }
+        Err(e) => {
+            checks.push(ReadinessCheck::fail(
+                "database",
+                format!("open failed: {e}"),
+            ));
+            checks.push(ReadinessCheck::fail(
+                "schema_version",
+                "database unavailable",
+            ));
+        }
+    }
+
+    checks.push(check_disk_space(db_path, thresholds.min_free_disk_mb));
+    checks.push(check_wal_size(db_path, thresholds.max_wal_mb));
+
+    checks
+}
+
+/// Take (and release) the write lock to prove check-ins can be committed
+fn check_writable(conn: &rusqlite::Connection) -> ReadinessCheck {
+    match conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;") {
+        Ok(()) => ReadinessCheck::pass("database", "writable"),
+        Err(e) => ReadinessCheck::fail("database", format!("not writable: {e}")),
+    }
+}
+
+fn check_schema_version(conn: &rusqlite::Connection) -> ReadinessCheck {
+    match db::schema_version(conn) {
+        Ok(v) if v == db::SCHEMA_VERSION => {
+            ReadinessCheck::pass("schema_version", format!("version {v}"))
+        }
+        Ok(v) => ReadinessCheck::fail(
+            "schema_version",
+            format!("found version {v}, expected {}", db::SCHEMA_VERSION),
+        ),
+        Err(e) => ReadinessCheck::fail("schema_version", format!("{e:#}")),
+    }
+}
+
+fn check_disk_space(db_path: &str, min_free_mb: u64) -> ReadinessCheck {
+    let dir = Path::new(db_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    match fs4::available_space(dir) {
+        Ok(free) if free >= min_free_mb.saturating_mul(MIB) => {
+            ReadinessCheck::pass("disk_space", format!("{} MiB free", free / MIB))
+        }
+        Ok(free) => ReadinessCheck::fail(
+            "disk_space",
+            format!("{} MiB free, need {min_free_mb} MiB", free / MIB),
+        ),
+        Err(e) => ReadinessCheck::fail("disk_space", format!("query failed: {e}")),
+    }
+}
+
+fn check_wal_size(db_path: &str, max_wal_mb: u64) -> ReadinessCheck {
+    // A missing WAL file just means nothing has been written since the last checkpoint
+    let size = std::fs::metadata(wal_path(db_path))
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    if size <= max_wal_mb.saturating_mul(MIB) {
+        ReadinessCheck::pass("wal_size", format!("{} MiB", size / MIB))
+    } else {
+        ReadinessCheck::fail(
+            "wal_size",
+            format!("{} MiB exceeds limit of {max_wal_mb} MiB", size / MIB),
+        )
+    }
+}
+
+/// SQLite keeps the write-ahead log next to the database as `<db>-wal`
+fn wal_path(db_path: &str) -> PathBuf {
+    PathBuf::from(format!("{db_path}-wal"))
+}
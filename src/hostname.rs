@@ -0,0 +1,102 @@
+use crate::config::HostnamePolicy;
+
+/// Longest DNS label
+pub const MAX_LABEL_LEN: usize = 63;
+
+/// Longest fully qualified name, as for DNS
+pub const MAX_FQDN_LEN: usize = 253;
+
+/// Whether `hostname` has the form `policy` accepts
+pub fn is_valid(hostname: &str, policy: HostnamePolicy) -> bool {
+    match policy {
+        HostnamePolicy::Netbios => is_computer_name(hostname),
+        HostnamePolicy::Label => is_dns_label(hostname),
+        HostnamePolicy::Fqdn => {
+            let (short_name, domain) = match hostname.split_once('.') {
+                Some((short_name, domain)) => (short_name, Some(domain)),
+                None => (hostname, None),
+            };
+            hostname.len() <= MAX_FQDN_LEN
+                && is_computer_name(short_name)
+                && domain.is_none_or(|d| d.split('.').all(is_dns_label))
+        }
+    }
+}
+
+/// Split a reported name into its short name and domain, e.g. `host.example.com`
+/// into `host` and `example.com`. The domain is lowercased for grouping.
+pub fn split(hostname: &str) -> (String, Option<String>) {
+    match hostname.split_once('.') {
+        Some((short_name, domain)) => (short_name.to_string(), Some(domain.to_ascii_lowercase())),
+        None => (hostname.to_string(), None),
+    }
+}
+
+/// Windows computer name: must start and end with a letter or digit, with hyphens and
+/// underscores allowed in between (real-world Windows names may include underscores)
+fn is_computer_name(name: &str) -> bool {
+    is_label(name, |c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// RFC 1123 label: letters, digits and hyphens, starting and ending with a letter or digit
+fn is_dns_label(name: &str) -> bool {
+    is_label(name, |c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn is_label(name: &str, allowed: impl Fn(char) -> bool) -> bool {
+    (1..=MAX_LABEL_LEN).contains(&name.len())
+        && name.chars().all(allowed)
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        let cases = [
+            // name, netbios, label, fqdn
+            ("LAPTOP-001", true, true, true),
+            ("LAPTOP_TEST_01", true, false, true),
+            ("123host", true, true, true),
+            ("host.example.com", false, false, true),
+            ("LAPTOP_01.corp.example.com", false, false, true),
+            ("host.under_score.com", false, false, false),
+            ("host.example.com.", false, false, false),
+            ("host..example.com", false, false, false),
+            (".example.com", false, false, false),
+            ("host.-bad.com", false, false, false),
+            ("-host", false, false, false),
+            ("host-", false, false, false),
+            ("", false, false, false),
+        ];
+        for (name, netbios, label, fqdn) in cases {
+            assert_eq!(is_valid(name, HostnamePolicy::Netbios), netbios, "{name}");
+            assert_eq!(is_valid(name, HostnamePolicy::Label), label, "{name}");
+            assert_eq!(is_valid(name, HostnamePolicy::Fqdn), fqdn, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        let label = "a".repeat(MAX_LABEL_LEN);
+        assert!(is_valid(&label, HostnamePolicy::Label));
+        assert!(!is_valid(&format!("{label}a"), HostnamePolicy::Netbios));
+
+        // Four 63-character labels and three dots: 255
+        let long = [label.as_str(); 4].join(".");
+        assert!(!is_valid(&long, HostnamePolicy::Fqdn));
+        assert!(is_valid(&long[..MAX_FQDN_LEN], HostnamePolicy::Fqdn));
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("LAPTOP-001"), ("LAPTOP-001".to_string(), None));
+        assert_eq!(
+            split("Mac-Mini.Corp.Example.com"),
+            ("Mac-Mini".to_string(), Some("corp.example.com".to_string()))
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use axum::http::{HeaderMap, HeaderName};
+use chrono::{DateTime, Utc};
+
+use crate::{clock_skew, errors::CheckInError};
+
+/// Request header carrying a client-chosen key for the submission
+pub static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Response header set when a submission was recognised as a replay and not stored again
+pub static IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest key accepted, in bytes
+pub const MAX_KEY_LEN: usize = 128;
+
+/// Keys are 1 to [`MAX_KEY_LEN`] visible ASCII characters, e.g. a UUID
+pub fn is_valid_key(key: &str) -> bool {
+    (1..=MAX_KEY_LEN).contains(&key.len()) && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// The `Idempotency-Key` header, if the request has one
+pub fn from_headers(headers: &HeaderMap) -> Result<Option<String>, CheckInError> {
+    let Some(value) = headers.get(&IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if is_valid_key(key) => Ok(Some(key.to_string())),
+        _ => Err(CheckInError::InvalidIdempotencyKey),
+    }
+}
+
+/// Creation time before which stored keys are forgotten
+pub fn cutoff(now: DateTime<Utc>, retention_hours: u64) -> String {
+    let cutoff = i64::try_from(retention_hours)
+        .ok()
+        .and_then(chrono::Duration::try_hours)
+        .and_then(|retention| now.checked_sub_signed(retention))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    clock_skew::format_utc(cutoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_is_valid_key() {
+        assert!(is_valid_key("6f1c2a9e-1b7d-4a53-9a4e-0c9f5d1b2e77"));
+        assert!(is_valid_key(&"k".repeat(MAX_KEY_LEN)));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key(&"k".repeat(MAX_KEY_LEN + 1)));
+        assert!(!is_valid_key("has space"));
+        assert!(!is_valid_key("ключ"));
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(from_headers(&headers).unwrap(), None);
+
+        headers.insert(&IDEMPOTENCY_KEY, HeaderValue::from_static("abc-123"));
+        assert_eq!(from_headers(&headers).unwrap(), Some("abc-123".to_string()));
+
+        headers.insert(&IDEMPOTENCY_KEY, HeaderValue::from_static("a b"));
+        assert!(from_headers(&headers).is_err());
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let now: DateTime<Utc> = "2024-01-15T10:30:00Z".parse().unwrap();
+        assert_eq!(cutoff(now, 72), "2024-01-12T10:30:00Z");
+        assert_eq!(cutoff(now, 0), "2024-01-15T10:30:00Z");
+        assert!(cutoff(now, u64::MAX).starts_with('-'));
+    }
+}
@@ -0,0 +1,456 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Redirect,
+    Form,
+};
+use serde::Deserialize;
+
+use crate::{
+    clock_skew,
+    config::IdentityConfig,
+    db,
+    lifecycle::{required, MAX_CHANGED_BY_CHARS, MAX_REASON_CHARS},
+    metadata::{device_path, encode},
+    models::{CheckIn, Drive, HostnameCount, SerialCollisionRow, SerialRemapRow},
+    AppState,
+};
+
+/// Prefix of serials derived from a device's hostname and drive serials
+pub const FALLBACK_PREFIX: &str = "FALLBACK-";
+
+/// Longest serial an admin can enter, as for check-ins
+const MAX_SERIAL_CHARS: usize = 128;
+
+/// Whether `serial` is one firmware reports when none was set
+pub fn is_placeholder(serial: &str, config: &IdentityConfig) -> bool {
+    let serial = serial.trim();
+    config
+        .placeholder_serials
+        .iter()
+        .any(|p| p.trim().eq_ignore_ascii_case(serial))
+}
+
+/// Serial to identify a machine by when it has none of its own: a hash of its
+/// hostname and drive serials, so the same machine keeps it across check-ins
+pub fn fallback_serial<'a>(hostname: &str, drive_serials: impl Iterator<Item = &'a str>) -> String {
+    let mut drives: Vec<String> = drive_serials
+        .map(|s| s.trim().to_uppercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    drives.sort();
+    drives.dedup();
+    let key = std::iter::once(hostname.trim().to_lowercase())
+        .chain(drives)
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{FALLBACK_PREFIX}{:016X}", fnv1a(key.as_bytes()))
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher is the same in every
+/// build
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Serial a check-in is stored under before remaps: the one it reports, or the
+/// fallback for a placeholder
+pub fn device_serial(payload: &CheckIn, config: &IdentityConfig) -> String {
+    if is_placeholder(&payload.laptop_serial, config) {
+        let drives = payload
+            .drives
+            .iter()
+            .filter_map(|d| d.serial_number.as_deref());
+        fallback_serial(&payload.hostname, drives)
+    } else {
+        payload.laptop_serial.clone()
+    }
+}
+
+/// [`device_serial`], after any remap an admin made by merging or splitting devices
+pub(crate) fn resolve(
+    conn: &rusqlite::Connection,
+    payload: &CheckIn,
+    config: &IdentityConfig,
+) -> rusqlite::Result<String> {
+    let serial = device_serial(payload, config);
+    Ok(db::find_serial_remap(conn, &serial, &payload.hostname)?.unwrap_or(serial))
+}
+
+/// Apply any remap to a check-in about to be stored
+pub(crate) fn remap(
+    conn: &rusqlite::Connection,
+    checkin: &mut db::NewCheckin,
+) -> rusqlite::Result<()> {
+    if let Some(serial) = db::find_serial_remap(conn, &checkin.laptop_serial, &checkin.hostname)? {
+        let reported = std::mem::replace(&mut checkin.laptop_serial, serial);
+        checkin.reported_serial.get_or_insert(reported);
+    }
+    Ok(())
+}
+
+/// Note a collision if a check-in about to be stored seems to come from another
+/// machine than the device's current state: the hostname differs and the two
+/// share no drive serial
+pub(crate) fn detect_collision(
+    conn: &rusqlite::Connection,
+    checkin: &db::NewCheckin,
+) -> rusqlite::Result<()> {
+    let Some((hostname, drives_json)) = db::get_laptop_identity(conn, &checkin.laptop_serial)?
+    else {
+        return Ok(());
+    };
+    if hostname.eq_ignore_ascii_case(&checkin.hostname) {
+        return Ok(());
+    }
+    let (current, incoming) = (
+        drive_serials(&drives_json),
+        drive_serials(&checkin.drives_json),
+    );
+    if current.is_empty() || incoming.is_empty() || current.iter().any(|s| incoming.contains(s)) {
+        return Ok(());
+    }
+    tracing::warn!(
+        laptop_serial = %checkin.laptop_serial,
+        hostname = %checkin.hostname,
+        current_hostname = %hostname,
+        "Check-in shares a serial with another machine"
+    );
+    db::record_serial_collision(
+        conn,
+        &checkin.laptop_serial,
+        &checkin.hostname,
+        &checkin.received_at_utc,
+    )
+}
+
+fn drive_serials(drives_json: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<Drive>>(drives_json)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|d| d.serial_number)
+        .map(|s| s.trim().to_uppercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// A serial entered by an admin: trimmed printable ASCII, and not a placeholder
+fn parse_serial(name: &str, serial: &str, config: &IdentityConfig) -> Result<String, String> {
+    let serial = required(name, serial, MAX_SERIAL_CHARS)?;
+    if !serial.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err(format!("{name} must be printable ASCII"));
+    }
+    if is_placeholder(&serial, config) {
+        return Err(format!("{name} is a placeholder serial"));
+    }
+    Ok(serial)
+}
+
+#[derive(Template)]
+#[template(path = "identity.html")]
+pub struct IdentityTemplate {
+    pub collisions: Vec<SerialCollisionRow>,
+    pub remaps: Vec<SerialRemapRow>,
+    pub placeholders: Vec<String>,
+    /// Device to split, from `?serial=`
+    pub split_serial: Option<String>,
+    /// Hostnames in that device's history
+    pub hostnames: Vec<HostnameCount>,
+    /// For the merge, split, dismiss and remove forms
+    pub csrf_token: String,
+}
+
+impl IdentityTemplate {
+    /// Path of a device's page
+    pub fn device_path(&self, serial: &str) -> String {
+        device_path(serial)
+    }
+
+    /// Link to split a device on this page
+    pub fn split_link(&self, serial: &str) -> String {
+        split_path(serial)
+    }
+}
+
+/// Path of the admin page set up to split or merge a device
+pub fn split_path(serial: &str) -> String {
+    format!("/admin/identity?serial={}", encode(serial))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdentityQuery {
+    serial: Option<String>,
+}
+
+/// GET /admin/identity - Serial collisions, remaps, and forms to merge devices or
+/// split one by hostname
+#[tracing::instrument(skip(state))]
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<IdentityQuery>,
+) -> Result<IdentityTemplate, (StatusCode, String)> {
+    let conn = open(&state)?;
+    let collisions = db::get_serial_collisions(&conn).map_err(internal("query collisions"))?;
+    let remaps = db::get_serial_remaps(&conn).map_err(internal("query remaps"))?;
+    let split_serial = query.serial.filter(|s| !s.is_empty());
+    let hostnames = match &split_serial {
+        Some(serial) => {
+            let hostnames =
+                db::get_device_hostnames(&conn, serial).map_err(internal("query hostnames"))?;
+            if hostnames.is_empty() {
+                return Err((StatusCode::NOT_FOUND, format!("Device not found: {serial}")));
+            }
+            hostnames
+        }
+        None => Vec::new(),
+    };
+
+    Ok(IdentityTemplate {
+        collisions,
+        remaps,
+        placeholders: state.settings().identity.placeholder_serials.clone(),
+        split_serial,
+        hostnames,
+        csrf_token: state.csrf_token.as_str().to_string(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeForm {
+    source: String,
+    target: String,
+    changed_by: String,
+    reason: String,
+}
+
+/// POST /admin/identity/merge - Merge one device's history into another's, and
+/// store later check-ins reporting its serial under the other
+#[tracing::instrument(skip_all)]
+pub async fn merge(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<MergeForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let bad_request = |e| (StatusCode::BAD_REQUEST, e);
+    let source = form.source.trim().to_string();
+    let target = form.target.trim().to_string();
+    let changed_by =
+        required("Changed by", &form.changed_by, MAX_CHANGED_BY_CHARS).map_err(bad_request)?;
+    let reason = required("Reason", &form.reason, MAX_REASON_CHARS).map_err(bad_request)?;
+    if source == target {
+        return Err(bad_request(
+            "A device can't be merged into itself".to_string(),
+        ));
+    }
+
+    let mut conn = open(&state)?;
+    for serial in [&source, &target] {
+        db::get_laptop_by_serial(&conn, serial)
+            .map_err(internal("query laptop"))?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Device not found: {serial}")))?;
+    }
+    let remap = db::NewSerialRemap {
+        reported_serial: source,
+        hostname: String::new(),
+        laptop_serial: target,
+        created_at_utc: clock_skew::format_utc(chrono::Utc::now()),
+        created_by: changed_by,
+        reason,
+    };
+    let tx = conn.transaction().map_err(internal("begin transaction"))?;
+    let moved = db::merge_devices(&tx, &remap).map_err(internal("merge devices"))?;
+    tx.commit().map_err(internal("commit merge"))?;
+    tracing::info!(
+        source = %remap.reported_serial,
+        target = %remap.laptop_serial,
+        checkins = moved,
+        changed_by = %remap.created_by,
+        "Devices merged"
+    );
+    Ok(Redirect::to(&device_path(&remap.laptop_serial)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SplitForm {
+    serial: String,
+    hostname: String,
+    /// Device to move the check-ins to; empty for the fallback serial
+    #[serde(default)]
+    new_serial: String,
+    changed_by: String,
+    reason: String,
+}
+
+/// POST /admin/identity/split - Move a device's check-ins from one hostname to
+/// another device, and store later ones from that host there too
+#[tracing::instrument(skip_all)]
+pub async fn split(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<SplitForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let bad_request = |e| (StatusCode::BAD_REQUEST, e);
+    let settings = state.settings();
+    let serial = form.serial.trim().to_string();
+    let hostname = required("Hostname", &form.hostname, 253).map_err(bad_request)?;
+    let changed_by =
+        required("Changed by", &form.changed_by, MAX_CHANGED_BY_CHARS).map_err(bad_request)?;
+    let reason = required("Reason", &form.reason, MAX_REASON_CHARS).map_err(bad_request)?;
+
+    let mut conn = open(&state)?;
+    let hostnames =
+        db::get_device_hostnames(&conn, &serial).map_err(internal("query hostnames"))?;
+    if hostnames.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("Device not found: {serial}")));
+    }
+    if !hostnames
+        .iter()
+        .any(|h| h.hostname.eq_ignore_ascii_case(&hostname))
+    {
+        return Err(bad_request(format!(
+            "{serial} has no check-ins from {hostname}"
+        )));
+    }
+    if hostnames.len() == 1 {
+        return Err(bad_request(format!(
+            "All of {serial}'s check-ins are from {hostname}; merge the device instead"
+        )));
+    }
+
+    let new_serial = if form.new_serial.trim().is_empty() {
+        let drives = db::latest_drives_for_hostname(&conn, &serial, &hostname)
+            .map_err(internal("query drives"))?
+            .unwrap_or_default();
+        let drives = drive_serials(&drives);
+        fallback_serial(&hostname, drives.iter().map(String::as_str))
+    } else {
+        parse_serial("New serial", &form.new_serial, &settings.identity).map_err(bad_request)?
+    };
+    if new_serial == serial {
+        return Err(bad_request(
+            "The new serial must differ from the device's".to_string(),
+        ));
+    }
+
+    let remap = db::NewSerialRemap {
+        reported_serial: serial,
+        hostname,
+        laptop_serial: new_serial,
+        created_at_utc: clock_skew::format_utc(chrono::Utc::now()),
+        created_by: changed_by,
+        reason,
+    };
+    let tx = conn.transaction().map_err(internal("begin transaction"))?;
+    let moved = db::split_device(&tx, &remap).map_err(internal("split device"))?;
+    tx.commit().map_err(internal("commit split"))?;
+    tracing::info!(
+        source = %remap.reported_serial,
+        hostname = %remap.hostname,
+        target = %remap.laptop_serial,
+        checkins = moved,
+        changed_by = %remap.created_by,
+        "Device split"
+    );
+    Ok(Redirect::to(&device_path(&remap.laptop_serial)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollisionForm {
+    serial: String,
+}
+
+/// POST /admin/identity/collisions/dismiss - Forget a collision judged harmless
+#[tracing::instrument(skip_all)]
+pub async fn dismiss(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<CollisionForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let conn = open(&state)?;
+    if db::delete_serial_collision(&conn, &form.serial).map_err(internal("dismiss collision"))? {
+        tracing::info!(laptop_serial = %form.serial, "Serial collision dismissed");
+    }
+    Ok(Redirect::to("/admin/identity"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemapForm {
+    reported_serial: String,
+    #[serde(default)]
+    hostname: String,
+}
+
+/// POST /admin/identity/remaps/delete - Store later check-ins under the serial
+/// they report again. Check-ins already moved stay where they are.
+#[tracing::instrument(skip_all)]
+pub async fn remove_remap(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<RemapForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let conn = open(&state)?;
+    if db::delete_serial_remap(&conn, &form.reported_serial, &form.hostname)
+        .map_err(internal("remove remap"))?
+    {
+        tracing::info!(
+            reported_serial = %form.reported_serial,
+            hostname = %form.hostname,
+            "Serial remap removed"
+        );
+    }
+    Ok(Redirect::to("/admin/identity"))
+}
+
+fn open(state: &AppState) -> Result<rusqlite::Connection, (StatusCode, String)> {
+    rusqlite::Connection::open(&state.db_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))
+}
+
+fn internal<E: std::fmt::Display>(what: &'static str) -> impl Fn(E) -> (StatusCode, String) {
+    move |e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{what}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_placeholder() {
+        let config = IdentityConfig::default();
+        assert!(is_placeholder("To be filled by O.E.M.", &config));
+        assert!(is_placeholder("  default STRING ", &config));
+        assert!(is_placeholder("0", &config));
+        assert!(!is_placeholder("5CG1234XYZ", &config));
+        assert!(!is_placeholder("00", &config));
+    }
+
+    #[test]
+    fn test_fallback_serial_is_stable() {
+        let a = fallback_serial("LAPTOP-001", ["WD-2", "wd-1 "].into_iter());
+        assert_eq!(
+            a,
+            fallback_serial("laptop-001", ["WD-1", "WD-2", ""].into_iter())
+        );
+        assert!(a.starts_with(FALLBACK_PREFIX));
+        assert_eq!(a.len(), FALLBACK_PREFIX.len() + 16);
+        assert_ne!(
+            a,
+            fallback_serial("LAPTOP-002", ["WD-1", "WD-2"].into_iter())
+        );
+        assert_ne!(a, fallback_serial("LAPTOP-001", ["WD-1"].into_iter()));
+        // Fixed across builds, since stored serials depend on it
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_parse_serial() {
+        let config = IdentityConfig::default();
+        assert_eq!(parse_serial("Serial", " SN 1 ", &config).unwrap(), "SN 1");
+        assert!(parse_serial("Serial", "", &config).is_err());
+        assert!(parse_serial("Serial", "Default string", &config).is_err());
+        assert!(parse_serial("Serial", "Séri", &config).is_err());
+        assert!(parse_serial("Serial", &"x".repeat(129), &config).is_err());
+    }
+}
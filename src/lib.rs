@@ -1,12 +1,123 @@
 // Library exports for integration tests
 
+pub mod client_ip;
+pub mod clock_skew;
 pub mod config;
+pub mod csrf;
 pub mod db;
 pub mod errors;
+pub mod export;
 pub mod handlers;
+pub mod health;
+pub mod hostname;
+pub mod idempotency;
+pub mod identity;
+pub mod lifecycle;
+pub mod listeners;
+pub mod logging;
+pub mod metadata;
 pub mod models;
+pub mod network;
+pub mod quarantine;
+pub mod rate_limit;
+pub mod reload;
+pub mod request_id;
+pub mod routes;
+pub mod schema;
+pub mod search;
+pub mod shutdown;
+pub mod snapshot;
+pub mod telemetry;
+pub mod users;
+pub mod validation;
+
+use std::sync::{Arc, RwLock};
+
+/// Settings that can be changed while the server is running (see [`reload`]).
+/// Everything else in [`config::Config`] is read once at startup.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub debug_mode: bool,
+    pub readiness: config::ReadinessConfig,
+    pub redact_user_names: bool,
+    pub limits: config::LimitsConfig,
+    pub clock_skew: config::ClockSkewConfig,
+    pub validation: config::ValidationConfig,
+    pub users: config::UsersConfig,
+    pub subnets: std::collections::BTreeMap<String, Vec<config::IpNetwork>>,
+    pub custom_fields: std::collections::BTreeMap<String, config::CustomField>,
+    pub identity: config::IdentityConfig,
+    pub trusted_proxies: Vec<config::IpNetwork>,
+    pub idempotency_key_retention_hours: u64,
+}
+
+impl Settings {
+    /// Extract the reloadable settings from `cfg`. `force_debug` reflects the `--debug`
+    /// flag and `INVENTORY_DEBUG`, which keep debug mode on regardless of the file.
+    pub fn from_config(cfg: &config::Config, force_debug: bool) -> Self {
+        Self {
+            debug_mode: force_debug || cfg.debug,
+            readiness: cfg.readiness.clone(),
+            redact_user_names: cfg.logging.redact_user_names,
+            limits: cfg.limits.clone(),
+            clock_skew: cfg.clock_skew.clone(),
+            validation: cfg.validation.clone(),
+            users: cfg.users.clone(),
+            subnets: cfg.subnets.clone(),
+            custom_fields: cfg.custom_fields.clone(),
+            identity: cfg.identity.clone(),
+            trusted_proxies: cfg.trusted_proxies.clone(),
+            idempotency_key_retention_hours: cfg.idempotency_key_retention_hours,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::from_config(&config::Config::default(), false)
+    }
+}
 
 pub struct AppState {
     pub db_path: String,
-    pub debug_mode: bool,
+    pub limiter: rate_limit::RateLimiter,
+    /// Token the admin forms carry, checked by [`csrf::guard`]
+    pub csrf_token: csrf::Token,
+    settings: RwLock<Arc<Settings>>,
+}
+
+impl AppState {
+    /// State for the database at `db_path` with default settings
+    pub fn new(db_path: String) -> Self {
+        Self::with_settings(db_path, Settings::default())
+    }
+
+    pub fn with_settings(db_path: String, settings: Settings) -> Self {
+        Self {
+            db_path,
+            limiter: rate_limit::RateLimiter::default(),
+            csrf_token: csrf::Token::generate(),
+            settings: RwLock::new(Arc::new(settings)),
+        }
+    }
+
+    /// Snapshot of the current settings. Handlers take one snapshot per request so a
+    /// reload never changes behavior halfway through.
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Swap in new settings; requests already in progress keep their snapshot
+    pub fn replace_settings(&self, settings: Settings) {
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(settings);
+    }
+
+    /// Mutable access before the state is shared, e.g. to adjust settings in tests
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        let settings = self.settings.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::make_mut(settings)
+    }
 }
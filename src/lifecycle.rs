@@ -0,0 +1,285 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Redirect,
+    Form,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock_skew, db, errors::CheckInError, metadata::device_path, AppState};
+
+pub(crate) const MAX_CHANGED_BY_CHARS: usize = 100;
+pub(crate) const MAX_REASON_CHARS: usize = 500;
+
+/// Where a device is in its life. Devices without a recorded state are deployed.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleState {
+    InStock,
+    #[default]
+    Deployed,
+    InRepair,
+    Lost,
+    Retired,
+    Disposed,
+}
+
+impl LifecycleState {
+    pub const ALL: [LifecycleState; 6] = [
+        Self::InStock,
+        Self::Deployed,
+        Self::InRepair,
+        Self::Lost,
+        Self::Retired,
+        Self::Disposed,
+    ];
+
+    /// Name used in URLs, forms, the API and the database, as serialized
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::InStock => "in_stock",
+            Self::Deployed => "deployed",
+            Self::InRepair => "in_repair",
+            Self::Lost => "lost",
+            Self::Retired => "retired",
+            Self::Disposed => "disposed",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::InStock => "In stock",
+            Self::Deployed => "Deployed",
+            Self::InRepair => "In repair",
+            Self::Lost => "Lost",
+            Self::Retired => "Retired",
+            Self::Disposed => "Disposed",
+        }
+    }
+
+    /// Out of the fleet for good, so hidden from the index unless asked for
+    pub fn is_decommissioned(self) -> bool {
+        matches!(self, Self::Retired | Self::Disposed)
+    }
+
+    /// Not expected to check in; a check-in from such a device is flagged
+    pub fn flags_checkins(self) -> bool {
+        matches!(self, Self::Lost | Self::Retired | Self::Disposed)
+    }
+
+    /// State of a stored key; unknown keys read as the default
+    pub fn from_key(key: &str) -> Self {
+        key.parse().unwrap_or_default()
+    }
+}
+
+impl FromStr for LifecycleState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|state| state.key() == s)
+            .ok_or_else(|| format!("Unknown lifecycle state: {s}"))
+    }
+}
+
+impl std::fmt::Display for LifecycleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// Which devices the index lists, from `?state=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateFilter {
+    /// Everything but retired and disposed devices
+    #[default]
+    Active,
+    All,
+    Only(LifecycleState),
+    /// Devices that checked in after being marked lost, retired or disposed
+    Flagged,
+}
+
+impl StateFilter {
+    /// Parse `?state=`; empty or missing means [`StateFilter::Active`]
+    pub fn from_param(param: Option<&str>) -> Result<Self, String> {
+        match param.map(str::trim).filter(|p| !p.is_empty()) {
+            None | Some("active") => Ok(Self::Active),
+            Some("all") => Ok(Self::All),
+            Some("flagged") => Ok(Self::Flagged),
+            Some(key) => key.parse().map(Self::Only),
+        }
+    }
+
+    /// `?state=` value selecting this filter, `None` for the default
+    pub fn param(self) -> Option<&'static str> {
+        match self {
+            Self::Active => None,
+            Self::All => Some("all"),
+            Self::Only(state) => Some(state.key()),
+            Self::Flagged => Some("flagged"),
+        }
+    }
+
+    /// Whether a device in `state`, flagged or not, is listed
+    pub fn matches(self, state: LifecycleState, flagged: bool) -> bool {
+        match self {
+            Self::Active => !state.is_decommissioned(),
+            Self::All => true,
+            Self::Only(only) => state == only,
+            Self::Flagged => flagged,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LifecycleForm {
+    state: String,
+    changed_by: String,
+    reason: String,
+}
+
+/// A state change as it will be stored: known state, and who and why given
+fn parse_form(form: &LifecycleForm) -> Result<(LifecycleState, String, String), String> {
+    let state = form.state.parse::<LifecycleState>()?;
+    let changed_by = required("Changed by", &form.changed_by, MAX_CHANGED_BY_CHARS)?;
+    let reason = required("Reason", &form.reason, MAX_REASON_CHARS)?;
+    Ok((state, changed_by, reason))
+}
+
+/// A trimmed single-line value that must be given, as entered on admin forms
+pub(crate) fn required(name: &str, value: &str, max_chars: usize) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(format!("{name} is required"));
+    }
+    if value.chars().count() > max_chars {
+        return Err(format!("{name} is at most {max_chars} characters"));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(format!("{name} must be a single line"));
+    }
+    Ok(value.to_string())
+}
+
+/// POST /device/:serial/lifecycle - Record a state change with who made it and
+/// why. It settles any alert about check-ins in the previous state.
+#[tracing::instrument(skip(state, form))]
+pub async fn change_state(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Form(form): Form<LifecycleForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let (new_state, changed_by, reason) =
+        parse_form(&form).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut conn = rusqlite::Connection::open(&state.db_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))?;
+    db::get_laptop_by_serial(&conn, &serial)
+        .map_err(internal("query laptop"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Device not found: {serial}")))?;
+
+    let now = chrono::Utc::now();
+    let event = db::NewLifecycleEvent {
+        laptop_serial: serial.clone(),
+        state: new_state.key().to_string(),
+        changed_at_utc: clock_skew::format_utc(now),
+        changed_at_ms: now.timestamp_millis(),
+        changed_by,
+        reason,
+    };
+    let tx = conn.transaction().map_err(internal("begin transaction"))?;
+    db::insert_lifecycle_event(&tx, &event).map_err(internal("record state change"))?;
+    tx.commit().map_err(internal("commit state change"))?;
+    tracing::info!(state = new_state.key(), changed_by = %event.changed_by, "Device lifecycle state changed");
+    Ok(Redirect::to(&device_path(&serial)))
+}
+
+/// Flag a check-in just stored if its device was marked lost, retired or disposed
+/// before the check-in was taken
+pub(crate) fn flag_checkin(
+    tx: &rusqlite::Connection,
+    checkin: &db::NewCheckin,
+) -> Result<(), CheckInError> {
+    if let Some(state) = db::flag_lifecycle_checkin(tx, checkin)? {
+        tracing::warn!(
+            laptop_serial = %checkin.laptop_serial,
+            hostname = %checkin.hostname,
+            state,
+            "Check-in from a device marked out of service"
+        );
+    }
+    Ok(())
+}
+
+fn internal<E: std::fmt::Display>(what: &'static str) -> impl Fn(E) -> (StatusCode, String) {
+    move |e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{what}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(state: &str, changed_by: &str, reason: &str) -> LifecycleForm {
+        LifecycleForm {
+            state: state.to_string(),
+            changed_by: changed_by.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_state_keys_round_trip() {
+        for state in LifecycleState::ALL {
+            assert_eq!(state.key().parse::<LifecycleState>(), Ok(state));
+        }
+        assert!("Retired".parse::<LifecycleState>().is_err());
+        assert_eq!(LifecycleState::from_key("bogus"), LifecycleState::Deployed);
+        assert_eq!(
+            serde_json::to_string(&LifecycleState::InRepair).unwrap(),
+            "\"in_repair\""
+        );
+    }
+
+    #[test]
+    fn test_state_filter() {
+        assert_eq!(StateFilter::from_param(None), Ok(StateFilter::Active));
+        assert_eq!(StateFilter::from_param(Some("")), Ok(StateFilter::Active));
+        assert_eq!(
+            StateFilter::from_param(Some("lost")),
+            Ok(StateFilter::Only(LifecycleState::Lost))
+        );
+        assert!(StateFilter::from_param(Some("gone")).is_err());
+
+        let active = StateFilter::Active;
+        assert!(active.matches(LifecycleState::Lost, true));
+        assert!(!active.matches(LifecycleState::Retired, false));
+        assert!(!active.matches(LifecycleState::Disposed, true));
+        assert!(StateFilter::All.matches(LifecycleState::Disposed, false));
+        assert!(StateFilter::Flagged.matches(LifecycleState::Retired, true));
+        assert!(!StateFilter::Flagged.matches(LifecycleState::Retired, false));
+        assert_eq!(StateFilter::Active.param(), None);
+        assert_eq!(StateFilter::Flagged.param(), Some("flagged"));
+    }
+
+    #[test]
+    fn test_parse_form() {
+        let (state, by, reason) = parse_form(&form("retired", " alice ", "Wiped")).unwrap();
+        assert_eq!(state, LifecycleState::Retired);
+        assert_eq!(by, "alice");
+        assert_eq!(reason, "Wiped");
+
+        assert!(parse_form(&form("gone", "alice", "Wiped")).is_err());
+        assert!(parse_form(&form("lost", " ", "Stolen")).is_err());
+        assert!(parse_form(&form("lost", "alice", "")).is_err());
+        assert!(parse_form(&form("lost", "alice", "a\nb")).is_err());
+        assert!(parse_form(&form("lost", "alice", &"x".repeat(MAX_REASON_CHARS + 1))).is_err());
+    }
+}
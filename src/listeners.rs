@@ -0,0 +1,334 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use tokio::sync::watch;
+
+use crate::{
+    config::{Config, ListenerConfig, ListenerRole},
+    routes, shutdown, AppState,
+};
+
+/// Where a listener accepts connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// A validated listener, ready to be started by [`serve`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    pub address: Address,
+    pub tls: bool,
+    pub role: ListenerRole,
+    /// Port redirected to, for `ListenerRole::Redirect`
+    pub redirect_port: Option<u16>,
+}
+
+/// Turn the configured listeners into validated ones.
+///
+/// With no `[[listeners]]` in the config, a single listener serving everything is
+/// started on `bind`, using TLS when a certificate is available (the behavior before
+/// listeners could be configured).
+pub fn resolve(cfg: &Config, bind: &str, tls_available: bool) -> Result<Vec<Listener>> {
+    if cfg.listeners.is_empty() {
+        let addr = bind.parse().context("parse bind address")?;
+        return Ok(vec![Listener {
+            address: Address::Tcp(addr),
+            tls: tls_available,
+            role: ListenerRole::All,
+            redirect_port: None,
+        }]);
+    }
+
+    let first_tls_port = cfg
+        .listeners
+        .iter()
+        .filter(|l| l.tls)
+        .find_map(|l| l.bind.as_deref()?.parse::<SocketAddr>().ok())
+        .map(|addr| addr.port());
+
+    cfg.listeners
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            resolve_one(l, tls_available, first_tls_port).with_context(|| format!("listeners[{i}]"))
+        })
+        .collect()
+}
+
+fn resolve_one(
+    cfg: &ListenerConfig,
+    tls_available: bool,
+    first_tls_port: Option<u16>,
+) -> Result<Listener> {
+    let address = match (&cfg.bind, &cfg.unix_socket) {
+        (Some(bind), None) => Address::Tcp(bind.parse().context("parse bind address")?),
+        #[cfg(unix)]
+        (None, Some(path)) => {
+            if cfg.tls {
+                bail!("tls is not supported on unix_socket listeners");
+            }
+            Address::Unix(PathBuf::from(path))
+        }
+        #[cfg(not(unix))]
+        (None, Some(_)) => bail!("unix_socket listeners are only supported on Unix"),
+        _ => bail!("exactly one of bind and unix_socket must be set"),
+    };
+
+    if cfg.tls && !tls_available {
+        bail!("tls = true requires tls_cert and tls_key");
+    }
+
+    let redirect_port = match cfg.serve {
+        ListenerRole::Redirect => Some(
+            cfg.redirect_port
+                .or(first_tls_port)
+                .context("serve = \"redirect\" needs redirect_port or a TLS listener")?,
+        ),
+        _ => None,
+    };
+
+    Ok(Listener {
+        address,
+        tls: cfg.tls,
+        role: cfg.serve,
+        redirect_port,
+    })
+}
+
+/// Router for what `listener` is configured to serve
+pub fn router_for(listener: &Listener, state: Arc<AppState>) -> Router {
+    match listener.role {
+        ListenerRole::All => routes::build_router(state),
+        ListenerRole::Health => routes::health_router(state),
+        ListenerRole::Redirect => {
+            routes::redirect_router(listener.redirect_port.expect("resolved redirect port"))
+        }
+    }
+}
+
+/// Run every listener until `signal` resolves, then drain them all within `timeout`.
+///
+/// TLS listeners share `tls`, so a certificate reload applies to all of them.
+pub async fn serve<F>(
+    listeners: Vec<Listener>,
+    state: Arc<AppState>,
+    tls: Option<RustlsConfig>,
+    timeout: Duration,
+    signal: F,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    // One handle across the TCP listeners stops them together
+    let handle = Handle::new();
+    let (stop_tx, stop_rx) = watch::channel(false);
+    tokio::spawn(shutdown::drain_on(
+        async move {
+            signal.await;
+            let _ = stop_tx.send(true);
+        },
+        handle.clone(),
+        timeout,
+    ));
+
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let app = router_for(&listener, state.clone());
+        match listener.address.clone() {
+            Address::Tcp(addr) => {
+                tracing::info!(%addr, tls = listener.tls, role = ?listener.role, "Listening");
+                let handle = handle.clone();
+                if listener.tls {
+                    let config = tls.clone().context("tls listener without certificate")?;
+                    servers.spawn(async move {
+                        axum_server::bind_rustls(addr, config)
+                            .handle(handle)
+                            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                            .await
+                            .with_context(|| format!("serve https on {addr}"))
+                    });
+                } else {
+                    servers.spawn(async move {
+                        axum_server::bind(addr)
+                            .handle(handle)
+                            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                            .await
+                            .with_context(|| format!("serve http on {addr}"))
+                    });
+                }
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                tracing::info!(path = %path.display(), role = ?listener.role, "Listening");
+                let listener = unix::bind(&path)?;
+                servers.spawn(unix::serve(listener, path, app, stop_rx.clone(), timeout));
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    drop(stop_rx);
+
+    // The first listener to fail takes the others down with it
+    let mut result = Ok(());
+    while let Some(joined) = servers.join_next().await {
+        let outcome = joined.context("listener task panicked").and_then(|r| r);
+        if let Err(e) = outcome {
+            if result.is_ok() {
+                handle.shutdown();
+                servers.abort_all();
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use axum::Router;
+    use hyper_util::{
+        rt::{TokioExecutor, TokioIo},
+        server::{conn::auto, graceful::GracefulShutdown},
+        service::TowerToHyperService,
+    };
+    use tokio::{net::UnixListener, sync::watch};
+
+    pub fn bind(path: &Path) -> Result<UnixListener> {
+        // A socket file left behind by an earlier run would make bind fail
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("remove stale socket {}", path.display()))?;
+        }
+        UnixListener::bind(path).with_context(|| format!("bind unix socket {}", path.display()))
+    }
+
+    /// Accept connections until `stop` fires, then wait up to `timeout` for open
+    /// connections to finish and remove the socket file.
+    pub async fn serve(
+        listener: UnixListener,
+        path: PathBuf,
+        app: Router,
+        mut stop: watch::Receiver<bool>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let builder = auto::Builder::new(TokioExecutor::new());
+        let graceful = GracefulShutdown::new();
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Failed to accept unix socket connection");
+                            continue;
+                        }
+                    };
+                    let service = TowerToHyperService::new(app.clone());
+                    let conn = builder
+                        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                        .into_owned();
+                    let conn = graceful.watch(conn);
+                    tokio::spawn(async move {
+                        if let Err(e) = conn.await {
+                            tracing::debug!(error = %e, "Unix socket connection closed with error");
+                        }
+                    });
+                }
+                _ = stop.changed() => break,
+            }
+        }
+
+        drop(listener);
+        if tokio::time::timeout(timeout, graceful.shutdown())
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out draining unix socket connections");
+        }
+        std::fs::remove_file(&path).ok();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(bind: &str, tls: bool, serve: ListenerRole) -> ListenerConfig {
+        ListenerConfig {
+            bind: Some(bind.to_string()),
+            unix_socket: None,
+            tls,
+            serve,
+            redirect_port: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_legacy_single_listener() {
+        let cfg = Config::default();
+        let listeners = resolve(&cfg, "127.0.0.1:9000", true).unwrap();
+        assert_eq!(
+            listeners,
+            vec![Listener {
+                address: Address::Tcp("127.0.0.1:9000".parse().unwrap()),
+                tls: true,
+                role: ListenerRole::All,
+                redirect_port: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_resolve_redirect_defaults_to_tls_port() {
+        let cfg = Config {
+            listeners: vec![
+                listener("0.0.0.0:8443", true, ListenerRole::All),
+                listener("0.0.0.0:8080", false, ListenerRole::Redirect),
+            ],
+            ..Default::default()
+        };
+        let listeners = resolve(&cfg, &cfg.bind, true).unwrap();
+        assert_eq!(listeners[1].redirect_port, Some(8443));
+    }
+
+    #[test]
+    fn test_resolve_rejects_invalid_listeners() {
+        let no_tls_target = Config {
+            listeners: vec![listener("0.0.0.0:8080", false, ListenerRole::Redirect)],
+            ..Default::default()
+        };
+        assert!(resolve(&no_tls_target, "", false).is_err());
+
+        let tls_without_cert = Config {
+            listeners: vec![listener("0.0.0.0:8443", true, ListenerRole::All)],
+            ..Default::default()
+        };
+        assert!(resolve(&tls_without_cert, "", false).is_err());
+
+        let neither = Config {
+            listeners: vec![ListenerConfig {
+                bind: None,
+                ..listener("", false, ListenerRole::All)
+            }],
+            ..Default::default()
+        };
+        assert!(resolve(&neither, "", false).is_err());
+    }
+}
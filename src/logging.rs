@@ -0,0 +1,203 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::MakeWriter,
+    layer::{Layered, SubscriberExt},
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+
+use crate::config::{LogFormat, LogRotation, LoggingConfig};
+
+const MIB: u64 = 1024 * 1024;
+
+/// A layer that can be stacked onto the global subscriber alongside the log output
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+type FilterHandle = reload::Handle<EnvFilter, Layered<Vec<BoxedLayer>, Registry>>;
+
+/// Keeps the logging pipeline alive and lets the filter be adjusted at runtime
+pub struct LogHandle {
+    filter: FilterHandle,
+    _file_guard: Option<WorkerGuard>,
+}
+
+impl LogHandle {
+    /// Turn this crate's debug events on or off without restarting
+    pub fn set_debug_mode(&self, debug_mode: bool) -> Result<()> {
+        self.filter
+            .reload(env_filter(debug_mode))
+            .context("reload log filter")
+    }
+}
+
+/// Minimal console subscriber used while the config file is being loaded,
+/// before the configured logging pipeline exists.
+pub fn bootstrap_subscriber() -> impl Subscriber + Send + Sync {
+    tracing_subscriber::fmt()
+        .with_env_filter(env_filter(false))
+        .finish()
+}
+
+/// Install the global subscriber described by `cfg`, plus any `extra` layers
+/// (such as span export).
+///
+/// The returned handle must be held for the lifetime of the process so buffered
+/// file output is flushed on exit.
+pub fn init(cfg: &LoggingConfig, debug_mode: bool, extra: Vec<BoxedLayer>) -> Result<LogHandle> {
+    let mut layers = extra;
+    layers.push(fmt_layer(cfg.format, std::io::stdout, true));
+
+    let guard = match &cfg.directory {
+        Some(dir) => {
+            let appender = file_appender(cfg, Path::new(dir))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            layers.push(fmt_layer(cfg.format, writer, false));
+            Some(guard)
+        }
+        None => None,
+    };
+
+    let (filter, filter_handle) = reload::Layer::new(env_filter(debug_mode));
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .context("install tracing subscriber")?;
+
+    Ok(LogHandle {
+        filter: filter_handle,
+        _file_guard: guard,
+    })
+}
+
+/// `RUST_LOG` when set, otherwise `info`. Debug mode additionally enables this
+/// crate's debug events, which carry the check-in payload dumps.
+fn env_filter(debug_mode: bool) -> EnvFilter {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+
+    if debug_mode {
+        filter.add_directive(
+            "inventory_server=debug"
+                .parse()
+                .expect("static directive is valid"),
+        )
+    } else {
+        filter
+    }
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .boxed(),
+    }
+}
+
+fn file_appender(cfg: &LoggingConfig, dir: &Path) -> Result<BasicRollingFileAppender> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("create log directory {}", dir.display()))?;
+
+    let mut condition = RollingConditionBasic::new();
+    condition = match cfg.rotation {
+        LogRotation::Hourly => condition.hourly(),
+        LogRotation::Daily => condition.daily(),
+        LogRotation::Never => condition,
+    };
+    if cfg.max_file_size_mb > 0 {
+        condition = condition.max_size(cfg.max_file_size_mb.saturating_mul(MIB));
+    }
+
+    let path = dir.join(&cfg.file_name);
+    BasicRollingFileAppender::new(&path, condition, cfg.max_files)
+        .with_context(|| format!("open log file {}", path.display()))
+}
+
+/// Mask the account part of a user name, keeping the domain so events stay useful
+/// for troubleshooting: `CONTOSO\jdoe` -> `CONTOSO\j***`, `jdoe@contoso.com` -> `j***@contoso.com`.
+pub fn redact_user(user: &str) -> String {
+    fn mask(account: &str) -> String {
+        match account.chars().next() {
+            Some(first) => format!("{first}***"),
+            None => String::new(),
+        }
+    }
+
+    if let Some((domain, account)) = user.split_once('\\') {
+        format!("{domain}\\{}", mask(account))
+    } else if let Some((account, domain)) = user.split_once('@') {
+        format!("{}@{domain}", mask(account))
+    } else {
+        mask(user)
+    }
+}
+
+/// Render an optional user name for a log event, applying redaction when enabled
+pub fn user_for_log(user: Option<&str>, redact: bool) -> String {
+    match user {
+        Some(u) if redact => redact_user(u),
+        Some(u) => u.to_string(),
+        None => "(none)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_domain_user() {
+        assert_eq!(redact_user("CONTOSO\\jdoe"), "CONTOSO\\j***");
+    }
+
+    #[test]
+    fn test_redact_upn() {
+        assert_eq!(redact_user("jdoe@contoso.com"), "j***@contoso.com");
+    }
+
+    #[test]
+    fn test_redact_bare_user() {
+        assert_eq!(redact_user("administrator"), "a***");
+        assert_eq!(redact_user(""), "");
+    }
+
+    #[test]
+    fn test_user_for_log() {
+        assert_eq!(user_for_log(Some("CONTOSO\\jdoe"), true), "CONTOSO\\j***");
+        assert_eq!(user_for_log(Some("CONTOSO\\jdoe"), false), "CONTOSO\\jdoe");
+        assert_eq!(user_for_log(None, true), "(none)");
+    }
+
+    #[test]
+    fn test_file_appender_creates_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_dir = dir.path().join("logs");
+        let cfg = LoggingConfig::default();
+
+        let appender = file_appender(&cfg, &log_dir);
+
+        assert!(appender.is_ok());
+        assert!(log_dir.join("inventory-server.log").exists());
+    }
+}
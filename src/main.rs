@@ -1,16 +1,13 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use axum::{
-    routing::{get, post},
-    Router,
-};
 use clap::Parser;
-use tower_http::trace::TraceLayer;
-use tracing_subscriber::EnvFilter;
 
-use inventory_server::{config, db, handlers, AppState};
+use axum_server::tls_rustls::RustlsConfig;
+use inventory_server::{
+    config, db, listeners, logging, reload, shutdown, telemetry, AppState, Settings,
+};
 
 #[derive(Parser)]
 #[command(name = "inventory-server")]
@@ -25,18 +22,15 @@ struct Args {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    // Load config from config.toml in the same directory as the executable.
+    // A bootstrap subscriber covers messages emitted before logging is configured.
+    let cfg =
+        tracing::subscriber::with_default(logging::bootstrap_subscriber(), config::load_config)?;
 
-    // Load config from config.toml in the same directory as the executable
-    let cfg = config::load_config()?;
+    let startup_config = cfg.clone();
 
     // Environment variables override config file values
-    let bind_addr: SocketAddr = std::env::var("INVENTORY_BIND")
-        .unwrap_or(cfg.bind)
-        .parse()
-        .context("parse bind address")?;
+    let bind_addr = std::env::var("INVENTORY_BIND").unwrap_or(cfg.bind.clone());
 
     let db_path = match std::env::var("INVENTORY_DB_PATH")
         .ok()
@@ -46,17 +40,30 @@ async fn main() -> Result<()> {
         None => config::default_db_path()?,
     };
 
-    // Debug mode can be enabled via --debug flag, INVENTORY_DEBUG env var, or config file
-    let debug_mode = args.debug
+    // Debug mode can be enabled via --debug flag, INVENTORY_DEBUG env var, or config file.
+    // The flag and env var can't change at runtime, so they pin debug mode on across reloads.
+    let force_debug = args.debug
         || std::env::var("INVENTORY_DEBUG")
             .map(|v| v == "1" || v.to_lowercase() == "true")
-            .unwrap_or(false)
-        || cfg.debug;
-
-    println!("Starting inventory-server on {}", bind_addr);
-    println!("Database path: {}", db_path);
-    if debug_mode {
-        println!("[DEBUG] Debug mode enabled - will log all incoming checkins");
+            .unwrap_or(false);
+    let settings = Settings::from_config(&startup_config, force_debug);
+
+    let tracer_provider = telemetry::init_tracer_provider(&cfg.telemetry)?;
+    let extra_layers: Vec<logging::BoxedLayer> = tracer_provider
+        .iter()
+        .map(|provider| Box::new(telemetry::layer(provider)) as logging::BoxedLayer)
+        .collect();
+
+    // Held until exit so buffered file output is flushed
+    let log_handle = Arc::new(logging::init(
+        &cfg.logging,
+        settings.debug_mode,
+        extra_layers,
+    )?);
+
+    tracing::info!(%db_path, "Starting inventory-server");
+    if settings.debug_mode {
+        tracing::info!("Debug mode enabled - will log all incoming checkins");
     }
 
     // Ensure DB directory exists
@@ -67,42 +74,63 @@ async fn main() -> Result<()> {
     // Initialize schema + WAL
     let _ = db::open_and_init(&db_path)?;
 
-    let state = Arc::new(AppState {
-        db_path,
-        debug_mode,
-    });
-
-    let app = Router::new()
-        .route("/", get(handlers::index))
-        .route("/device/:serial", get(handlers::device_detail))
-        .route("/checkin", post(handlers::checkin))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+    let state = Arc::new(AppState::with_settings(db_path.clone(), settings));
 
     // TLS config: env vars override config file
     let cert_path = std::env::var("INVENTORY_TLS_CERT")
         .ok()
-        .or(cfg.tls_cert)
+        .or(cfg.tls_cert.clone())
         .unwrap_or_default();
     let key_path = std::env::var("INVENTORY_TLS_KEY")
         .ok()
-        .or(cfg.tls_key)
+        .or(cfg.tls_key.clone())
         .unwrap_or_default();
 
-    if !cert_path.is_empty() && !key_path.is_empty() {
-        let config = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert_path, key_path)
+    let tls = if !cert_path.is_empty() && !key_path.is_empty() {
+        let config = RustlsConfig::from_pem_file(&cert_path, &key_path)
             .await
             .context("load tls cert/key")?;
-        axum_server::bind_rustls(bind_addr, config)
-            .serve(app.into_make_service())
-            .await
-            .context("serve rustls")?;
+        Some(reload::TlsFiles {
+            config,
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+        })
     } else {
-        axum_server::bind(bind_addr)
-            .serve(app.into_make_service())
-            .await
-            .context("serve http")?;
+        None
+    };
+    let tls_config = tls.as_ref().map(|t| t.config.clone());
+    let listeners = listeners::resolve(&cfg, &bind_addr, tls.is_some())?;
+
+    // Pick up config.toml and certificate changes on SIGHUP or when the files change
+    let reloader = reload::Reloader {
+        state: state.clone(),
+        config_path: config::config_path()?,
+        force_debug,
+        startup_config,
+        log: Some(log_handle.clone()),
+        tls,
+    };
+    tokio::spawn(reloader.watch(Duration::from_secs(cfg.reload_interval_secs)));
+
+    // Stop accepting connections on SIGTERM/Ctrl+C and drain in-flight requests
+    listeners::serve(
+        listeners,
+        state,
+        tls_config,
+        Duration::from_secs(cfg.shutdown_timeout_secs),
+        shutdown::signal(),
+    )
+    .await?;
+
+    shutdown::finish(&db_path)?;
+
+    if let Some(provider) = tracer_provider {
+        // Flush spans still queued in the batch exporter
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!(error = %e, "Failed to flush OpenTelemetry spans");
+        }
     }
 
+    tracing::info!("Shutdown complete");
     Ok(())
 }
@@ -0,0 +1,416 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Redirect,
+    Form,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::{
+    clock_skew,
+    config::{CustomField, FieldType},
+    db,
+    models::DeviceMeta,
+    AppState,
+};
+
+/// Most tags one device can have
+const MAX_TAGS: usize = 32;
+const MAX_TAG_CHARS: usize = 40;
+const MAX_NOTES_CHARS: usize = 4000;
+/// Longest value of a text field
+const MAX_TEXT_CHARS: usize = 200;
+
+/// Configured custom fields, by key
+pub type CustomFields = BTreeMap<String, CustomField>;
+
+/// A tag as it will be stored: trimmed, with letters, digits, spaces and `-_.:/`
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("Tag is empty".to_string());
+    }
+    if tag.chars().count() > MAX_TAG_CHARS {
+        return Err(format!("Tags are at most {MAX_TAG_CHARS} characters"));
+    }
+    if let Some(c) = tag
+        .chars()
+        .find(|c| !c.is_alphanumeric() && !matches!(c, ' ' | '-' | '_' | '.' | ':' | '/'))
+    {
+        return Err(format!(
+            "Tags may contain letters, digits, spaces and - _ . : / (not {c:?})"
+        ));
+    }
+    Ok(tag.to_string())
+}
+
+/// Notes as they will be stored: trimmed, with `\n` line endings. Empty notes
+/// remove them.
+pub fn parse_notes(notes: &str) -> Result<String, String> {
+    let notes = notes.replace("\r\n", "\n");
+    let notes = notes.trim();
+    if notes.chars().count() > MAX_NOTES_CHARS {
+        return Err(format!("Notes are at most {MAX_NOTES_CHARS} characters"));
+    }
+    if notes
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\t'))
+    {
+        return Err("Notes contain control characters".to_string());
+    }
+    Ok(notes.to_string())
+}
+
+/// Value to store for `field` from what was entered; `None` when left empty, which
+/// clears the field
+pub fn parse_value(field: &CustomField, value: &str) -> Result<Option<String>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let parsed = match field.kind {
+        FieldType::Text => {
+            if value.chars().count() > MAX_TEXT_CHARS {
+                return Err(format!("at most {MAX_TEXT_CHARS} characters"));
+            }
+            if value.chars().any(char::is_control) {
+                return Err("contains control characters".to_string());
+            }
+            value.to_string()
+        }
+        FieldType::Number => match value.parse::<f64>() {
+            Ok(n) if n.is_finite() => n.to_string(),
+            _ => return Err(format!("{value:?} is not a number")),
+        },
+        FieldType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("{value:?} is not a date (YYYY-MM-DD)"))?
+            .format("%Y-%m-%d")
+            .to_string(),
+        FieldType::Bool => match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => "true".to_string(),
+            "false" | "no" | "off" | "0" => "false".to_string(),
+            _ => return Err(format!("{value:?} is not yes or no")),
+        },
+        FieldType::Choice => field
+            .choices
+            .iter()
+            .find(|c| c.eq_ignore_ascii_case(value))
+            .cloned()
+            .ok_or_else(|| format!("{value:?} is not one of {}", field.choices.join(", ")))?,
+    };
+    Ok(Some(parsed))
+}
+
+/// A stored value as shown on pages and in exports
+pub fn display_value(kind: FieldType, value: &str) -> String {
+    match (kind, value) {
+        (FieldType::Bool, "true") => "Yes".to_string(),
+        (FieldType::Bool, "false") => "No".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Label of a field, falling back to its key
+pub fn label<'a>(key: &'a str, field: &'a CustomField) -> &'a str {
+    field.label.as_deref().unwrap_or(key)
+}
+
+/// A configured field and a device's value for it, for the device page
+#[derive(Debug, Clone)]
+pub struct FieldValue {
+    pub key: String,
+    pub label: String,
+    pub kind: FieldType,
+    pub choices: Vec<String>,
+    /// Stored value, e.g. `true` for a bool
+    pub value: Option<String>,
+}
+
+impl FieldValue {
+    /// HTML input type for the field's editor
+    pub fn input_type(&self) -> &'static str {
+        match self.kind {
+            FieldType::Number => "number",
+            FieldType::Date => "date",
+            _ => "text",
+        }
+    }
+
+    pub fn is_bool(&self) -> bool {
+        self.kind == FieldType::Bool
+    }
+
+    pub fn is_choice(&self) -> bool {
+        self.kind == FieldType::Choice
+    }
+
+    /// Whether `option` is the stored value, to preselect it
+    pub fn is_value(&self, option: &str) -> bool {
+        self.value.as_deref() == Some(option)
+    }
+}
+
+/// Every configured field with the device's value for it; values of fields no
+/// longer configured are left out
+pub fn field_values(fields: &CustomFields, meta: &DeviceMeta) -> Vec<FieldValue> {
+    fields
+        .iter()
+        .map(|(key, field)| FieldValue {
+            key: key.clone(),
+            label: label(key, field).to_string(),
+            kind: field.kind,
+            choices: field.choices.clone(),
+            value: meta.fields.get(key).cloned(),
+        })
+        .collect()
+}
+
+/// Number of devices with one tag
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Tags in use across `metas`, alphabetically without regard to case
+pub fn tag_counts<'a>(metas: impl IntoIterator<Item = &'a DeviceMeta>) -> Vec<TagCount> {
+    let mut counts = BTreeMap::<String, TagCount>::new();
+    for tag in metas.into_iter().flat_map(|m| &m.tags) {
+        counts
+            .entry(tag.to_lowercase())
+            .or_insert_with(|| TagCount {
+                tag: tag.clone(),
+                count: 0,
+            })
+            .count += 1;
+    }
+    counts.into_values().collect()
+}
+
+/// Percent-encode everything but unreserved characters, for a path segment or
+/// query value
+pub fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Path of a device's page
+pub fn device_path(serial: &str) -> String {
+    format!("/device/{}", encode(serial))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagForm {
+    tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotesForm {
+    notes: String,
+}
+
+/// POST /device/:serial/tags - Add a tag
+#[tracing::instrument(skip(state, form))]
+pub async fn add_tag(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Form(form): Form<TagForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let tag = parse_tag(&form.tag).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let conn = open_device(&state, &serial)?;
+    let meta = db::get_device_meta(&conn, &serial).map_err(internal("query device tags"))?;
+    if meta.tags.len() >= MAX_TAGS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("A device can have at most {MAX_TAGS} tags"),
+        ));
+    }
+    if db::add_device_tag(&conn, &serial, &tag).map_err(internal("add tag"))? {
+        tracing::info!(tag, "Device tagged");
+    }
+    Ok(Redirect::to(&device_path(&serial)))
+}
+
+/// POST /device/:serial/tags/delete - Remove a tag
+#[tracing::instrument(skip(state, form))]
+pub async fn remove_tag(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Form(form): Form<TagForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let conn = open_device(&state, &serial)?;
+    if db::remove_device_tag(&conn, &serial, form.tag.trim()).map_err(internal("remove tag"))? {
+        tracing::info!(tag = form.tag.trim(), "Device tag removed");
+    }
+    Ok(Redirect::to(&device_path(&serial)))
+}
+
+/// POST /device/:serial/notes - Replace the notes; empty notes remove them
+#[tracing::instrument(skip(state, form))]
+pub async fn save_notes(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Form(form): Form<NotesForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let notes = parse_notes(&form.notes).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let conn = open_device(&state, &serial)?;
+    let now = clock_skew::format_utc(chrono::Utc::now());
+    db::set_device_notes(&conn, &serial, &notes, &now).map_err(internal("save notes"))?;
+    tracing::info!(chars = notes.chars().count(), "Device notes saved");
+    Ok(Redirect::to(&device_path(&serial)))
+}
+
+/// POST /device/:serial/fields - Set custom field values from `field.<key>` form
+/// fields. Fields left empty are cleared; fields not in the form are unchanged.
+#[tracing::instrument(skip(state, form))]
+pub async fn save_fields(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let settings = state.settings();
+    let mut values = Vec::new();
+    for (key, field) in &settings.custom_fields {
+        let Some(entered) = form.get(&format!("field.{key}")) else {
+            continue;
+        };
+        let value = parse_value(field, entered).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("{}: {e}", label(key, field)),
+            )
+        })?;
+        values.push((key, value));
+    }
+
+    let mut conn = open_device(&state, &serial)?;
+    let now = clock_skew::format_utc(chrono::Utc::now());
+    let tx = conn.transaction().map_err(internal("begin transaction"))?;
+    for (key, value) in &values {
+        db::set_device_field(&tx, &serial, key, value.as_deref(), &now)
+            .map_err(internal("save field"))?;
+    }
+    tx.commit().map_err(internal("commit fields"))?;
+    tracing::info!(fields = values.len(), "Device fields saved");
+    Ok(Redirect::to(&device_path(&serial)))
+}
+
+/// Connection for editing a device that has checked in
+fn open_device(
+    state: &AppState,
+    serial: &str,
+) -> Result<rusqlite::Connection, (StatusCode, String)> {
+    let conn = rusqlite::Connection::open(&state.db_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))?;
+    db::get_laptop_by_serial(&conn, serial)
+        .map_err(internal("query laptop"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Device not found: {serial}")))?;
+    Ok(conn)
+}
+
+fn internal<E: std::fmt::Display>(what: &'static str) -> impl Fn(E) -> (StatusCode, String) {
+    move |e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{what}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(kind: FieldType, choices: &[&str]) -> CustomField {
+        CustomField {
+            label: None,
+            kind,
+            choices: choices.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(parse_tag("  loaner ").unwrap(), "loaner");
+        assert_eq!(parse_tag("site:Zürich/2").unwrap(), "site:Zürich/2");
+        assert!(parse_tag("  ").is_err());
+        assert!(parse_tag("<b>").is_err());
+        assert!(parse_tag("a,b").is_err());
+        assert!(parse_tag(&"x".repeat(MAX_TAG_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        assert_eq!(
+            parse_notes(" Screen cracked\r\nReplaced 2024-03 \n").unwrap(),
+            "Screen cracked\nReplaced 2024-03"
+        );
+        assert_eq!(parse_notes("\n").unwrap(), "");
+        assert!(parse_notes("bell\u{7}").is_err());
+        assert!(parse_notes(&"x".repeat(MAX_NOTES_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_value_by_type() {
+        let text = field(FieldType::Text, &[]);
+        assert_eq!(
+            parse_value(&text, " A-123 ").unwrap().as_deref(),
+            Some("A-123")
+        );
+        assert_eq!(parse_value(&text, "  ").unwrap(), None);
+        assert!(parse_value(&text, "a\nb").is_err());
+
+        let number = field(FieldType::Number, &[]);
+        assert_eq!(
+            parse_value(&number, "1200.50").unwrap().as_deref(),
+            Some("1200.5")
+        );
+        assert_eq!(parse_value(&number, "-3").unwrap().as_deref(), Some("-3"));
+        assert!(parse_value(&number, "12 EUR").is_err());
+        assert!(parse_value(&number, "NaN").is_err());
+
+        let date = field(FieldType::Date, &[]);
+        assert_eq!(
+            parse_value(&date, "2024-02-29").unwrap().as_deref(),
+            Some("2024-02-29")
+        );
+        assert!(parse_value(&date, "2023-02-29").is_err());
+        assert!(parse_value(&date, "29/02/2024").is_err());
+
+        let flag = field(FieldType::Bool, &[]);
+        assert_eq!(parse_value(&flag, "Yes").unwrap().as_deref(), Some("true"));
+        assert_eq!(parse_value(&flag, "off").unwrap().as_deref(), Some("false"));
+        assert!(parse_value(&flag, "maybe").is_err());
+        assert_eq!(display_value(FieldType::Bool, "true"), "Yes");
+
+        let choice = field(FieldType::Choice, &["IT", "Sales"]);
+        assert_eq!(
+            parse_value(&choice, "sales").unwrap().as_deref(),
+            Some("Sales")
+        );
+        assert!(parse_value(&choice, "Legal").is_err());
+    }
+
+    #[test]
+    fn test_tag_counts_ignore_case() {
+        let meta = |tags: &[&str]| DeviceMeta {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..DeviceMeta::default()
+        };
+        let metas = [meta(&["Loaner", "spare"]), meta(&["loaner"])];
+        let counts = tag_counts(&metas);
+        let counts: Vec<(&str, usize)> = counts.iter().map(|t| (t.tag.as_str(), t.count)).collect();
+        assert_eq!(counts, [("Loaner", 2), ("spare", 1)]);
+    }
+
+    #[test]
+    fn test_device_path() {
+        assert_eq!(device_path("SN-001"), "/device/SN-001");
+        assert_eq!(device_path("A B/C"), "/device/A%20B%2FC");
+    }
+}
@@ -1,17 +1,17 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
-use validator::{Validate, ValidationError};
+use unicode_normalization::is_nfc;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::config::{Charset, HostnamePolicy, ValidationConfig};
+use crate::lifecycle::LifecycleState;
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct Drive {
-    #[validate(
-        length(min = 1, max = 256),
-        custom(function = "validate_printable_ascii_required")
-    )]
+    #[validate(length(min = 1, max = 256), custom(function = "validate_text"))]
     pub model: String,
-    #[validate(
-        length(max = 256),
-        custom(function = "validate_printable_ascii_required")
-    )]
+    #[validate(length(max = 256), custom(function = "validate_text"))]
     pub serial_number: Option<String>,
     #[validate(
         length(min = 1, max = 256),
@@ -22,14 +22,11 @@ pub struct Drive {
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct CheckIn {
-    #[validate(length(min = 1, max = 63), custom(function = "validate_hostname"))]
+    #[validate(length(min = 1, max = 253), custom(function = "validate_hostname"))]
     pub hostname: String,
     #[validate(custom(function = "validate_ip_address"))]
     pub ip_address: String,
-    #[validate(
-        length(max = 512),
-        custom(function = "validate_printable_ascii_required")
-    )]
+    #[validate(length(max = 512), custom(function = "validate_text"))]
     pub logged_in_user: Option<String>,
     #[validate(
         length(min = 1, max = 128),
@@ -40,6 +37,98 @@ pub struct CheckIn {
     pub drives: Vec<Drive>,
     #[validate(custom(function = "validate_timestamp"))]
     pub timestamp_utc: String,
+    /// Agent-generated ID for this submission; a retry with the same ID is not stored twice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_checkin_id"))]
+    pub checkin_id: Option<String>,
+}
+
+impl CheckIn {
+    /// Validate with the configured rules: the hostname policy, and the charset for the
+    /// user name and drive models and serials. The derived `validate` is the loosest
+    /// form, accepting an FQDN and any safe Unicode as [`Charset::Unicode`] does.
+    pub fn validate_with(&self, rules: &ValidationConfig) -> Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        if !errors.0.contains_key("hostname")
+            && !crate::hostname::is_valid(&self.hostname, rules.hostname)
+        {
+            errors.add("hostname", ValidationError::new("invalid_hostname"));
+        }
+        if rules.charset == Charset::Ascii {
+            require_ascii(
+                &mut errors,
+                "logged_in_user",
+                self.logged_in_user.as_deref(),
+            );
+            self.require_ascii_drives(&mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn require_ascii_drives(&self, errors: &mut ValidationErrors) {
+        let mut items = match errors.0.remove("drives") {
+            Some(ValidationErrorsKind::List(items)) => items,
+            None => std::collections::BTreeMap::new(),
+            // Too many drives, so they weren't checked one by one
+            Some(other) => {
+                errors.0.insert(Cow::Borrowed("drives"), other);
+                return;
+            }
+        };
+        for (index, drive) in self.drives.iter().enumerate() {
+            let item = items.entry(index).or_default();
+            require_ascii(item, "model", Some(&drive.model));
+            require_ascii(item, "serial_number", drive.serial_number.as_deref());
+        }
+        items.retain(|_, item| !item.is_empty());
+        if !items.is_empty() {
+            errors
+                .0
+                .insert(Cow::Borrowed("drives"), ValidationErrorsKind::List(items));
+        }
+    }
+}
+
+/// Flag a non-ASCII value, unless the field already failed another rule
+fn require_ascii(errors: &mut ValidationErrors, field: &'static str, value: Option<&str>) {
+    if value.is_some_and(|v| !v.is_ascii()) && !errors.0.contains_key(field) {
+        errors.add(field, ValidationError::new("invalid_characters"));
+    }
+}
+
+/// Outcome of one item in a `/checkin/batch` request
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BatchItemResult {
+    /// Position of the item in the request
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub laptop_serial: Option<String>,
+    /// `accepted` or `rejected`
+    pub status: String,
+    /// Set when the item's `checkin_id` was already stored, so it was not stored again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+    /// Whether the item became the device's current state (accepted items only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Failing fields of a rejected item, when `validation.detailed_errors` is enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<crate::validation::FieldError>,
+}
+
+/// Response body for `/checkin/batch`, with results in request order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub results: Vec<BatchItemResult>,
 }
 
 /// Represents a row from the laptops table for display
@@ -51,6 +140,15 @@ pub struct LaptopRow {
     pub logged_in_user: Option<String>,
     pub last_seen_utc: String,
     pub drives_json: String,
+    /// Server time of the latest check-in (absent for rows stored before it was recorded)
+    pub received_at_utc: Option<String>,
+    pub clock_skew_secs: Option<i64>,
+    /// Address the latest check-in was observed to come from
+    pub source_ip: Option<String>,
+    /// `hostname` up to the first dot (absent for rows stored before it was recorded)
+    pub short_name: Option<String>,
+    /// Lowercased DNS domain of `hostname`, when it was reported as an FQDN
+    pub domain: Option<String>,
 }
 
 /// Represents a row from the checkins table for display
@@ -60,17 +158,192 @@ pub struct CheckinRow {
     pub ip_address: String,
     pub logged_in_user: Option<String>,
     pub timestamp_utc: String,
+    pub received_at_utc: Option<String>,
+    pub clock_skew_secs: Option<i64>,
+    pub source_ip: Option<String>,
+    /// Serial the agent sent, when stored under another one
+    pub reported_serial: Option<String>,
+}
+
+impl CheckinRow {
+    /// Skew for display, `-` when it wasn't recorded
+    pub fn clock_skew_display(&self) -> String {
+        self.clock_skew_secs
+            .map(crate::clock_skew::describe)
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+/// Represents a row from the quarantine table for the admin page
+#[derive(Debug, Clone)]
+pub struct QuarantineRow {
+    pub id: i64,
+    pub received_at_utc: String,
+    pub source_ip: Option<String>,
+    pub laptop_serial: Option<String>,
+    pub body: String,
+    pub error: String,
+    pub errors_json: String,
+}
+
+impl QuarantineRow {
+    /// Failed validation rules, empty when the payload couldn't be read at all
+    pub fn field_errors(&self) -> Vec<crate::validation::FieldError> {
+        serde_json::from_str(&self.errors_json).unwrap_or_default()
+    }
+}
+
+/// A device's latest check-in matching a search. Field values carry the match
+/// markers passed to [`crate::db::search_checkins`].
+#[derive(Debug, Clone)]
+pub struct SearchRow {
+    /// The device's serial, without markers
+    pub serial: String,
+    pub laptop_serial: String,
+    pub hostname: String,
+    pub logged_in_user: Option<String>,
+    /// Reported and observed addresses, space separated
+    pub addresses: String,
+    /// Drive models, comma separated
+    pub drive_models: Option<String>,
+    /// Drive serial numbers, comma separated
+    pub drive_serials: Option<String>,
+    /// Check-ins of the device that match
+    pub matching_checkins: i64,
+    pub first_match_utc: String,
+    pub last_match_utc: String,
+    /// Whether the latest match is the device's current state
+    pub current: bool,
 }
 
 /// Represents a laptop row with parsed drives for index page display
 #[derive(Debug)]
 pub struct IndexLaptopRow {
     pub laptop_serial: String,
+    /// Full name as reported
     pub hostname: String,
+    pub short_name: String,
+    pub domain: Option<String>,
     pub ip_address: String,
     pub logged_in_user: Option<String>,
     pub last_seen_utc: String,
-    pub drive_serials_display: String,
+    /// Drive serial numbers, each rendered (and escaped) on its own line
+    pub drive_serials: Vec<String>,
+    /// Named subnet the device's address is in
+    pub location: Option<String>,
+    /// Set when the latest check-in's clock skew is outside the configured window
+    pub clock_skew_warning: Option<String>,
+    /// Tags, notes and custom field values
+    pub meta: DeviceMeta,
+    pub lifecycle_state: LifecycleState,
+    /// Check-ins since the device was marked lost, retired or disposed
+    pub lifecycle_alert: Option<LifecycleAlertRow>,
+}
+
+/// A device's latest check-in from an address in the searched networks
+#[derive(Debug, Clone)]
+pub struct NetworkRow {
+    pub laptop_serial: String,
+    pub hostname: String,
+    pub ip_address: String,
+    pub source_ip: Option<String>,
+    pub logged_in_user: Option<String>,
+    pub matching_checkins: i64,
+    pub first_seen_utc: String,
+    pub last_seen_utc: String,
+    /// Addresses of the device's current state
+    pub current_ip_address: Option<String>,
+    pub current_source_ip: Option<String>,
+}
+
+impl NetworkRow {
+    /// Reported then observed address of the matching check-in
+    pub fn addresses(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.ip_address.as_str()).chain(self.source_ip.as_deref())
+    }
+}
+
+/// Check-ins of one `logged_in_user` value on one device
+#[derive(Debug, Clone)]
+pub struct UserDeviceRow {
+    /// The user name as reported
+    pub logged_in_user: String,
+    pub laptop_serial: String,
+    /// Current hostname and user of the device
+    pub hostname: Option<String>,
+    pub current_user: Option<String>,
+    pub checkins: i64,
+    /// Earliest and latest check-in times, as RFC 3339 in UTC
+    pub first_seen_utc: String,
+    pub last_seen_utc: String,
+}
+
+/// Tags, notes and custom field values an admin entered for one device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceMeta {
+    /// Tags in alphabetical order
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub notes_updated_utc: Option<String>,
+    /// Custom field values by key, as stored
+    pub fields: std::collections::BTreeMap<String, String>,
+}
+
+/// A recorded lifecycle state change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LifecycleEventRow {
+    pub id: i64,
+    pub laptop_serial: String,
+    /// State key, e.g. `in_repair`
+    pub state: String,
+    pub changed_at_utc: String,
+    pub changed_by: String,
+    pub reason: String,
+}
+
+/// Check-ins from a device after it was marked lost, retired or disposed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleAlertRow {
+    #[serde(skip)]
+    pub laptop_serial: String,
+    /// State key the device was in
+    pub state: String,
+    pub checkins: i64,
+    /// Server times of the first and latest such check-in
+    pub first_received_utc: String,
+    pub last_received_utc: String,
+}
+
+/// Check-ins reporting one serial that are stored under another device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialRemapRow {
+    pub reported_serial: String,
+    /// Host the remap applies to; empty for any host
+    pub hostname: String,
+    pub laptop_serial: String,
+    pub created_at_utc: String,
+    pub created_by: String,
+    pub reason: String,
+}
+
+/// A serial that check-ins from apparently different machines were stored under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialCollisionRow {
+    pub laptop_serial: String,
+    pub detections: i64,
+    pub first_detected_utc: String,
+    pub last_detected_utc: String,
+    /// Hostname of the latest check-in that didn't match
+    pub last_hostname: String,
+}
+
+/// One hostname in a device's check-in history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostnameCount {
+    pub hostname: String,
+    pub checkins: i64,
+    pub first_seen_utc: String,
+    pub last_seen_utc: String,
 }
 
 /// Validates that a string is a valid IPv4 or IPv6 address
@@ -98,24 +371,37 @@ fn validate_printable_ascii_required(s: &str) -> Result<(), ValidationError> {
     }
 }
 
-/// Validates that a hostname follows Windows computer name conventions
-/// Allows alphanumeric, hyphens, and underscores (common in Windows environments)
+/// Validates free text that may come from any locale: NFC-normalized, with no
+/// control characters and no bidi formatting characters that could reorder how
+/// it and its neighbours are displayed
+fn validate_text(s: &str) -> Result<(), ValidationError> {
+    if s.chars().any(|c| c.is_control() || is_bidi_control(c)) {
+        Err(ValidationError::new("invalid_characters"))
+    } else if !is_nfc(s) {
+        Err(ValidationError::new("not_normalized"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Explicit bidi formatting characters: marks, embeddings, overrides and isolates
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Validates a `checkin_id` with the same rules as the `Idempotency-Key` header
+fn validate_checkin_id(id: &str) -> Result<(), ValidationError> {
+    if crate::idempotency::is_valid_key(id) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_checkin_id"))
+    }
+}
+
+/// Validates that a hostname is a Windows computer name, optionally followed by a
+/// DNS domain; [`CheckIn::validate_with`] narrows this to the configured policy
 fn validate_hostname(hostname: &str) -> Result<(), ValidationError> {
-    // Must start and end with alphanumeric, can contain hyphens and underscores in the middle
-    // This accommodates real-world Windows computer names which may include underscores
-    let valid = hostname
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
-        && hostname
-            .chars()
-            .next()
-            .is_some_and(|c| c.is_ascii_alphanumeric())
-        && hostname
-            .chars()
-            .last()
-            .is_some_and(|c| c.is_ascii_alphanumeric());
-
-    if valid {
+    if crate::hostname::is_valid(hostname, HostnamePolicy::Fqdn) {
         Ok(())
     } else {
         Err(ValidationError::new("invalid_hostname"))
@@ -179,6 +465,7 @@ mod tests {
                 device_id: "PHYSICALDRIVE0".to_string(),
             }],
             timestamp_utc: "2025-12-18T10:00:00Z".to_string(),
+            checkin_id: None,
         };
 
         let json = serde_json::to_string(&checkin).unwrap();
@@ -225,6 +512,7 @@ mod tests {
                 },
             ],
             timestamp_utc: "2025-12-18T14:00:00Z".to_string(),
+            checkin_id: None,
         };
 
         let json = serde_json::to_string(&checkin).unwrap();
@@ -245,6 +533,7 @@ mod tests {
             laptop_serial: "ABC123".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T10:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin.validate().is_ok());
 
@@ -256,6 +545,7 @@ mod tests {
             laptop_serial: "XYZ789".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T11:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin2.validate().is_ok());
 
@@ -267,6 +557,7 @@ mod tests {
             laptop_serial: "BAD001".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T12:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin3.validate().is_err());
 
@@ -278,6 +569,7 @@ mod tests {
             laptop_serial: "BAD002".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T13:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin4.validate().is_err());
 
@@ -289,6 +581,7 @@ mod tests {
             laptop_serial: "BAD003".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T14:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin5.validate().is_err());
     }
@@ -305,6 +598,7 @@ mod tests {
             laptop_serial: "TRAD001".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T15:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin.validate().is_ok());
 
@@ -316,7 +610,150 @@ mod tests {
             laptop_serial: "TRAD002".to_string(),
             drives: vec![],
             timestamp_utc: "2025-12-21T16:00:00Z".to_string(),
+            checkin_id: None,
         };
         assert!(checkin2.validate().is_ok());
     }
+
+    fn checkin_with_text(user: &str, model: &str, serial: &str) -> CheckIn {
+        CheckIn {
+            hostname: "LAPTOP-01".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            logged_in_user: Some(user.to_string()),
+            laptop_serial: "SN1".to_string(),
+            drives: vec![Drive {
+                model: model.to_string(),
+                serial_number: Some(serial.to_string()),
+                device_id: "PHYSICALDRIVE0".to_string(),
+            }],
+            timestamp_utc: "2025-12-21T15:00:00Z".to_string(),
+            checkin_id: None,
+        }
+    }
+
+    fn rules(charset: Charset) -> ValidationConfig {
+        ValidationConfig {
+            charset,
+            ..ValidationConfig::default()
+        }
+    }
+
+    fn codes(result: Result<(), ValidationErrors>) -> Vec<(String, String)> {
+        result.map(|()| Vec::new()).unwrap_or_else(|e| {
+            crate::validation::field_errors(&e)
+                .into_iter()
+                .map(|f| (f.field, f.code))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_unicode_text_in_mixed_scripts() {
+        for (user, model, serial) in [
+            ("CONTOSO\\José", "Samsung SSD 970", "S5H2NS0N123456"),
+            ("山田 太郎", "東芝 MQ04", "X7-東京"),
+            ("Иван Petrov", "Накопитель", "SN-Ж1"),
+            ("محمد علي", "WD Blue", "ΑΒΓ-123"),
+            ("दीपक 👩‍💻", "Crucial MX500", "ज़-9"),
+        ] {
+            let checkin = checkin_with_text(user, model, serial);
+            assert!(
+                checkin.validate_with(&rules(Charset::Unicode)).is_ok(),
+                "{user}"
+            );
+            assert!(
+                checkin.validate_with(&rules(Charset::Ascii)).is_err(),
+                "{user}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ascii_charset_names_each_field() {
+        let checkin = checkin_with_text("José", "Накопитель", "SN1");
+        assert_eq!(
+            codes(checkin.validate_with(&rules(Charset::Ascii))),
+            [
+                (
+                    "drives[0].model".to_string(),
+                    "invalid_characters".to_string()
+                ),
+                (
+                    "logged_in_user".to_string(),
+                    "invalid_characters".to_string()
+                ),
+            ]
+        );
+
+        // Another failed rule isn't repeated as invalid_characters
+        let long = "é".repeat(600);
+        assert_eq!(
+            codes(checkin_with_text(&long, "M", "S").validate_with(&rules(Charset::Ascii))),
+            [("logged_in_user".to_string(), "length".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_unicode_text_rejects_unsafe_characters() {
+        for (user, code) in [
+            ("admin\u{202E}nimda", "invalid_characters"),
+            ("user\u{2066}x\u{2069}", "invalid_characters"),
+            ("user\u{200F}", "invalid_characters"),
+            ("tab\there", "invalid_characters"),
+            ("bell\u{7}", "invalid_characters"),
+            ("c1\u{85}", "invalid_characters"),
+            // "José" with a combining acute accent instead of é
+            ("Jose\u{301}", "not_normalized"),
+        ] {
+            let checkin = checkin_with_text(user, "M", "S");
+            assert_eq!(
+                codes(checkin.validate_with(&rules(Charset::Unicode))),
+                [("logged_in_user".to_string(), code.to_string())],
+                "{user:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_serial_and_device_id_stay_ascii() {
+        let mut checkin = checkin_with_text("José", "M", "S");
+        checkin.laptop_serial = "SN-東京".to_string();
+        checkin.drives[0].device_id = "ДИСК0".to_string();
+        assert_eq!(
+            codes(checkin.validate_with(&rules(Charset::Unicode))),
+            [
+                (
+                    "drives[0].device_id".to_string(),
+                    "invalid_characters".to_string()
+                ),
+                (
+                    "laptop_serial".to_string(),
+                    "invalid_characters".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hostname_policy() {
+        let mut checkin = checkin_with_text("user", "M", "S");
+        checkin.hostname = "mac-mini.corp.example.com".to_string();
+        let mut rules = ValidationConfig::default();
+        assert_eq!(
+            codes(checkin.validate_with(&rules)),
+            [("hostname".to_string(), "invalid_hostname".to_string())]
+        );
+
+        rules.hostname = HostnamePolicy::Fqdn;
+        assert!(checkin.validate_with(&rules).is_ok());
+
+        checkin.hostname = [&*"a".repeat(60); 5].join(".");
+        assert_eq!(
+            codes(checkin.validate_with(&rules)),
+            [
+                ("hostname".to_string(), "invalid_hostname".to_string()),
+                ("hostname".to_string(), "length".to_string()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::IpNetwork, db, errors::ErrorBody, models::NetworkRow, request_id, AppState};
+
+/// Devices returned when no `limit` is given, and the most that can be asked for
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 500;
+
+/// Named networks from the `[subnets]` config section
+pub type Subnets = BTreeMap<String, Vec<IpNetwork>>;
+
+/// Sortable text form of an address, stored in `checkin_addresses`: `4` and 8 hex
+/// digits for IPv4, `6` and 32 for IPv6. The addresses of a network are a contiguous
+/// range of keys. IPv4-mapped IPv6 addresses are keyed as IPv4.
+pub fn ip_key(ip: IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => format!("4{:08x}", u32::from(v4)),
+        IpAddr::V6(v6) => format!("6{:032x}", u128::from(v6)),
+    }
+}
+
+/// Key of a stored address; `None` for text that isn't an IP address
+pub fn parse_key(address: &str) -> Option<String> {
+    address.parse().ok().map(ip_key)
+}
+
+/// First and last key of the addresses in `net`
+pub fn key_range(net: &IpNet) -> (String, String) {
+    (ip_key(net.network()), ip_key(net.broadcast()))
+}
+
+/// Name of the most specific subnet containing any of `addresses`, trying them in
+/// order, e.g. the reported address before the observed one
+pub fn location<'a>(
+    addresses: impl IntoIterator<Item = &'a str>,
+    subnets: &'a Subnets,
+) -> Option<&'a str> {
+    addresses.into_iter().find_map(|address| {
+        let ip = address.parse::<IpAddr>().ok()?.to_canonical();
+        subnets
+            .iter()
+            .flat_map(|(name, nets)| nets.iter().map(move |net| (name, net.0)))
+            .filter(|(_, net)| net.contains(&ip))
+            .max_by_key(|(_, net)| net.prefix_len())
+            .map(|(name, _)| name.as_str())
+    })
+}
+
+/// Networks a query stands for: those of a configured subnet, matched by name
+/// without regard to case, or an address or CIDR range
+pub fn resolve(q: &str, subnets: &Subnets) -> Option<Vec<IpNet>> {
+    let q = q.trim();
+    if let Some((_, nets)) = subnets
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(q))
+    {
+        return Some(nets.iter().map(|n| n.0).collect());
+    }
+    IpNetwork::try_from(q.to_string())
+        .ok()
+        .map(|n| vec![n.0.trunc()])
+}
+
+/// A device seen in the searched networks
+#[derive(Debug, Clone)]
+pub struct NetworkResult {
+    pub row: NetworkRow,
+    /// Location of the latest matching check-in
+    pub location: Option<String>,
+    /// Whether the device's current address is in the searched networks
+    pub current: bool,
+}
+
+/// Devices with check-ins from an address in `nets`, most recently seen there first
+pub fn search(
+    conn: &rusqlite::Connection,
+    nets: &[IpNet],
+    subnets: &Subnets,
+    limit: usize,
+) -> anyhow::Result<Vec<NetworkResult>> {
+    let ranges: Vec<(String, String)> = nets.iter().map(key_range).collect();
+    let rows = db::find_checkins_by_address(conn, &ranges, limit)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let location = location(row.addresses(), subnets).map(str::to_string);
+            let current = [&row.current_ip_address, &row.current_source_ip]
+                .into_iter()
+                .flatten()
+                .filter_map(|a| a.parse::<IpAddr>().ok())
+                .any(|ip| nets.iter().any(|n| n.contains(&ip.to_canonical())));
+            NetworkResult {
+                row,
+                location,
+                current,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+impl NetworkQuery {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+/// Outcome of a query: the networks searched, the devices found and whether there
+/// were more than the limit
+struct Found {
+    nets: Vec<IpNet>,
+    results: Vec<NetworkResult>,
+    truncated: bool,
+}
+
+fn run(state: &AppState, query: &NetworkQuery, subnets: &Subnets) -> Result<Found, RunError> {
+    let nets = resolve(&query.q, subnets).ok_or(RunError::Invalid)?;
+    let conn = rusqlite::Connection::open(&state.db_path).map_err(|e| RunError::Db(e.into()))?;
+    let limit = query.limit();
+    let mut results = search(&conn, &nets, subnets, limit + 1).map_err(RunError::Db)?;
+    let truncated = results.len() > limit;
+    results.truncate(limit);
+    Ok(Found {
+        nets,
+        results,
+        truncated,
+    })
+}
+
+enum RunError {
+    Invalid,
+    Db(anyhow::Error),
+}
+
+#[derive(Template)]
+#[template(path = "network.html")]
+pub struct NetworkTemplate {
+    pub q: String,
+    /// Networks searched, as CIDR ranges
+    pub nets: Vec<String>,
+    pub results: Vec<NetworkResult>,
+    pub truncated: bool,
+    /// Configured subnets and their ranges, listed when nothing was searched
+    pub subnets: Vec<(String, String)>,
+}
+
+/// GET /network?q= - Devices seen at an address, in a CIDR range or in a named subnet
+#[tracing::instrument(skip(state))]
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NetworkQuery>,
+) -> Result<NetworkTemplate, (StatusCode, String)> {
+    let settings = state.settings();
+    let subnets = settings
+        .subnets
+        .iter()
+        .map(|(name, nets)| {
+            let ranges: Vec<String> = nets.iter().map(|n| n.0.to_string()).collect();
+            (name.clone(), ranges.join(", "))
+        })
+        .collect();
+
+    if query.q.trim().is_empty() {
+        return Ok(NetworkTemplate {
+            q: String::new(),
+            nets: Vec::new(),
+            results: Vec::new(),
+            truncated: false,
+            subnets,
+        });
+    }
+
+    let found = run(&state, &query, &settings.subnets).map_err(|e| match e {
+        RunError::Invalid => (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Not an IP address, CIDR range or subnet name: {}",
+                query.q.trim()
+            ),
+        ),
+        RunError::Db(e) => {
+            tracing::error!(error = ?e, "Network search failed");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "network search failed".to_string(),
+            )
+        }
+    })?;
+
+    Ok(NetworkTemplate {
+        q: query.q,
+        nets: found.nets.iter().map(IpNet::to_string).collect(),
+        results: found.results,
+        truncated: found.truncated,
+        subnets,
+    })
+}
+
+/// One device in the JSON response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiDevice {
+    pub laptop_serial: String,
+    pub hostname: String,
+    /// Addresses and user of the latest matching check-in
+    pub ip_address: String,
+    pub source_ip: Option<String>,
+    pub logged_in_user: Option<String>,
+    pub location: Option<String>,
+    pub current: bool,
+    pub matching_checkins: i64,
+    pub first_seen_utc: String,
+    pub last_seen_utc: String,
+}
+
+/// Response body for `/api/v1/network`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkResponse {
+    pub query: String,
+    pub networks: Vec<String>,
+    pub truncated: bool,
+    pub results: Vec<ApiDevice>,
+}
+
+/// GET /api/v1/network?q= - Network search results as JSON
+#[tracing::instrument(skip(state))]
+pub async fn api(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NetworkQuery>,
+) -> Result<Json<NetworkResponse>, Response> {
+    if query.q.trim().is_empty() {
+        return Err(bad_request("Missing network query", None));
+    }
+    let settings = state.settings();
+    let found = run(&state, &query, &settings.subnets).map_err(|e| match e {
+        RunError::Invalid => bad_request(
+            "Invalid network query",
+            Some("expected an IP address, a CIDR range or a configured subnet name".to_string()),
+        ),
+        RunError::Db(e) => {
+            tracing::error!(error = ?e, "Network search failed");
+            let body = ErrorBody {
+                error: "Internal server error",
+                detail: None,
+                errors: Vec::new(),
+                request_id: request_id::current(),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    })?;
+
+    let results = found
+        .results
+        .into_iter()
+        .map(|r| ApiDevice {
+            laptop_serial: r.row.laptop_serial,
+            hostname: r.row.hostname,
+            ip_address: r.row.ip_address,
+            source_ip: r.row.source_ip,
+            logged_in_user: r.row.logged_in_user,
+            location: r.location,
+            current: r.current,
+            matching_checkins: r.row.matching_checkins,
+            first_seen_utc: r.row.first_seen_utc,
+            last_seen_utc: r.row.last_seen_utc,
+        })
+        .collect();
+
+    Ok(Json(NetworkResponse {
+        query: query.q.trim().to_string(),
+        networks: found.nets.iter().map(IpNet::to_string).collect(),
+        truncated: found.truncated,
+        results,
+    }))
+}
+
+fn bad_request(error: &'static str, detail: Option<String>) -> Response {
+    let body = ErrorBody {
+        error,
+        detail,
+        errors: Vec::new(),
+        request_id: request_id::current(),
+    };
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnets() -> Subnets {
+        let net = |s: &str| IpNetwork::try_from(s.to_string()).unwrap();
+        [
+            (
+                "HQ".to_string(),
+                vec![net("10.1.0.0/16"), net("fd00:1::/48")],
+            ),
+            ("HQ lab".to_string(), vec![net("10.1.5.0/24")]),
+            ("VPN".to_string(), vec![net("10.200.0.0/20")]),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn test_ip_key_orders_addresses() {
+        let key = |s: &str| parse_key(s).unwrap();
+        assert_eq!(key("10.1.2.3"), "40a010203");
+        assert_eq!(key("::ffff:10.1.2.3"), key("10.1.2.3"));
+        assert_eq!(key("fd00:1::1"), "6fd000001000000000000000000000001");
+        assert!(key("10.1.2.3") < key("10.1.2.10"));
+        assert!(key("9.255.255.255") < key("10.0.0.0"));
+        assert_eq!(parse_key("not-an-ip"), None);
+
+        let (first, last) = key_range(&"10.1.0.0/16".parse().unwrap());
+        assert_eq!((first.as_str(), last.as_str()), ("40a010000", "40a01ffff"));
+        let inside = key("10.1.200.7");
+        assert!(first <= inside && inside <= last);
+    }
+
+    #[test]
+    fn test_location_most_specific() {
+        let subnets = subnets();
+        assert_eq!(location(["10.1.9.9"], &subnets), Some("HQ"));
+        assert_eq!(location(["10.1.5.9"], &subnets), Some("HQ lab"));
+        assert_eq!(location(["fd00:1:0:2::10"], &subnets), Some("HQ"));
+        // Falls back to the next address
+        assert_eq!(
+            location(["192.168.1.5", "10.200.3.4"], &subnets),
+            Some("VPN")
+        );
+        assert_eq!(location(["192.168.1.5", "bogus"], &subnets), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let subnets = subnets();
+        assert_eq!(resolve("hq", &subnets).unwrap().len(), 2);
+        assert_eq!(
+            resolve(" 10.1.2.3/16 ", &subnets).unwrap(),
+            vec!["10.1.0.0/16".parse::<IpNet>().unwrap()]
+        );
+        assert_eq!(
+            resolve("2001:db8::1", &subnets).unwrap(),
+            vec!["2001:db8::1/128".parse::<IpNet>().unwrap()]
+        );
+        assert_eq!(resolve("10.1.0.0/40", &subnets), None);
+        assert_eq!(resolve("Paris", &subnets), None);
+    }
+}
@@ -0,0 +1,256 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    clock_skew, db,
+    errors::CheckInError,
+    handlers::{self, Stored},
+    idempotency,
+    models::QuarantineRow,
+    validation::{self, FieldError},
+    AppState, Settings,
+};
+
+#[derive(Template)]
+#[template(path = "quarantine.html")]
+pub struct QuarantineTemplate {
+    pub entries: Vec<QuarantineRow>,
+    pub max_rows: usize,
+    pub rejection_counts: Vec<db::RejectionCount>,
+    /// Outcome of the last re-process, shown after the redirect
+    pub notice: Option<String>,
+    /// For the re-process and discard forms
+    pub csrf_token: String,
+}
+
+/// Message and failed rules for a rejection that belongs in the quarantine: the
+/// payload failed validation or couldn't be read as a check-in. Limits, clock skew
+/// and server errors aren't quarantined.
+pub fn reason(err: &CheckInError) -> Option<(String, Vec<FieldError>)> {
+    match err {
+        CheckInError::ValidationFailed(e) | CheckInError::ValidationDetailed(e) => Some((
+            "Invalid input data".to_string(),
+            validation::field_errors(e),
+        )),
+        CheckInError::InvalidPayload(e) => Some((e.to_string(), Vec::new())),
+        _ => None,
+    }
+}
+
+/// Quarantine entry for a check-in rejected at `received`
+pub fn entry(
+    received: DateTime<Utc>,
+    source_ip: Option<IpAddr>,
+    laptop_serial: Option<String>,
+    body: String,
+    error: &str,
+    errors: &[FieldError],
+) -> db::NewQuarantined {
+    db::NewQuarantined {
+        received_at_utc: clock_skew::format_utc(received),
+        source_ip: source_ip.map(|ip| ip.to_string()),
+        laptop_serial,
+        body,
+        error: error.to_string(),
+        errors_json: serde_json::to_string(errors).unwrap_or_else(|_| "[]".to_string()),
+    }
+}
+
+/// Keep rejected check-ins, unless the quarantine is disabled. A failure is logged
+/// and otherwise ignored, so it never changes the response.
+pub fn store(state: &AppState, settings: &Settings, entries: &[db::NewQuarantined]) {
+    let max_rows = settings.validation.quarantine_max_rows;
+    if max_rows == 0 || entries.is_empty() {
+        return;
+    }
+
+    let result = rusqlite::Connection::open(&state.db_path).and_then(|mut conn| {
+        let tx = conn.transaction()?;
+        for entry in entries {
+            db::insert_quarantined(&tx, entry, max_rows)?;
+        }
+        tx.commit()
+    });
+    if let Err(e) = result {
+        tracing::error!(error = ?e, "Failed to quarantine rejected check-in");
+    }
+}
+
+/// Counts from re-processing quarantined check-ins
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Reprocessed {
+    /// Now accepted and removed from the quarantine
+    pub stored: usize,
+    /// Still rejected; their errors are updated
+    pub failed: usize,
+}
+
+/// Run quarantined check-ins through the current validation again, oldest first.
+/// Each is judged as of its original receive time and stored with its original
+/// source address. Accepted entries leave the quarantine.
+pub fn reprocess(
+    conn: &mut rusqlite::Connection,
+    settings: &Settings,
+    mut rows: Vec<QuarantineRow>,
+) -> Result<Reprocessed, CheckInError> {
+    rows.sort_by_key(|row| row.id);
+    let now = Utc::now();
+    let key_cutoff = idempotency::cutoff(now, settings.idempotency_key_retention_hours);
+    let mut outcome = Reprocessed::default();
+
+    let tx = conn.transaction()?;
+    db::prune_idempotency_keys(&tx, &key_cutoff)?;
+    for row in rows {
+        let received = db::parse_instant(&row.received_at_utc).unwrap_or(now);
+        let item = serde_json::from_str(&row.body).map_err(|e| format!("invalid JSON: {e}"));
+
+        let failure = match handlers::prepare_batch_item(item, received, settings, row.source_ip) {
+            Ok((mut checkin, key)) => {
+                match handlers::store_prepared(&tx, &mut checkin, key.as_deref(), &key_cutoff)? {
+                    Stored::Inserted { .. } | Stored::Replayed => None,
+                    Stored::Conflict => Some((
+                        "checkin_id was already used for a different device".to_string(),
+                        Vec::new(),
+                    )),
+                }
+            }
+            Err(rejection) => Some((rejection.error, rejection.errors)),
+        };
+
+        match failure {
+            None => {
+                db::delete_quarantined(&tx, row.id)?;
+                outcome.stored += 1;
+            }
+            Some((error, errors)) => {
+                let errors_json = serde_json::to_string(&errors)?;
+                db::update_quarantined_error(&tx, row.id, &error, &errors_json)?;
+                outcome.failed += 1;
+            }
+        }
+    }
+    tx.commit()?;
+
+    tracing::info!(
+        stored = outcome.stored,
+        failed = outcome.failed,
+        "Re-processed quarantined check-ins"
+    );
+    Ok(outcome)
+}
+
+// ============== Admin Handlers ==============
+
+#[derive(Debug, Deserialize)]
+pub struct NoticeQuery {
+    stored: Option<usize>,
+    failed: Option<usize>,
+}
+
+/// GET /admin/quarantine - Rejected check-ins and rejection counts per source
+#[tracing::instrument(skip_all)]
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NoticeQuery>,
+) -> Result<QuarantineTemplate, (StatusCode, String)> {
+    let conn = open(&state)?;
+    let entries = db::get_quarantined(&conn).map_err(internal("query quarantine"))?;
+    let rejection_counts =
+        db::get_rejection_counts(&conn).map_err(internal("query rejection counts"))?;
+
+    let notice = match (query.stored, query.failed) {
+        (Some(stored), Some(failed)) => Some(format!(
+            "Re-processed {}: {stored} stored, {failed} still rejected",
+            stored + failed
+        )),
+        _ => None,
+    };
+
+    Ok(QuarantineTemplate {
+        entries,
+        max_rows: state.settings().validation.quarantine_max_rows,
+        rejection_counts,
+        notice,
+        csrf_token: state.csrf_token.as_str().to_string(),
+    })
+}
+
+/// POST /admin/quarantine/reprocess - Re-process every quarantined check-in
+#[tracing::instrument(skip_all)]
+pub async fn reprocess_all(
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let mut conn = open(&state)?;
+    let rows = db::get_quarantined(&conn).map_err(internal("query quarantine"))?;
+    let outcome = reprocess(&mut conn, &state.settings(), rows).map_err(reprocess_failed)?;
+    Ok(redirect(outcome))
+}
+
+/// POST /admin/quarantine/:id/reprocess - Re-process one quarantined check-in
+#[tracing::instrument(skip(state))]
+pub async fn reprocess_one(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let mut conn = open(&state)?;
+    let row = db::get_quarantined_by_id(&conn, id)
+        .map_err(internal("query quarantine"))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Quarantine entry not found: {id}"),
+            )
+        })?;
+    let outcome = reprocess(&mut conn, &state.settings(), vec![row]).map_err(reprocess_failed)?;
+    Ok(redirect(outcome))
+}
+
+/// POST /admin/quarantine/:id/delete - Discard a quarantined check-in
+#[tracing::instrument(skip(state))]
+pub async fn discard(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let conn = open(&state)?;
+    if !db::delete_quarantined(&conn, id).map_err(internal("delete quarantine entry"))? {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Quarantine entry not found: {id}"),
+        ));
+    }
+    tracing::info!(id, "Discarded quarantined check-in");
+    Ok(Redirect::to("/admin/quarantine"))
+}
+
+fn open(state: &AppState) -> Result<rusqlite::Connection, (StatusCode, String)> {
+    rusqlite::Connection::open(&state.db_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))
+}
+
+fn internal<E: std::fmt::Display>(what: &'static str) -> impl Fn(E) -> (StatusCode, String) {
+    move |e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{what}: {e}"))
+}
+
+fn reprocess_failed(e: CheckInError) -> (StatusCode, String) {
+    tracing::error!(error = ?e, "Failed to re-process quarantined check-ins");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "re-process quarantine failed".to_string(),
+    )
+}
+
+fn redirect(outcome: Reprocessed) -> Redirect {
+    Redirect::to(&format!(
+        "/admin/quarantine?stored={}&failed={}",
+        outcome.stored, outcome.failed
+    ))
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{client_ip, errors::CheckInError, AppState};
+
+/// Keys tracked per map before idle entries are swept out
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Which limit turned a check-in away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    SourceIp,
+    Serial,
+    MinInterval,
+    BodySize,
+}
+
+impl Limit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Limit::SourceIp => "source_ip",
+            Limit::Serial => "laptop_serial",
+            Limit::MinInterval => "min_interval",
+            Limit::BodySize => "body_size",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A check-in refused by a rate limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub limit: Limit,
+    /// How long the client should wait before trying again
+    pub retry_after: Duration,
+}
+
+/// In-memory check-in limits: token buckets per source IP and per `laptop_serial`,
+/// plus the time of each device's last accepted check-in.
+///
+/// State is per process and starts empty on restart, which is fine for protecting
+/// the database from a looping agent.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    per_ip: Mutex<HashMap<IpAddr, Bucket>>,
+    per_serial: Mutex<HashMap<String, Bucket>>,
+    last_accepted: Mutex<HashMap<String, Instant>>,
+    rejected: [AtomicU64; 4],
+}
+
+impl RateLimiter {
+    /// Take one request from `ip`'s allowance of `per_minute` (0 disables the limit)
+    pub fn check_ip(&self, ip: IpAddr, per_minute: u32) -> Result<(), Rejected> {
+        self.check_ip_at(ip, per_minute, Instant::now())
+    }
+
+    /// Apply the per-device limits to a check-in for `serial`. Call
+    /// [`record_accepted`](Self::record_accepted) once it has been stored.
+    pub fn check_device(
+        &self,
+        serial: &str,
+        per_minute: u32,
+        min_interval: Duration,
+    ) -> Result<(), Rejected> {
+        self.check_device_at(serial, per_minute, min_interval, Instant::now())
+    }
+
+    /// Start the minimum interval for `serial` from now
+    pub fn record_accepted(&self, serial: &str) {
+        let mut last = lock(&self.last_accepted);
+        if last.len() >= MAX_TRACKED_KEYS {
+            // Anything older than an hour is past any sensible minimum interval
+            let now = Instant::now();
+            last.retain(|_, at| now.duration_since(*at) < Duration::from_secs(3600));
+        }
+        last.insert(serial.to_string(), Instant::now());
+    }
+
+    /// Count a rejection and log it. `key` identifies the client or device.
+    pub fn reject(&self, limit: Limit, key: &str) {
+        let total = self.rejected[limit.index()].fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::warn!(
+            limit = limit.as_str(),
+            key,
+            rejected_total = total,
+            "Check-in rejected by limit"
+        );
+    }
+
+    /// Rejections counted for `limit` since startup
+    pub fn rejected(&self, limit: Limit) -> u64 {
+        self.rejected[limit.index()].load(Ordering::Relaxed)
+    }
+
+    fn check_ip_at(&self, ip: IpAddr, per_minute: u32, now: Instant) -> Result<(), Rejected> {
+        take(&self.per_ip, ip, per_minute, now).map_err(|retry_after| {
+            self.reject(Limit::SourceIp, &ip.to_string());
+            Rejected {
+                limit: Limit::SourceIp,
+                retry_after,
+            }
+        })
+    }
+
+    fn check_device_at(
+        &self,
+        serial: &str,
+        per_minute: u32,
+        min_interval: Duration,
+        now: Instant,
+    ) -> Result<(), Rejected> {
+        if !min_interval.is_zero() {
+            if let Some(at) = lock(&self.last_accepted).get(serial) {
+                let elapsed = now.saturating_duration_since(*at);
+                if elapsed < min_interval {
+                    self.reject(Limit::MinInterval, serial);
+                    return Err(Rejected {
+                        limit: Limit::MinInterval,
+                        retry_after: min_interval - elapsed,
+                    });
+                }
+            }
+        }
+
+        take(&self.per_serial, serial.to_string(), per_minute, now).map_err(|retry_after| {
+            self.reject(Limit::Serial, serial);
+            Rejected {
+                limit: Limit::Serial,
+                retry_after,
+            }
+        })
+    }
+}
+
+/// Token bucket holding up to a minute's allowance, refilled continuously
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Take a token for `key`, or return how long until one is available
+fn take<K: std::hash::Hash + Eq>(
+    buckets: &Mutex<HashMap<K, Bucket>>,
+    key: K,
+    per_minute: u32,
+    now: Instant,
+) -> Result<(), Duration> {
+    if per_minute == 0 {
+        return Ok(());
+    }
+    let capacity = f64::from(per_minute);
+    let per_sec = capacity / 60.0;
+
+    let mut buckets = lock(buckets);
+    if buckets.len() >= MAX_TRACKED_KEYS {
+        // A bucket idle for a minute is full again, the same as having none
+        buckets.retain(|_, b| now.saturating_duration_since(b.updated) < Duration::from_secs(60));
+    }
+
+    let bucket = buckets.entry(key).or_insert(Bucket {
+        tokens: capacity,
+        updated: now,
+    });
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Middleware for `/checkin`: applies the per-source-IP limit before the body is read,
+/// and counts bodies refused for exceeding `limits.max_body_kb`.
+pub async fn guard(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let settings = state.settings();
+    let client = client_ip::resolve(req.extensions(), req.headers(), &settings.trusted_proxies);
+
+    if let Some(ip) = client {
+        let per_minute = settings.limits.per_ip_per_minute;
+        if let Err(rejected) = state.limiter.check_ip(ip, per_minute) {
+            return CheckInError::RateLimited(rejected).into_response();
+        }
+    }
+
+    let response = next.run(req).await;
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        let key = client.map(|ip| ip.to_string()).unwrap_or_default();
+        state.limiter.reject(Limit::BodySize, &key);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_limit_allows_burst_then_refills() {
+        let limiter = RateLimiter::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_ip_at(ip, 3, start).is_ok());
+        }
+        let rejected = limiter.check_ip_at(ip, 3, start).unwrap_err();
+        assert_eq!(rejected.limit, Limit::SourceIp);
+        assert_eq!(rejected.retry_after, Duration::from_secs(20));
+
+        // One token back every 20 seconds at 3 per minute
+        assert!(limiter
+            .check_ip_at(ip, 3, start + Duration::from_secs(20))
+            .is_ok());
+        assert_eq!(limiter.rejected(Limit::SourceIp), 1);
+    }
+
+    #[test]
+    fn test_limits_are_per_key() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+
+        assert!(limiter
+            .check_device_at("SN1", 1, Duration::ZERO, now)
+            .is_ok());
+        assert!(limiter
+            .check_device_at("SN1", 1, Duration::ZERO, now)
+            .is_err());
+        assert!(limiter
+            .check_device_at("SN2", 1, Duration::ZERO, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_zero_disables_limit() {
+        let limiter = RateLimiter::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert!(limiter.check_ip_at(ip, 0, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_min_interval_between_accepted_checkins() {
+        let limiter = RateLimiter::default();
+        let interval = Duration::from_secs(300);
+
+        assert!(limiter.check_device("SN1", 0, interval).is_ok());
+        limiter.record_accepted("SN1");
+
+        let rejected = limiter.check_device("SN1", 0, interval).unwrap_err();
+        assert_eq!(rejected.limit, Limit::MinInterval);
+        assert!(rejected.retry_after <= interval);
+        assert_eq!(limiter.rejected(Limit::MinInterval), 1);
+    }
+}
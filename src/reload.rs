@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::{config, logging::LogHandle, AppState, Settings};
+
+/// Certificate and key files backing a running HTTPS listener
+pub struct TlsFiles {
+    pub config: RustlsConfig,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Applies configuration and certificate changes to a running server without
+/// dropping connections. Triggered by SIGHUP (Unix) or by the watched files changing.
+pub struct Reloader {
+    pub state: Arc<AppState>,
+    pub config_path: PathBuf,
+    /// `--debug` / `INVENTORY_DEBUG`, which keep debug mode on across reloads
+    pub force_debug: bool,
+    /// Config the server was started with, to spot changes that need a restart
+    pub startup_config: config::Config,
+    pub log: Option<Arc<LogHandle>>,
+    pub tls: Option<TlsFiles>,
+}
+
+impl Reloader {
+    /// Re-read config.toml and swap in its reloadable settings.
+    /// On error the current settings stay in effect.
+    pub fn reload_config(&self) -> Result<()> {
+        let cfg = config::read_config(&self.config_path)?;
+        let settings = Settings::from_config(&cfg, self.force_debug);
+
+        if let Some(log) = &self.log {
+            log.set_debug_mode(settings.debug_mode)?;
+        }
+
+        let restart_required = restart_only_changes(&self.startup_config, &cfg);
+        if !restart_required.is_empty() {
+            tracing::warn!(
+                fields = ?restart_required,
+                "Config changes ignored until the server is restarted"
+            );
+        }
+
+        tracing::info!(
+            debug_mode = settings.debug_mode,
+            redact_user_names = settings.redact_user_names,
+            "Configuration reloaded"
+        );
+        self.state.replace_settings(settings);
+
+        Ok(())
+    }
+
+    /// Load the certificate and key from disk into the live TLS config. New handshakes
+    /// use the new certificate; established connections are unaffected.
+    pub async fn reload_tls(&self) -> Result<()> {
+        let Some(tls) = &self.tls else {
+            return Ok(());
+        };
+
+        tls.config
+            .reload_from_pem_file(&tls.cert_path, &tls.key_path)
+            .await
+            .context("reload tls cert/key")?;
+
+        tracing::info!(cert = %tls.cert_path.display(), "TLS certificate reloaded");
+        Ok(())
+    }
+
+    /// Reload on SIGHUP, and whenever config.toml or the certificate/key files change
+    /// (checked every `interval`; a zero interval disables file watching).
+    pub async fn watch(self, interval: Duration) {
+        let mut hangup = Hangup::new();
+        let mut config_seen = Fingerprint::of(&self.config_path);
+        let mut tls_seen = self.tls_fingerprint();
+
+        let mut ticker = (!interval.is_zero()).then(|| {
+            let mut t = tokio::time::interval(interval);
+            t.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            t
+        });
+
+        loop {
+            let forced = tokio::select! {
+                _ = hangup.recv() => true,
+                _ = tick(&mut ticker) => false,
+            };
+            if forced {
+                tracing::info!("SIGHUP received, reloading configuration and certificates");
+            }
+
+            let config_now = Fingerprint::of(&self.config_path);
+            if forced || config_now != config_seen {
+                match self.reload_config() {
+                    Ok(()) => config_seen = config_now,
+                    Err(e) => tracing::error!(error = %format!("{e:#}"), "Config reload failed"),
+                }
+            }
+
+            // Only remember the files once they load, so a renewal caught halfway
+            // (new cert, old key) is retried on the next tick
+            let tls_now = self.tls_fingerprint();
+            if forced || tls_now != tls_seen {
+                match self.reload_tls().await {
+                    Ok(()) => tls_seen = tls_now,
+                    Err(e) => tracing::error!(error = %format!("{e:#}"), "TLS reload failed"),
+                }
+            }
+        }
+    }
+
+    fn tls_fingerprint(&self) -> Option<(Fingerprint, Fingerprint)> {
+        self.tls
+            .as_ref()
+            .map(|t| (Fingerprint::of(&t.cert_path), Fingerprint::of(&t.key_path)))
+    }
+}
+
+/// Startup-only fields whose values differ between `old` and `new`
+fn restart_only_changes(old: &config::Config, new: &config::Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.bind != new.bind {
+        changed.push("bind");
+    }
+    if old.listeners != new.listeners {
+        changed.push("listeners");
+    }
+    if old.db_path != new.db_path {
+        changed.push("db_path");
+    }
+    if old.tls_cert != new.tls_cert {
+        changed.push("tls_cert");
+    }
+    if old.tls_key != new.tls_key {
+        changed.push("tls_key");
+    }
+    if old.limits.max_body_kb != new.limits.max_body_kb {
+        changed.push("limits.max_body_kb");
+    }
+    if old.limits.max_batch_body_kb != new.limits.max_batch_body_kb {
+        changed.push("limits.max_batch_body_kb");
+    }
+    if old.shutdown_timeout_secs != new.shutdown_timeout_secs {
+        changed.push("shutdown_timeout_secs");
+    }
+    if old.reload_interval_secs != new.reload_interval_secs {
+        changed.push("reload_interval_secs");
+    }
+    // Log output and span export are set up once; only redaction is reloaded
+    let output = |logging: &config::LoggingConfig| config::LoggingConfig {
+        redact_user_names: true,
+        ..logging.clone()
+    };
+    if output(&old.logging) != output(&new.logging) {
+        changed.push("logging");
+    }
+    if old.telemetry != new.telemetry {
+        changed.push("telemetry");
+    }
+    changed
+}
+
+async fn tick(ticker: &mut Option<tokio::time::Interval>) {
+    match ticker {
+        Some(t) => {
+            t.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Cheap change detection: modification time plus size. `None` when the file is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint(Option<(SystemTime, u64)>);
+
+impl Fingerprint {
+    fn of(path: &Path) -> Self {
+        Self(
+            std::fs::metadata(path)
+                .ok()
+                .and_then(|m| Some((m.modified().ok()?, m.len()))),
+        )
+    }
+}
+
+/// SIGHUP listener; never fires on platforms without it
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .inspect_err(|e| tracing::error!(error = %e, "Failed to listen for SIGHUP"))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        {
+            Self {}
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_only_changes() {
+        let old = config::Config::default();
+        let mut new = config::Config {
+            debug: true,
+            ..Default::default()
+        };
+        assert!(restart_only_changes(&old, &new).is_empty());
+
+        new.bind = "127.0.0.1:9000".to_string();
+        new.tls_cert = Some("new.pem".to_string());
+        assert_eq!(restart_only_changes(&old, &new), vec!["bind", "tls_cert"]);
+    }
+
+    #[test]
+    fn test_restart_only_changes_logging_and_telemetry() {
+        let old = config::Config::default();
+        let mut new = config::Config::default();
+        new.logging.redact_user_names = false;
+        assert!(restart_only_changes(&old, &new).is_empty());
+
+        new.logging.format = config::LogFormat::Json;
+        new.logging.directory = Some("logs".to_string());
+        new.telemetry.otlp_endpoint = Some("http://localhost:4318/v1/traces".to_string());
+        assert_eq!(
+            restart_only_changes(&old, &new),
+            vec!["logging", "telemetry"]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cert.pem");
+
+        let missing = Fingerprint::of(&path);
+        assert_eq!(missing, Fingerprint(None));
+
+        std::fs::write(&path, "first").unwrap();
+        let first = Fingerprint::of(&path);
+        assert_ne!(first, missing);
+
+        std::fs::write(&path, "second, longer").unwrap();
+        assert_ne!(Fingerprint::of(&path), first);
+    }
+}
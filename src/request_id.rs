@@ -0,0 +1,93 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+
+/// Header used to carry the request ID in both directions
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied ID that will be honored; anything longer is replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// ID assigned to the current request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The ID of the request being handled by the current task, if any.
+///
+/// Lets error responses deep in the handler stack echo the ID without threading it
+/// through every signature.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Middleware that honors an incoming `X-Request-Id` or generates a new one, makes it
+/// available to the rest of the stack, and echoes it on the response.
+pub async fn propagate(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_acceptable(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let request_id = RequestId(id.clone());
+    req.extensions_mut().insert(request_id.clone());
+
+    let mut response = CURRENT.scope(request_id, next.run(req)).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), value);
+    }
+
+    response
+}
+
+/// Client IDs are echoed into logs and headers, so keep them short and printable
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Span factory for `TraceLayer` that tags every request span with its ID
+pub fn make_span<B>(req: &axum::http::Request<B>) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or("-");
+
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        request_id = %request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_acceptable() {
+        assert!(is_acceptable("3f2b8c1e-agent-retry-2"));
+        assert!(!is_acceptable(""));
+        assert!(!is_acceptable("has space"));
+        assert!(!is_acceptable(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn test_current_outside_request_is_none() {
+        assert_eq!(current(), None);
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    middleware,
+    response::Redirect,
+    routing::{get, post},
+    Router,
+};
+use tower_http::{
+    compression::CompressionLayer, decompression::RequestDecompressionLayer, trace::TraceLayer,
+};
+
+use crate::{
+    csrf, export, handlers, health, identity, lifecycle, metadata, network, quarantine, rate_limit,
+    request_id, search, snapshot, users, AppState,
+};
+
+/// Build the application router shared by the server binary and integration tests.
+///
+/// Check-in bodies may be sent with `Content-Encoding: gzip`, `deflate` or `zstd`. They
+/// are decompressed before the body limit is applied, so the limit caps the
+/// decompressed size and a small compressed bomb is refused with 413.
+pub fn build_router(state: Arc<AppState>) -> Router {
+    let limits = state.settings().limits.clone();
+    let max_body_bytes = limits.max_body_kb.saturating_mul(1024);
+    let max_batch_body_bytes = limits.max_batch_body_kb.saturating_mul(1024);
+
+    // Forms that change data, which must carry the CSRF token
+    let forms = Router::new()
+        .route("/device/:serial/tags", post(metadata::add_tag))
+        .route("/device/:serial/tags/delete", post(metadata::remove_tag))
+        .route("/device/:serial/notes", post(metadata::save_notes))
+        .route("/device/:serial/fields", post(metadata::save_fields))
+        .route("/device/:serial/lifecycle", post(lifecycle::change_state))
+        .route(
+            "/admin/quarantine/reprocess",
+            post(quarantine::reprocess_all),
+        )
+        .route(
+            "/admin/quarantine/:id/reprocess",
+            post(quarantine::reprocess_one),
+        )
+        .route("/admin/quarantine/:id/delete", post(quarantine::discard))
+        .route("/admin/identity/merge", post(identity::merge))
+        .route("/admin/identity/split", post(identity::split))
+        .route(
+            "/admin/identity/collisions/dismiss",
+            post(identity::dismiss),
+        )
+        .route(
+            "/admin/identity/remaps/delete",
+            post(identity::remove_remap),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), csrf::guard));
+
+    Router::new()
+        .route("/", get(handlers::index))
+        .route("/device/:serial", get(handlers::device_detail))
+        .route("/users", get(users::list))
+        .route("/user/:name", get(users::page))
+        .route("/search", get(search::page))
+        .route("/api/v1/search", get(search::api))
+        .route("/network", get(network::page))
+        .route("/api/v1/network", get(network::api))
+        .route("/api/v1/devices", get(snapshot::list))
+        .route("/api/v1/devices/:serial", get(snapshot::device))
+        .route("/api/v1/schema", get(handlers::checkin_schema))
+        .route("/export/devices.csv", get(export::devices_csv))
+        .route("/admin/quarantine", get(quarantine::page))
+        .route("/admin/identity", get(identity::page))
+        .merge(forms)
+        .route(
+            "/checkin",
+            post(handlers::checkin)
+                .layer(RequestDecompressionLayer::new())
+                .handle_error(decompression_failed)
+                .layer(DefaultBodyLimit::max(max_body_bytes))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit::guard,
+                )),
+        )
+        .route(
+            "/checkin/batch",
+            post(handlers::checkin_batch)
+                .layer(RequestDecompressionLayer::new())
+                .handle_error(decompression_failed)
+                .layer(DefaultBodyLimit::max(max_batch_body_bytes))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit::guard,
+                )),
+        )
+        // Pages and JSON responses, for clients that send Accept-Encoding
+        .layer(CompressionLayer::new())
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+        // Probes are merged after the trace layer so frequent polling doesn't flood the logs
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        // Outermost, so the ID exists before the trace span is created
+        .layer(middleware::from_fn(request_id::propagate))
+        .with_state(state)
+}
+
+/// The decompression layer only fails if the handler does, which it can't; map the
+/// boxed error type back to a response regardless
+async fn decompression_failed(err: axum::BoxError) -> (StatusCode, String) {
+    tracing::error!(error = %err, "Request decompression failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+/// Router for listeners that only answer health probes, e.g. a plain-HTTP port
+/// polled by a load balancer
+pub fn health_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .layer(middleware::from_fn(request_id::propagate))
+        .with_state(state)
+}
+
+/// Router that permanently redirects every request to the same host and path on
+/// the HTTPS listener at `https_port`
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new()
+        .fallback(redirect_to_https)
+        .with_state(https_port)
+}
+
+async fn redirect_to_https(
+    State(https_port): State<u16>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Result<Redirect, StatusCode> {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(strip_port)
+        .filter(|h| !h.is_empty())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let location = if https_port == 443 {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}:{https_port}{path}")
+    };
+
+    Ok(Redirect::permanent(&location))
+}
+
+/// `host:port` -> `host`, leaving bracketed IPv6 literals intact
+fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.find(']') {
+        return &host[..=end];
+    }
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(
+            strip_port("inventory.contoso.com:8080"),
+            "inventory.contoso.com"
+        );
+        assert_eq!(strip_port("inventory.contoso.com"), "inventory.contoso.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+}
@@ -0,0 +1,300 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    config::{Charset, HostnamePolicy, ValidationConfig},
+    hostname,
+    models::CheckIn,
+};
+
+/// Payload version produced by current agents and described by [`json_schema`]
+pub const CURRENT_VERSION: u64 = 1;
+
+/// Oldest payload version still accepted
+pub const MIN_VERSION: u64 = 1;
+
+/// Field naming the payload version; absent means version 1
+pub const VERSION_FIELD: &str = "schema_version";
+
+/// Rewrites of older payload shapes, in order. Entry `i` upgrades a payload from
+/// version `MIN_VERSION + i` to the next one, so it can be read as the current model.
+type Upgrade = fn(&mut Map<String, Value>);
+const UPGRADES: &[Upgrade] = &[];
+
+/// Why a payload couldn't be read as a [`CheckIn`]
+#[derive(Debug)]
+pub enum PayloadError {
+    /// `schema_version` is not a version this server accepts, as sent
+    UnsupportedVersion(Value),
+    /// Doesn't match the shape of its version
+    Invalid(serde_json::Error),
+}
+
+impl PayloadError {
+    /// Short code for counting rejections
+    pub fn code(&self) -> &'static str {
+        match self {
+            PayloadError::UnsupportedVersion(_) => "unsupported_schema_version",
+            PayloadError::Invalid(_) => "invalid_payload",
+        }
+    }
+}
+
+impl std::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadError::UnsupportedVersion(version) => write!(
+                f,
+                "{VERSION_FIELD} {version} is not supported (supported: {})",
+                supported_versions()
+            ),
+            PayloadError::Invalid(e) => write!(f, "invalid check-in: {e}"),
+        }
+    }
+}
+
+/// Supported versions for messages, e.g. `1` or `1-3`
+pub fn supported_versions() -> String {
+    if MIN_VERSION == CURRENT_VERSION {
+        CURRENT_VERSION.to_string()
+    } else {
+        format!("{MIN_VERSION}-{CURRENT_VERSION}")
+    }
+}
+
+/// Read a check-in of any supported version, upgrading older shapes first
+pub fn parse_checkin(value: Value) -> Result<CheckIn, PayloadError> {
+    parse_with(value, MIN_VERSION, UPGRADES)
+}
+
+/// [`parse_checkin`] accepting versions `min` to `min + upgrades.len()`
+fn parse_with(value: Value, min: u64, upgrades: &[Upgrade]) -> Result<CheckIn, PayloadError> {
+    let Value::Object(mut fields) = value else {
+        return serde_json::from_value(value).map_err(PayloadError::Invalid);
+    };
+
+    let current = min + upgrades.len() as u64;
+    let version = match fields.remove(VERSION_FIELD) {
+        None | Some(Value::Null) => 1,
+        Some(v) => v.as_u64().ok_or(PayloadError::UnsupportedVersion(v))?,
+    };
+    // Unversioned payloads too, once version 1 is no longer accepted
+    if !(min..=current).contains(&version) {
+        return Err(PayloadError::UnsupportedVersion(json!(version)));
+    }
+
+    for upgrade in &upgrades[(version - min) as usize..] {
+        upgrade(&mut fields);
+    }
+
+    serde_json::from_value(Value::Object(fields)).map_err(PayloadError::Invalid)
+}
+
+/// JSON Schema (draft 2020-12) of the current check-in payload, as served at
+/// `/api/v1/schema`. Keep in step with the validation rules on [`CheckIn`] and the
+/// configured hostname policy and charset.
+pub fn json_schema(rules: &ValidationConfig) -> Value {
+    let printable = "^[ -~]*$";
+    // Must also be NFC-normalized in Unicode mode, which a pattern can't express
+    let text = match rules.charset {
+        Charset::Ascii => printable,
+        Charset::Unicode => {
+            r"^[^\u0000-\u001F\u007F-\u009F\u061C\u200E\u200F\u202A-\u202E\u2066-\u2069]*$"
+        }
+    };
+    // At most 63 characters each
+    let label = "[A-Za-z0-9]([A-Za-z0-9-]{0,61}[A-Za-z0-9])?";
+    let computer_name = "[A-Za-z0-9]([A-Za-z0-9_-]{0,61}[A-Za-z0-9])?";
+    let (hostname_pattern, hostname_max) = match rules.hostname {
+        HostnamePolicy::Netbios => (format!("^{computer_name}$"), hostname::MAX_LABEL_LEN),
+        HostnamePolicy::Label => (format!("^{label}$"), hostname::MAX_LABEL_LEN),
+        HostnamePolicy::Fqdn => (
+            format!(r"^{computer_name}(\.{label})*$"),
+            hostname::MAX_FQDN_LEN,
+        ),
+    };
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "CheckIn",
+        "description": "Inventory snapshot sent by an agent to POST /checkin",
+        "type": "object",
+        "required": ["hostname", "ip_address", "laptop_serial", "drives", "timestamp_utc"],
+        "properties": {
+            VERSION_FIELD: {
+                "description": format!(
+                    "Payload version; older supported versions are upgraded on receipt (supported: {})",
+                    supported_versions()
+                ),
+                "type": "integer",
+                "minimum": MIN_VERSION,
+                "maximum": CURRENT_VERSION,
+                "default": 1
+            },
+            "hostname": {
+                "type": "string",
+                "minLength": 1,
+                "maxLength": hostname_max,
+                "pattern": hostname_pattern
+            },
+            "ip_address": {
+                "description": "Address the agent reports for itself",
+                "type": "string",
+                "anyOf": [{ "format": "ipv4" }, { "format": "ipv6" }]
+            },
+            "logged_in_user": {
+                "type": ["string", "null"],
+                "maxLength": 512,
+                "pattern": text
+            },
+            "laptop_serial": {
+                "type": "string",
+                "minLength": 1,
+                "maxLength": 128,
+                "pattern": printable
+            },
+            "drives": {
+                "type": "array",
+                "maxItems": 32,
+                "items": { "$ref": "#/$defs/Drive" }
+            },
+            "timestamp_utc": {
+                "description": "Agent time of the snapshot, RFC 3339",
+                "type": "string",
+                "format": "date-time"
+            },
+            "checkin_id": {
+                "description": "Agent-generated ID; a retry with the same ID is not stored twice",
+                "type": "string",
+                "minLength": 1,
+                "maxLength": crate::idempotency::MAX_KEY_LEN,
+                "pattern": "^[!-~]+$"
+            }
+        },
+        "$defs": {
+            "Drive": {
+                "type": "object",
+                "required": ["model", "device_id"],
+                "properties": {
+                    "model": {
+                        "type": "string",
+                        "minLength": 1,
+                        "maxLength": 256,
+                        "pattern": text
+                    },
+                    "serial_number": {
+                        "type": ["string", "null"],
+                        "maxLength": 256,
+                        "pattern": text
+                    },
+                    "device_id": {
+                        "type": "string",
+                        "minLength": 1,
+                        "maxLength": 256,
+                        "pattern": printable
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Value {
+        json!({
+            "hostname": "LAPTOP-001",
+            "ip_address": "10.0.0.1",
+            "laptop_serial": "SN1",
+            "drives": [],
+            "timestamp_utc": "2024-01-15T10:30:00Z"
+        })
+    }
+
+    #[test]
+    fn test_upgrades_cover_every_older_version() {
+        assert_eq!(MIN_VERSION + UPGRADES.len() as u64, CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_missing_version_is_version_one() {
+        let checkin = parse_checkin(payload()).unwrap();
+        assert_eq!(checkin.laptop_serial, "SN1");
+
+        let mut explicit = payload();
+        explicit[VERSION_FIELD] = json!(1);
+        assert!(parse_checkin(explicit).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_versions_are_named() {
+        for version in [json!(0), json!(CURRENT_VERSION + 1), json!("1"), json!(1.5)] {
+            let mut body = payload();
+            body[VERSION_FIELD] = version.clone();
+            match parse_checkin(body) {
+                Err(e @ PayloadError::UnsupportedVersion(_)) => {
+                    assert!(e.to_string().contains(&version.to_string()), "{e}");
+                }
+                other => panic!("expected unsupported version, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_older_versions_are_upgraded_in_order() {
+        // Version 1 called it `host`, version 2 `host_name`
+        fn v1_to_v2(fields: &mut Map<String, Value>) {
+            let host = fields.remove("host").unwrap();
+            fields.insert("host_name".to_string(), host);
+        }
+        fn v2_to_v3(fields: &mut Map<String, Value>) {
+            let host = fields.remove("host_name").unwrap();
+            fields.insert("hostname".to_string(), host);
+        }
+        let upgrades: &[Upgrade] = &[v1_to_v2, v2_to_v3];
+
+        let mut v1 = payload();
+        let host = v1.as_object_mut().unwrap().remove("hostname").unwrap();
+        v1["host"] = host.clone();
+        assert_eq!(parse_with(v1, 1, upgrades).unwrap().hostname, "LAPTOP-001");
+
+        let mut v2 = payload();
+        v2.as_object_mut().unwrap().remove("hostname");
+        v2["host_name"] = host;
+        v2[VERSION_FIELD] = json!(2);
+        assert_eq!(parse_with(v2, 1, upgrades).unwrap().hostname, "LAPTOP-001");
+    }
+
+    #[test]
+    fn test_unversioned_payload_rejected_once_version_one_is_dropped() {
+        fn v2_to_v3(_: &mut Map<String, Value>) {}
+        let upgrades: &[Upgrade] = &[v2_to_v3];
+
+        match parse_with(payload(), 2, upgrades) {
+            Err(e @ PayloadError::UnsupportedVersion(_)) => {
+                assert_eq!(e.code(), "unsupported_schema_version");
+                assert!(e.to_string().starts_with("schema_version 1 "), "{e}");
+            }
+            other => panic!("expected unsupported version, got {other:?}"),
+        }
+        let mut v2 = payload();
+        v2[VERSION_FIELD] = json!(2);
+        assert!(parse_with(v2, 2, upgrades).is_ok());
+    }
+
+    #[test]
+    fn test_schema_lists_every_field() {
+        let checkin = parse_checkin(payload()).unwrap();
+        let serialized = serde_json::to_value(&checkin).unwrap();
+        let schema = json_schema(&ValidationConfig::default());
+        for field in serialized.as_object().unwrap().keys() {
+            assert!(
+                schema["properties"].get(field).is_some(),
+                "{field} missing from schema"
+            );
+        }
+        for field in schema["required"].as_array().unwrap() {
+            assert!(serialized.get(field.as_str().unwrap()).is_some());
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{db, errors::ErrorBody, models::SearchRow, request_id, AppState};
+
+/// Longest query accepted, in characters
+pub const MAX_QUERY_LEN: usize = 256;
+
+/// Words of a query used; the rest are ignored
+const MAX_TERMS: usize = 16;
+
+/// Devices returned when no `limit` is given, and the most that can be asked for
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 500;
+
+/// Markers around matched text in indexed values. Validation keeps control
+/// characters out of stored check-ins, so they can't occur in the text itself.
+const MATCH_START: &str = "\u{2}";
+const MATCH_END: &str = "\u{3}";
+
+/// Build an FTS5 query from what a user typed: every word must match, as a prefix
+/// of a token in any field. Words are quoted, so FTS5 operators are taken literally.
+/// `None` when there's nothing to search for.
+pub fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .take(MAX_TERMS)
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Part of a field value, matched or not
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    pub matched: bool,
+}
+
+/// Split a value marked by [`db::search_checkins`] into plain and matched parts
+fn segments(marked: &str) -> Vec<Segment> {
+    let mut out = Vec::new();
+    let mut rest = marked;
+    while let Some(start) = rest.find(MATCH_START) {
+        if start > 0 {
+            out.push(Segment {
+                text: rest[..start].to_string(),
+                matched: false,
+            });
+        }
+        rest = &rest[start + MATCH_START.len()..];
+        let end = rest.find(MATCH_END).unwrap_or(rest.len());
+        out.push(Segment {
+            text: rest[..end].to_string(),
+            matched: true,
+        });
+        rest = rest.get(end + MATCH_END.len()..).unwrap_or("");
+    }
+    if !rest.is_empty() {
+        out.push(Segment {
+            text: rest.to_string(),
+            matched: false,
+        });
+    }
+    out
+}
+
+/// The value with its markers removed
+fn plain(segments: &[Segment]) -> String {
+    segments.iter().map(|s| s.text.as_str()).collect()
+}
+
+/// The value as HTML, with matched parts in `<mark>` and everything escaped
+fn html(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in segments {
+        let text = escape_html(&segment.text);
+        if segment.matched {
+            out.push_str("<mark>");
+            out.push_str(&text);
+            out.push_str("</mark>");
+        } else {
+            out.push_str(&text);
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#x27;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A field of a matching check-in
+#[derive(Debug, Clone)]
+pub struct Field {
+    /// Name in the JSON response, e.g. `drive_serials`
+    pub name: &'static str,
+    /// Heading on the search page
+    pub label: &'static str,
+    pub segments: Vec<Segment>,
+}
+
+impl Field {
+    fn new(name: &'static str, label: &'static str, marked: &str) -> Self {
+        Self {
+            name,
+            label,
+            segments: segments(marked),
+        }
+    }
+
+    pub fn matched(&self) -> bool {
+        self.segments.iter().any(|s| s.matched)
+    }
+}
+
+/// A device with matching check-ins
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub laptop_serial: String,
+    /// Every field of the latest matching check-in; see [`Field::matched`]
+    pub fields: Vec<Field>,
+    pub matching_checkins: i64,
+    pub first_match_utc: String,
+    pub last_match_utc: String,
+    pub current: bool,
+}
+
+impl SearchResult {
+    fn from_row(row: SearchRow) -> Self {
+        let mut fields = vec![
+            Field::new("hostname", "Hostname", &row.hostname),
+            Field::new("laptop_serial", "Serial", &row.laptop_serial),
+        ];
+        let optional = [
+            ("logged_in_user", "User", row.logged_in_user),
+            ("addresses", "IP addresses", Some(row.addresses)),
+            ("drive_models", "Drive models", row.drive_models),
+            ("drive_serials", "Drive serials", row.drive_serials),
+        ];
+        for (name, label, value) in optional {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                fields.push(Field::new(name, label, &value));
+            }
+        }
+
+        Self {
+            laptop_serial: row.serial,
+            fields,
+            matching_checkins: row.matching_checkins,
+            first_match_utc: row.first_match_utc,
+            last_match_utc: row.last_match_utc,
+            current: row.current,
+        }
+    }
+
+    /// Hostname of the matching check-in
+    pub fn hostname(&self) -> &Field {
+        &self.fields[0]
+    }
+
+    /// Fields other than the hostname and serial that contain a match
+    pub fn other_matches(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().skip(2).filter(|f| f.matched())
+    }
+}
+
+/// Run a search typed by a user; empty when there's nothing to search for
+pub fn search(
+    conn: &rusqlite::Connection,
+    q: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let Some(query) = fts_query(q) else {
+        return Ok(Vec::new());
+    };
+    let rows = db::search_checkins(conn, &query, MATCH_START, MATCH_END, limit)?;
+    Ok(rows.into_iter().map(SearchResult::from_row).collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+impl SearchQuery {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    fn too_long(&self) -> bool {
+        self.q.chars().count() > MAX_QUERY_LEN
+    }
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    pub q: String,
+    pub results: Vec<SearchResult>,
+    /// Whether more devices matched than are shown
+    pub truncated: bool,
+}
+
+/// GET /search?q= - Devices whose current or past check-ins match a query
+#[tracing::instrument(skip(state))]
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<SearchTemplate, (StatusCode, String)> {
+    if query.too_long() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Search query is longer than {MAX_QUERY_LEN} characters"),
+        ));
+    }
+    let (results, truncated) = run(&state, &query).map_err(|e| {
+        tracing::error!(error = ?e, "Search failed");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "search failed".to_string(),
+        )
+    })?;
+    Ok(SearchTemplate {
+        q: query.q,
+        results,
+        truncated,
+    })
+}
+
+/// One matching field in the JSON response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiMatch {
+    pub field: String,
+    pub value: String,
+    /// `value` as HTML, with matched text in `<mark>`
+    pub highlighted: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiResult {
+    pub laptop_serial: String,
+    pub hostname: String,
+    pub current: bool,
+    pub matching_checkins: i64,
+    pub first_match_utc: String,
+    pub last_match_utc: String,
+    pub matches: Vec<ApiMatch>,
+}
+
+/// Response body for `/api/v1/search`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub truncated: bool,
+    pub results: Vec<ApiResult>,
+}
+
+/// GET /api/v1/search?q= - Search results as JSON
+#[tracing::instrument(skip(state))]
+pub async fn api(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, Response> {
+    if query.q.trim().is_empty() {
+        return Err(bad_request("Missing search query", None));
+    }
+    if query.too_long() {
+        return Err(bad_request(
+            "Search query too long",
+            Some(format!("at most {MAX_QUERY_LEN} characters")),
+        ));
+    }
+
+    let (results, truncated) = run(&state, &query).map_err(|e| {
+        tracing::error!(error = ?e, "Search failed");
+        let body = ErrorBody {
+            error: "Internal server error",
+            detail: None,
+            errors: Vec::new(),
+            request_id: request_id::current(),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+    })?;
+
+    let results = results
+        .into_iter()
+        .map(|result| ApiResult {
+            hostname: plain(&result.hostname().segments),
+            matches: result
+                .fields
+                .iter()
+                .filter(|f| f.matched())
+                .map(|f| ApiMatch {
+                    field: f.name.to_string(),
+                    value: plain(&f.segments),
+                    highlighted: html(&f.segments),
+                })
+                .collect(),
+            laptop_serial: result.laptop_serial,
+            current: result.current,
+            matching_checkins: result.matching_checkins,
+            first_match_utc: result.first_match_utc,
+            last_match_utc: result.last_match_utc,
+        })
+        .collect();
+
+    Ok(Json(SearchResponse {
+        query: query.q,
+        truncated,
+        results,
+    }))
+}
+
+/// Results for `query`, and whether there were more than its limit
+fn run(state: &AppState, query: &SearchQuery) -> anyhow::Result<(Vec<SearchResult>, bool)> {
+    let conn = rusqlite::Connection::open(&state.db_path)?;
+    let limit = query.limit();
+    let mut results = search(&conn, &query.q, limit + 1)?;
+    let truncated = results.len() > limit;
+    results.truncate(limit);
+    Ok((results, truncated))
+}
+
+fn bad_request(error: &'static str, detail: Option<String>) -> Response {
+    let body = ErrorBody {
+        error,
+        detail,
+        errors: Vec::new(),
+        request_id: request_id::current(),
+    };
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_query_quotes_every_word() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("jdoe"), Some(r#""jdoe"*"#.to_string()));
+        assert_eq!(
+            fts_query(r#"S4EV-NX OR "x NEAR(a b)"#),
+            Some(r#""S4EV-NX"* "OR"* """x"* "NEAR(a"* "b)"*"#.to_string())
+        );
+        let many = "a ".repeat(MAX_TERMS + 5);
+        assert_eq!(fts_query(&many).unwrap().matches('*').count(), MAX_TERMS);
+    }
+
+    #[test]
+    fn test_segments_and_html() {
+        let marked = format!("CONTOSO\\{MATCH_START}José{MATCH_END} <x>");
+        let parts = segments(&marked);
+        assert_eq!(
+            parts,
+            [
+                Segment {
+                    text: "CONTOSO\\".to_string(),
+                    matched: false
+                },
+                Segment {
+                    text: "José".to_string(),
+                    matched: true
+                },
+                Segment {
+                    text: " <x>".to_string(),
+                    matched: false
+                },
+            ]
+        );
+        assert_eq!(plain(&parts), "CONTOSO\\José <x>");
+        assert_eq!(html(&parts), "CONTOSO\\<mark>José</mark> &lt;x&gt;");
+        assert!(segments("").is_empty());
+    }
+}
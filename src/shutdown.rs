@@ -0,0 +1,90 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum_server::Handle;
+
+/// Resolves when the process is asked to stop: Ctrl+C everywhere, SIGTERM on Unix,
+/// and console close / system shutdown on Windows (what service wrappers send).
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(windows)]
+    let terminate = async {
+        use tokio::signal::windows::{ctrl_close, ctrl_shutdown};
+        match (ctrl_close(), ctrl_shutdown()) {
+            (Ok(mut close), Ok(mut shutdown)) => {
+                tokio::select! {
+                    _ = close.recv() => {}
+                    _ = shutdown.recv() => {}
+                }
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::error!(error = %e, "Failed to listen for console close events");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Once `signal` resolves, stop accepting connections on `handle` and give in-flight
+/// requests up to `timeout` to finish before the remaining connections are closed.
+///
+/// Each check-in is written in one synchronous transaction with no await points, so a
+/// request is never cut off halfway through its write: it either committed before the
+/// deadline or never started.
+pub async fn drain_on<F>(signal: F, handle: Handle, timeout: Duration)
+where
+    F: Future<Output = ()>,
+{
+    signal.await;
+    tracing::info!(
+        timeout_secs = timeout.as_secs(),
+        connections = handle.connection_count(),
+        "Shutdown requested, draining in-flight requests"
+    );
+    handle.graceful_shutdown(Some(timeout));
+}
+
+/// Final database housekeeping after the listeners have stopped: fold the WAL back into
+/// the main database file so the next start (or a file copy) sees a single consistent file.
+pub fn finish(db_path: &str) -> Result<()> {
+    let conn = rusqlite::Connection::open(db_path).context("open database for checkpoint")?;
+    let (busy, log_frames, checkpointed) = crate::db::checkpoint(&conn)?;
+
+    if busy {
+        tracing::warn!(
+            log_frames,
+            checkpointed,
+            "WAL checkpoint could not complete"
+        );
+    } else {
+        tracing::info!(checkpointed, "WAL checkpoint complete");
+    }
+
+    Ok(())
+}
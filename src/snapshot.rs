@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db,
+    errors::ErrorBody,
+    lifecycle::LifecycleState,
+    metadata::CustomFields,
+    models::{DeviceMeta, Drive, LaptopRow, LifecycleAlertRow, LifecycleEventRow},
+    AppState,
+};
+
+/// Forms accepted for `as_of`, for error messages
+const AS_OF_FORMATS: &str =
+    "expected an RFC 3339 timestamp, YYYY-MM-DDTHH:MM[:SS] (UTC) or YYYY-MM-DD (end of that day, UTC)";
+
+/// An instant to show the fleet as of, from an `as_of` parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsOf {
+    pub instant: DateTime<Utc>,
+    /// Whether only a date was given, meaning the end of that day
+    pub date_only: bool,
+}
+
+impl AsOf {
+    /// Parse an RFC 3339 timestamp, a `datetime-local` value (`2024-03-03T14:30`,
+    /// taken as UTC) or a date, which means the last moment of that day in UTC
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Some(instant) = db::parse_instant(input) {
+            return Some(Self {
+                instant,
+                date_only: false,
+            });
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(input, format) {
+                return Some(Self {
+                    instant: naive.and_utc(),
+                    date_only: false,
+                });
+            }
+        }
+        let date = NaiveDate::parse_from_str(input, "%Y-%m-%d").ok()?;
+        let next_day = date.succ_opt()?.and_hms_opt(0, 0, 0)?.and_utc();
+        Some(Self {
+            instant: next_day - Duration::milliseconds(1),
+            date_only: true,
+        })
+    }
+
+    /// Parse an optional query parameter; an empty value means now
+    pub fn from_param(param: Option<&str>) -> Result<Option<Self>, String> {
+        match param.map(str::trim).filter(|p| !p.is_empty()) {
+            None => Ok(None),
+            Some(p) => Self::parse(p)
+                .map(Some)
+                .ok_or_else(|| format!("invalid as_of {p:?}: {AS_OF_FORMATS}")),
+        }
+    }
+
+    /// The instant as RFC 3339 in UTC, to millisecond precision
+    pub fn timestamp(&self) -> String {
+        self.instant.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    /// `YYYY-MM-DD` of the instant, for a date picker
+    pub fn date(&self) -> String {
+        self.instant.format("%Y-%m-%d").to_string()
+    }
+
+    /// Value to pass on in links, so pages reached from a snapshot stay on it
+    pub fn param(&self) -> String {
+        if self.date_only {
+            self.date()
+        } else {
+            self.timestamp()
+        }
+    }
+
+    /// Description for page headings
+    pub fn label(&self) -> String {
+        if self.date_only {
+            format!("end of {} (UTC)", self.date())
+        } else {
+            self.instant.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    pub as_of: Option<String>,
+}
+
+/// A device's state in the JSON API
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub laptop_serial: String,
+    pub hostname: String,
+    pub domain: Option<String>,
+    pub ip_address: String,
+    pub source_ip: Option<String>,
+    pub logged_in_user: Option<String>,
+    /// Agent time of the check-in this state comes from
+    pub last_seen_utc: String,
+    pub received_at_utc: Option<String>,
+    pub clock_skew_secs: Option<i64>,
+    pub drives: Vec<Drive>,
+    /// Tags, notes and custom field values entered by admins. These aren't kept
+    /// over time: a device as of an instant has its current ones.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// Lifecycle state as of the same instant as the rest
+    #[serde(default)]
+    pub lifecycle_state: LifecycleState,
+    /// Check-ins since the device was marked lost, retired or disposed; only in
+    /// the current state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle_alert: Option<LifecycleAlertRow>,
+}
+
+impl DeviceState {
+    /// Add the device's tags, notes and values of the configured custom fields
+    fn with_meta(mut self, meta: DeviceMeta, fields: &CustomFields) -> Self {
+        self.tags = meta.tags;
+        self.notes = meta.notes;
+        self.fields = meta
+            .fields
+            .into_iter()
+            .filter(|(key, _)| fields.contains_key(key))
+            .collect();
+        self
+    }
+
+    /// Add the device's lifecycle state from its latest change, and any alert
+    fn with_lifecycle(
+        mut self,
+        latest: Option<&LifecycleEventRow>,
+        alert: Option<LifecycleAlertRow>,
+    ) -> Self {
+        self.lifecycle_state = latest.map_or_else(LifecycleState::default, |e| {
+            LifecycleState::from_key(&e.state)
+        });
+        self.lifecycle_alert = alert;
+        self
+    }
+}
+
+impl From<LaptopRow> for DeviceState {
+    fn from(row: LaptopRow) -> Self {
+        Self {
+            drives: serde_json::from_str(&row.drives_json).unwrap_or_default(),
+            laptop_serial: row.laptop_serial,
+            hostname: row.hostname,
+            domain: row.domain,
+            ip_address: row.ip_address,
+            source_ip: row.source_ip,
+            logged_in_user: row.logged_in_user,
+            last_seen_utc: row.last_seen_utc,
+            received_at_utc: row.received_at_utc,
+            clock_skew_secs: row.clock_skew_secs,
+            tags: Vec::new(),
+            notes: None,
+            fields: BTreeMap::new(),
+            lifecycle_state: LifecycleState::default(),
+            lifecycle_alert: None,
+        }
+    }
+}
+
+/// Response body for `/api/v1/devices`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DevicesResponse {
+    /// Instant the devices are shown as of; absent for the current state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<String>,
+    pub devices: Vec<DeviceState>,
+}
+
+/// Response body for `/api/v1/devices/:serial`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<String>,
+    pub device: DeviceState,
+}
+
+/// GET /api/v1/devices?as_of= - Every device's current state, or its state as of
+/// an instant
+#[tracing::instrument(skip(state))]
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<DevicesResponse>, Response> {
+    let as_of = AsOf::from_param(query.as_of.as_deref()).map_err(invalid_as_of)?;
+    let conn = rusqlite::Connection::open(&state.db_path).map_err(|e| internal(e.into()))?;
+    let rows = match &as_of {
+        Some(as_of) => db::get_all_laptops_as_of(&conn, as_of.instant),
+        None => db::get_all_laptops(&conn),
+    }
+    .map_err(internal)?;
+    let mut metas = db::get_all_device_meta(&conn).map_err(internal)?;
+    let states = db::get_lifecycle_states(&conn, as_of_ms(as_of.as_ref())).map_err(internal)?;
+    let mut alerts = match as_of {
+        Some(_) => Default::default(),
+        None => db::get_lifecycle_alerts(&conn).map_err(internal)?,
+    };
+    let fields = &state.settings().custom_fields;
+
+    let devices = rows
+        .into_iter()
+        .map(|row| {
+            let meta = metas.remove(&row.laptop_serial).unwrap_or_default();
+            let latest = states.get(&row.laptop_serial);
+            let alert = alerts.remove(&row.laptop_serial);
+            DeviceState::from(row)
+                .with_meta(meta, fields)
+                .with_lifecycle(latest, alert)
+        })
+        .collect();
+    Ok(Json(DevicesResponse {
+        as_of: as_of.map(|a| a.timestamp()),
+        devices,
+    }))
+}
+
+/// GET /api/v1/devices/:serial?as_of= - One device's current state, or its state
+/// as of an instant
+#[tracing::instrument(skip(state))]
+pub async fn device(
+    State(state): State<Arc<AppState>>,
+    Path(serial): Path<String>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<DeviceResponse>, Response> {
+    let as_of = AsOf::from_param(query.as_of.as_deref()).map_err(invalid_as_of)?;
+    let conn = rusqlite::Connection::open(&state.db_path).map_err(|e| internal(e.into()))?;
+    let row = match &as_of {
+        Some(as_of) => db::get_laptop_by_serial_as_of(&conn, &serial, as_of.instant),
+        None => db::get_laptop_by_serial(&conn, &serial),
+    }
+    .map_err(internal)?;
+
+    let Some(row) = row else {
+        let detail = match &as_of {
+            Some(as_of) => format!("{serial} had not checked in by {}", as_of.timestamp()),
+            None => format!("no device with serial {serial}"),
+        };
+        return Err(ErrorBody::detailed(
+            StatusCode::NOT_FOUND,
+            "Device not found",
+            Some(detail),
+        ));
+    };
+
+    let meta = db::get_device_meta(&conn, &serial).map_err(internal)?;
+    let events =
+        db::get_lifecycle_events(&conn, &serial, as_of_ms(as_of.as_ref())).map_err(internal)?;
+    let alert = match as_of {
+        Some(_) => None,
+        None => db::get_lifecycle_alert(&conn, &serial).map_err(internal)?,
+    };
+    let device = DeviceState::from(row)
+        .with_meta(meta, &state.settings().custom_fields)
+        .with_lifecycle(events.first(), alert);
+    Ok(Json(DeviceResponse {
+        as_of: as_of.map(|a| a.timestamp()),
+        device,
+    }))
+}
+
+/// The instant in Unix milliseconds, for queries on lifecycle changes
+fn as_of_ms(as_of: Option<&AsOf>) -> Option<i64> {
+    as_of.map(|a| a.instant.timestamp_millis())
+}
+
+fn invalid_as_of(detail: String) -> Response {
+    ErrorBody::detailed(StatusCode::BAD_REQUEST, "Invalid as_of", Some(detail))
+}
+
+fn internal(e: anyhow::Error) -> Response {
+    tracing::error!(error = ?e, "Device query failed");
+    ErrorBody::detailed(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error",
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forms() {
+        let exact = AsOf::parse("2024-03-03T14:30:00+02:00").unwrap();
+        assert_eq!(exact.timestamp(), "2024-03-03T12:30:00.000Z");
+        assert!(!exact.date_only);
+
+        let local = AsOf::parse("2024-03-03T14:30").unwrap();
+        assert_eq!(local.timestamp(), "2024-03-03T14:30:00.000Z");
+        assert_eq!(local.param(), local.timestamp());
+
+        let day = AsOf::parse(" 2024-03-03 ").unwrap();
+        assert_eq!(day.timestamp(), "2024-03-03T23:59:59.999Z");
+        assert_eq!(day.param(), "2024-03-03");
+        assert_eq!(day.label(), "end of 2024-03-03 (UTC)");
+
+        for bad in [
+            "",
+            "yesterday",
+            "2024-02-30",
+            "2024-03-03T25:00",
+            "1709424000",
+        ] {
+            assert_eq!(AsOf::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_from_param() {
+        assert_eq!(AsOf::from_param(None), Ok(None));
+        assert_eq!(AsOf::from_param(Some("  ")), Ok(None));
+        assert!(AsOf::from_param(Some("2024-03-03")).unwrap().is_some());
+        let err = AsOf::from_param(Some("soon")).unwrap_err();
+        assert!(err.contains("YYYY-MM-DD"), "{err}");
+    }
+}
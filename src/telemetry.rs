@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::config::TelemetryConfig;
+
+/// Build an OTLP/HTTP tracer provider when an endpoint is configured.
+///
+/// Spans are exported in batches from a background thread. Call
+/// [`SdkTracerProvider::shutdown`] before exit to flush what is still queued.
+pub fn init_tracer_provider(cfg: &TelemetryConfig) -> Result<Option<SdkTracerProvider>> {
+    let Some(endpoint) = &cfg.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .context("build OTLP span exporter")?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(cfg.service_name.clone())
+                .build(),
+        )
+        .build();
+
+    Ok(Some(provider))
+}
+
+/// `tracing` layer that forwards spans to `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S> + Send + Sync
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
@@ -0,0 +1,418 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{config::UsersConfig, db, models::UserDeviceRow, AppState};
+
+/// Key under which user names reported in different forms are grouped, e.g.
+/// `contoso\jdoe` for `CONTOSO\jdoe`, `jdoe@contoso.com` and `JDOE@Contoso.Local`.
+/// Case is ignored, and a DNS domain stands for the NetBIOS domain named by its
+/// first label unless `rules` maps it to another. `None` for a blank name, and for a
+/// machine-local account like `.\admin`, which is a different person on every
+/// device; see [`qualified_name`].
+pub fn normalize(user: &str, rules: &UsersConfig) -> Option<String> {
+    let (domain, name) = split(user);
+    let name = name.to_lowercase();
+    if name.is_empty() || domain == LOCAL_DOMAIN {
+        return None;
+    }
+    if domain.is_empty() {
+        return Some(name);
+    }
+
+    let domain = rules
+        .domain_aliases
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(domain))
+        .map_or(domain, |(_, canonical)| canonical.as_str());
+    let domain = match domain.split_once('.') {
+        Some((first, _)) if !first.is_empty() => first,
+        _ => domain,
+    };
+    Some(format!("{}\\{name}", domain.to_lowercase()))
+}
+
+/// A user name without its domain, lowercased, e.g. `jdoe` for `CONTOSO\jdoe`. All
+/// forms of one person's name share it whatever the domain rules, so it is stored
+/// with each check-in to find a person's check-ins by index. `None` for a blank name.
+pub fn account_name(user: &str) -> Option<String> {
+    Some(split(user).1.to_lowercase()).filter(|name| !name.is_empty())
+}
+
+/// A name as reported from the device `hostname`, with the `.` of a machine-local
+/// account replaced by the computer name, e.g. `LAPTOP-001\admin` for `.\admin`, so
+/// that it names one person on its own. Other names are returned as they are.
+pub fn qualified_name(user: &str, hostname: &str) -> String {
+    let computer = hostname.split('.').next().unwrap_or_default();
+    match split(user) {
+        (LOCAL_DOMAIN, name) if !computer.is_empty() => format!("{computer}\\{name}"),
+        _ => user.to_string(),
+    }
+}
+
+/// Domain Windows reports machine-local accounts under, as `.\name` or `\name`
+const LOCAL_DOMAIN: &str = ".";
+
+/// Domain and name of `DOMAIN\name`, `name@domain` or a bare `name`
+fn split(user: &str) -> (&str, &str) {
+    let user = user.trim();
+    if let Some((domain, name)) = user.split_once('\\') {
+        (
+            if domain.is_empty() {
+                LOCAL_DOMAIN
+            } else {
+                domain
+            },
+            name,
+        )
+    } else if let Some((name, domain)) = user.rsplit_once('@') {
+        (domain, name)
+    } else {
+        ("", user)
+    }
+}
+
+/// A person's check-ins on one device, across the forms their name was reported in
+#[derive(Debug, Clone)]
+pub struct UserDevice {
+    pub laptop_serial: String,
+    pub hostname: Option<String>,
+    /// Whether the device's latest check-in is from this person
+    pub current: bool,
+    pub checkins: i64,
+    pub first_seen_utc: String,
+    pub last_seen_utc: String,
+}
+
+/// Everything seen of one person
+#[derive(Debug, Clone)]
+pub struct User {
+    pub key: String,
+    /// Names reported for this person, most recently seen first
+    pub names: Vec<String>,
+    /// Devices, most recently used first
+    pub devices: Vec<UserDevice>,
+}
+
+impl User {
+    /// The name this person was last reported under
+    pub fn name(&self) -> &str {
+        &self.names[0]
+    }
+
+    /// Names other than [`User::name`]
+    pub fn other_names(&self) -> &[String] {
+        &self.names[1..]
+    }
+
+    pub fn checkins(&self) -> i64 {
+        self.devices.iter().map(|d| d.checkins).sum()
+    }
+
+    pub fn first_seen_utc(&self) -> &str {
+        self.devices
+            .iter()
+            .map(|d| d.first_seen_utc.as_str())
+            .min()
+            .unwrap_or_default()
+    }
+
+    pub fn last_seen_utc(&self) -> &str {
+        self.devices
+            .first()
+            .map_or("", |d| d.last_seen_utc.as_str())
+    }
+
+    /// Devices currently reporting this person as logged in
+    pub fn current_devices(&self) -> usize {
+        self.devices.iter().filter(|d| d.current).count()
+    }
+}
+
+/// Group rows from [`db::get_user_devices`] by person, most recently seen first
+pub fn group(rows: Vec<UserDeviceRow>, rules: &UsersConfig) -> Vec<User> {
+    // Per person: the latest time each name was seen, and per-device totals
+    type Names = HashMap<String, String>;
+    type Devices = BTreeMap<String, UserDevice>;
+    let mut people = BTreeMap::<String, (Names, Devices)>::new();
+
+    for row in rows {
+        // Machine-local accounts are told apart by the device's current name
+        let hostname = row.hostname.as_deref().unwrap_or_default();
+        let Some(key) = normalize(&qualified_name(&row.logged_in_user, hostname), rules) else {
+            continue;
+        };
+        let current = row
+            .current_user
+            .as_deref()
+            .and_then(|u| normalize(&qualified_name(u, hostname), rules))
+            .is_some_and(|u| u == key);
+        let (names, devices) = people.entry(key).or_default();
+
+        let seen = names.entry(row.logged_in_user).or_default();
+        if row.last_seen_utc > *seen {
+            seen.clone_from(&row.last_seen_utc);
+        }
+
+        match devices.get_mut(&row.laptop_serial) {
+            Some(device) => {
+                device.checkins += row.checkins;
+                if row.first_seen_utc < device.first_seen_utc {
+                    device.first_seen_utc = row.first_seen_utc;
+                }
+                if row.last_seen_utc > device.last_seen_utc {
+                    device.last_seen_utc = row.last_seen_utc;
+                }
+            }
+            None => {
+                let device = UserDevice {
+                    laptop_serial: row.laptop_serial.clone(),
+                    hostname: row.hostname,
+                    current,
+                    checkins: row.checkins,
+                    first_seen_utc: row.first_seen_utc,
+                    last_seen_utc: row.last_seen_utc,
+                };
+                devices.insert(row.laptop_serial, device);
+            }
+        }
+    }
+
+    let mut users: Vec<User> = people
+        .into_iter()
+        .map(|(key, (names, devices))| {
+            let mut names: Vec<(String, String)> = names.into_iter().collect();
+            names.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let mut devices: Vec<UserDevice> = devices.into_values().collect();
+            devices.sort_by(|a, b| b.last_seen_utc.cmp(&a.last_seen_utc));
+            User {
+                key,
+                names: names.into_iter().map(|(name, _)| name).collect(),
+                devices,
+            }
+        })
+        .collect();
+    users.sort_by(|a, b| {
+        b.last_seen_utc()
+            .cmp(a.last_seen_utc())
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    users
+}
+
+#[derive(Template)]
+#[template(path = "users.html")]
+pub struct UsersTemplate {
+    pub users: Vec<User>,
+}
+
+impl UsersTemplate {
+    /// Distinct devices anyone has been seen on
+    pub fn device_count(&self) -> usize {
+        self.users
+            .iter()
+            .flat_map(|u| u.devices.iter().map(|d| d.laptop_serial.as_str()))
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "user.html")]
+pub struct UserTemplate {
+    pub user: User,
+}
+
+/// GET /users - Everyone seen logged in, with their devices
+#[tracing::instrument(skip_all)]
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+) -> Result<UsersTemplate, (StatusCode, String)> {
+    let users = load(&state)?;
+    Ok(UsersTemplate { users })
+}
+
+/// GET /user/:name - Devices a person has been seen on. Any form of their name finds
+/// them, e.g. `/user/jdoe@contoso.com` and `/user/CONTOSO%5Cjdoe`.
+#[tracing::instrument(skip_all)]
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<UserTemplate, (StatusCode, String)> {
+    let settings = state.settings();
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            format!("No check-ins from user {name}"),
+        )
+    };
+    let key = normalize(&name, &settings.users).ok_or_else(not_found)?;
+    let account = account_name(&name).ok_or_else(not_found)?;
+    // Only check-ins from names with the same account; other domains drop out here
+    let rows = query(&state, |conn| db::get_account_devices(conn, &account))?;
+    let user = group(rows, &settings.users)
+        .into_iter()
+        .find(|u| u.key == key)
+        .ok_or_else(not_found)?;
+    Ok(UserTemplate { user })
+}
+
+fn load(state: &AppState) -> Result<Vec<User>, (StatusCode, String)> {
+    let rows = query(state, db::get_user_devices)?;
+    Ok(group(rows, &state.settings().users))
+}
+
+fn query(
+    state: &AppState,
+    rows: impl FnOnce(&rusqlite::Connection) -> anyhow::Result<Vec<UserDeviceRow>>,
+) -> Result<Vec<UserDeviceRow>, (StatusCode, String)> {
+    let conn = rusqlite::Connection::open(&state.db_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("db open: {e}")))?;
+    rows(&conn).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("query users: {e}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> UsersConfig {
+        UsersConfig {
+            domain_aliases: [("corp.example.com".to_string(), "EXAMPLE".to_string())].into(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_forms() {
+        let rules = rules();
+        let key = |u: &str| normalize(u, &rules);
+        assert_eq!(key("CONTOSO\\jdoe").as_deref(), Some("contoso\\jdoe"));
+        assert_eq!(key("jdoe@contoso.com"), key("CONTOSO\\jdoe"));
+        assert_eq!(key("JDoe@Contoso.Local"), key("CONTOSO\\jdoe"));
+        assert_eq!(key(" jdoe ").as_deref(), Some("jdoe"));
+        assert_ne!(key("jdoe"), key("CONTOSO\\jdoe"));
+        assert_ne!(key("FABRIKAM\\jdoe"), key("CONTOSO\\jdoe"));
+        assert_eq!(key("José@Contoso.com").as_deref(), Some("contoso\\josé"));
+
+        // Aliased domain, where the NetBIOS name isn't the first DNS label
+        assert_eq!(key("asmith@CORP.example.com"), key("EXAMPLE\\asmith"));
+        assert_eq!(key("CORP\\asmith").as_deref(), Some("corp\\asmith"));
+
+        assert_eq!(key(".\\admin"), None);
+        assert_eq!(key("\\admin"), None);
+        assert_eq!(key("CONTOSO\\"), None);
+        assert_eq!(key(""), None);
+    }
+
+    #[test]
+    fn test_qualified_name() {
+        assert_eq!(
+            qualified_name(".\\admin", "LAPTOP-001"),
+            "LAPTOP-001\\admin"
+        );
+        assert_eq!(
+            qualified_name("\\admin", "laptop-001.corp.example.com"),
+            "laptop-001\\admin"
+        );
+        assert_eq!(
+            qualified_name("CONTOSO\\jdoe", "LAPTOP-001"),
+            "CONTOSO\\jdoe"
+        );
+        assert_eq!(qualified_name("jdoe", "LAPTOP-001"), "jdoe");
+        assert_eq!(qualified_name(".\\admin", ""), ".\\admin");
+    }
+
+    #[test]
+    fn test_account_name() {
+        for user in [
+            "CONTOSO\\JDoe",
+            "jdoe@contoso.com",
+            " jdoe ",
+            "FABRIKAM\\jdoe",
+        ] {
+            assert_eq!(account_name(user).as_deref(), Some("jdoe"), "{user}");
+        }
+        assert_eq!(account_name("José@Contoso.com").as_deref(), Some("josé"));
+        assert_eq!(account_name("CONTOSO\\"), None);
+        assert_eq!(account_name(""), None);
+    }
+
+    fn row(user: &str, serial: &str, current: &str, first: &str, last: &str) -> UserDeviceRow {
+        UserDeviceRow {
+            logged_in_user: user.to_string(),
+            laptop_serial: serial.to_string(),
+            hostname: Some(format!("host-{serial}")),
+            current_user: Some(current.to_string()),
+            checkins: 2,
+            first_seen_utc: first.to_string(),
+            last_seen_utc: last.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_group_merges_names() {
+        let rows = vec![
+            row(
+                "CONTOSO\\jdoe",
+                "SN1",
+                "asmith",
+                "2024-01-01T00:00:00Z",
+                "2024-01-05T00:00:00Z",
+            ),
+            row(
+                "jdoe@contoso.com",
+                "SN1",
+                "asmith",
+                "2024-01-06T00:00:00Z",
+                "2024-01-07T00:00:00Z",
+            ),
+            row(
+                "jdoe@contoso.com",
+                "SN2",
+                "CONTOSO\\JDOE",
+                "2024-01-03T00:00:00Z",
+                "2024-01-04T00:00:00Z",
+            ),
+            row(
+                "asmith",
+                "SN1",
+                "asmith",
+                "2024-01-08T00:00:00Z",
+                "2024-01-08T00:00:00Z",
+            ),
+        ];
+        let users = group(rows, &UsersConfig::default());
+
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].key, "asmith");
+        assert_eq!(users[0].current_devices(), 1);
+
+        let jdoe = &users[1];
+        assert_eq!(jdoe.name(), "jdoe@contoso.com");
+        assert_eq!(jdoe.other_names(), ["CONTOSO\\jdoe"]);
+        assert_eq!(jdoe.checkins(), 6);
+        assert_eq!(jdoe.first_seen_utc(), "2024-01-01T00:00:00Z");
+        assert_eq!(jdoe.last_seen_utc(), "2024-01-07T00:00:00Z");
+
+        let serials: Vec<&str> = jdoe
+            .devices
+            .iter()
+            .map(|d| d.laptop_serial.as_str())
+            .collect();
+        assert_eq!(serials, ["SN1", "SN2"]);
+        assert_eq!(jdoe.devices[0].checkins, 4);
+        assert_eq!(jdoe.devices[0].first_seen_utc, "2024-01-01T00:00:00Z");
+        assert!(!jdoe.devices[0].current);
+        assert!(jdoe.devices[1].current);
+        assert_eq!(UsersTemplate { users }.device_count(), 2);
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+/// One failed rule in a check-in payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Path to the field, e.g. `hostname` or `drives[2].serial_number`
+    pub field: String,
+    /// Validator code, e.g. `invalid_hostname` or `length`
+    pub code: String,
+}
+
+/// Flatten nested validator errors into field paths, sorted by path
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut out = Vec::new();
+    collect(errors, "", &mut out);
+    out.sort_by(|a, b| a.field.cmp(&b.field).then_with(|| a.code.cmp(&b.code)));
+    out
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (name, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{prefix}.{name}")
+        };
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                out.extend(list.iter().map(|e| FieldError {
+                    field: path.clone(),
+                    code: e.code.to_string(),
+                }));
+            }
+            ValidationErrorsKind::Struct(nested) => collect(nested, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect(nested, &format!("{path}[{index}]"), out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CheckIn, Drive};
+    use validator::Validate;
+
+    fn drive(serial: &str) -> Drive {
+        Drive {
+            model: "Samsung SSD 970".to_string(),
+            serial_number: Some(serial.to_string()),
+            device_id: r"\\.\PHYSICALDRIVE0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_field_paths_include_list_indexes() {
+        let checkin = CheckIn {
+            hostname: "-bad-".to_string(),
+            ip_address: "192.168.1.100".to_string(),
+            logged_in_user: None,
+            laptop_serial: "SN1".to_string(),
+            drives: vec![drive("OK1"), drive("OK2"), drive("BAD\u{7}")],
+            timestamp_utc: "not a time".to_string(),
+            checkin_id: None,
+        };
+
+        let errors = field_errors(&checkin.validate().unwrap_err());
+        let pairs: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|e| (e.field.as_str(), e.code.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("drives[2].serial_number", "invalid_characters"),
+                ("hostname", "invalid_hostname"),
+                ("timestamp_utc", "invalid_timestamp"),
+            ]
+        );
+    }
+}
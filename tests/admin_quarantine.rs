@@ -0,0 +1,238 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::db;
+use tower::ServiceExt;
+
+async fn post_checkin(app: &Router, uri: &str, body: String) -> StatusCode {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr: SocketAddr = "10.0.0.5:50000".parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// GET a page, or POST one of its forms with the token from the quarantine page
+async fn admin(app: &Router, method: &str, uri: &str) -> (StatusCode, Option<String>, String) {
+    let body = if method == "POST" {
+        let (_, _, page) = send(app, "GET", "/admin/quarantine", String::new()).await;
+        format!("csrf_token={}", common::csrf_token(&page))
+    } else {
+        String::new()
+    };
+    send(app, method, uri, body).await
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: String,
+) -> (StatusCode, Option<String>, String) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        location,
+        String::from_utf8_lossy(&body).into_owned(),
+    )
+}
+
+fn with_hostname(hostname: &str, serial: &str) -> String {
+    common::checkin_json_with(
+        hostname,
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        "2024-01-15T10:30:00Z",
+    )
+}
+
+fn quarantined(db: &tempfile::NamedTempFile) -> Vec<inventory_server::models::QuarantineRow> {
+    let conn = rusqlite::Connection::open(db.path()).unwrap();
+    db::get_quarantined(&conn).unwrap()
+}
+
+#[tokio::test]
+async fn test_rejected_checkin_is_quarantined() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let status = post_checkin(&app, "/checkin", with_hostname("laptop.corp", "SN001")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let entries = quarantined(&temp_db);
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry.source_ip.as_deref(), Some("10.0.0.5"));
+    assert_eq!(entry.laptop_serial.as_deref(), Some("SN001"));
+    assert!(entry.body.contains("laptop.corp"));
+    let errors = entry.field_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "hostname");
+    assert_eq!(errors[0].code, "invalid_hostname");
+
+    // Shape errors are kept too, rate limits and clock skew aren't
+    let mut missing: serde_json::Value =
+        serde_json::from_str(&with_hostname("OK", "SN002")).unwrap();
+    missing.as_object_mut().unwrap().remove("drives");
+    post_checkin(&app, "/checkin", missing.to_string()).await;
+    let entries = quarantined(&temp_db);
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].error.contains("missing field `drives`"));
+}
+
+#[tokio::test]
+async fn test_quarantine_is_bounded() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().validation.quarantine_max_rows = 2;
+    });
+
+    let batch = format!(
+        "[{},{},{}]",
+        with_hostname("_a", "SN-A"),
+        with_hostname("_b", "SN-B"),
+        with_hostname("_c", "SN-C")
+    );
+    assert_eq!(
+        post_checkin(&app, "/checkin/batch", batch).await,
+        StatusCode::OK
+    );
+
+    let serials: Vec<String> = quarantined(&temp_db)
+        .into_iter()
+        .filter_map(|e| e.laptop_serial)
+        .collect();
+    assert_eq!(serials, ["SN-C", "SN-B"]);
+}
+
+#[tokio::test]
+async fn test_quarantine_can_be_disabled() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().validation.quarantine_max_rows = 0;
+    });
+
+    post_checkin(&app, "/checkin", with_hostname("_bad", "SN001")).await;
+    assert!(quarantined(&temp_db).is_empty());
+}
+
+#[tokio::test]
+async fn test_admin_page_lists_entries() {
+    let (app, _temp_db) = common::setup_test_app();
+    post_checkin(&app, "/checkin", with_hostname("<b>x</b>", "SN001")).await;
+
+    let (status, _, html) = admin(&app, "GET", "/admin/quarantine").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(html.contains("SN001"));
+    assert!(html.contains("invalid_hostname"));
+    assert!(html.contains("10.0.0.5"));
+    // The raw body is escaped, not rendered
+    assert!(!html.contains("<b>x</b>"));
+}
+
+#[tokio::test]
+async fn test_reprocess_stores_entries_that_now_pass() {
+    let (app, temp_db) = common::setup_test_app();
+    post_checkin(&app, "/checkin", with_hostname("_bad", "SN-BAD")).await;
+
+    // Stand-in for a validation change: an entry whose body passes the current rules
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    db::insert_quarantined(
+        &conn,
+        &db::NewQuarantined {
+            received_at_utc: "2024-01-15T10:30:05Z".to_string(),
+            source_ip: Some("10.0.0.9".to_string()),
+            laptop_serial: Some("SN-OK".to_string()),
+            body: with_hostname("LAPTOP-OK", "SN-OK"),
+            error: "Invalid input data".to_string(),
+            errors_json: "[]".to_string(),
+        },
+        100,
+    )
+    .unwrap();
+
+    let (status, location, _) = admin(&app, "POST", "/admin/quarantine/reprocess").await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        location.as_deref(),
+        Some("/admin/quarantine?stored=1&failed=1")
+    );
+
+    let laptop = db::get_laptop_by_serial(&conn, "SN-OK").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "LAPTOP-OK");
+    assert_eq!(laptop.source_ip.as_deref(), Some("10.0.0.9"));
+    assert_eq!(
+        laptop.received_at_utc.as_deref(),
+        Some("2024-01-15T10:30:05Z")
+    );
+
+    let remaining = quarantined(&temp_db);
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].laptop_serial.as_deref(), Some("SN-BAD"));
+
+    let (_, _, html) = admin(&app, "GET", &location.unwrap()).await;
+    assert!(html.contains("Re-processed 2: 1 stored, 1 still rejected"));
+}
+
+#[tokio::test]
+async fn test_discard_entry() {
+    let (app, temp_db) = common::setup_test_app();
+    post_checkin(&app, "/checkin", with_hostname("_bad", "SN001")).await;
+    let id = quarantined(&temp_db)[0].id;
+    // Read while the page still has forms
+    let (_, _, page) = admin(&app, "GET", "/admin/quarantine").await;
+    let form = format!("csrf_token={}", common::csrf_token(&page));
+
+    let uri = format!("/admin/quarantine/{id}/delete");
+    let (status, _, _) = send(&app, "POST", &uri, form.clone()).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert!(quarantined(&temp_db).is_empty());
+
+    let uri = format!("/admin/quarantine/{id}/reprocess");
+    let (status, _, _) = send(&app, "POST", &uri, form).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_forms_require_csrf_token() {
+    let (app, temp_db) = common::setup_test_app();
+    post_checkin(&app, "/checkin", with_hostname("_bad", "SN001")).await;
+    let id = quarantined(&temp_db)[0].id;
+
+    for body in ["", "csrf_token=forged"] {
+        let uri = format!("/admin/quarantine/{id}/delete");
+        let (status, _, _) = send(&app, "POST", &uri, body.to_string()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{body:?}");
+    }
+    assert_eq!(quarantined(&temp_db).len(), 1);
+
+    let (_, _, page) = admin(&app, "GET", "/admin/quarantine").await;
+    assert_eq!(page.matches(common::csrf_token(&page)).count(), 3);
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use inventory_server::snapshot::{DeviceResponse, DevicesResponse};
+
+/// LAPTOP-001 is used by jdoe, then asmith; LAPTOP-002 appears in between
+async fn fleet() -> (Router, tempfile::NamedTempFile) {
+    let (app, temp_db) = common::setup_test_app();
+    common::seed(
+        &app,
+        &[
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "jdoe",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-002",
+                "SN2",
+                "10.0.0.5",
+                "mlee",
+                "2024-03-05T09:00:00Z",
+            ),
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "asmith",
+                "2024-03-10T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+    (app, temp_db)
+}
+
+fn users(response: &DevicesResponse) -> Vec<(&str, &str)> {
+    response
+        .devices
+        .iter()
+        .map(|d| {
+            (
+                d.laptop_serial.as_str(),
+                d.logged_in_user.as_deref().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_devices_api_current_and_as_of() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/api/v1/devices").await;
+    assert_eq!(status, StatusCode::OK);
+    let current: DevicesResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(current.as_of, None);
+    assert_eq!(users(&current), [("SN1", "asmith"), ("SN2", "mlee")]);
+
+    // A date means the end of that day
+    let (status, body) = common::get(&app, "/api/v1/devices?as_of=2024-03-05").await;
+    assert_eq!(status, StatusCode::OK);
+    let snapshot: DevicesResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(snapshot.as_of.as_deref(), Some("2024-03-05T23:59:59.999Z"));
+    assert_eq!(users(&snapshot), [("SN2", "mlee"), ("SN1", "jdoe")]);
+    assert_eq!(snapshot.devices[1].last_seen_utc, "2024-03-01T09:00:00Z");
+
+    // Before SN2's first check-in, and before any check-in
+    let (_, body) = common::get(&app, "/api/v1/devices?as_of=2024-03-05T08:59:59Z").await;
+    let snapshot: DevicesResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(users(&snapshot), [("SN1", "jdoe")]);
+    let (_, body) = common::get(&app, "/api/v1/devices?as_of=2024-02-01").await;
+    let snapshot: DevicesResponse = serde_json::from_str(&body).unwrap();
+    assert!(snapshot.devices.is_empty());
+}
+
+#[tokio::test]
+async fn test_device_api_as_of() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/api/v1/devices/SN1?as_of=2024-03-10T08:00").await;
+    assert_eq!(status, StatusCode::OK);
+    let response: DeviceResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(response.device.logged_in_user.as_deref(), Some("jdoe"));
+    assert_eq!(response.as_of.as_deref(), Some("2024-03-10T08:00:00.000Z"));
+
+    let (status, body) = common::get(&app, "/api/v1/devices/SN2?as_of=2024-03-01").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("had not checked in by"), "{body}");
+
+    let (status, _) = common::get(&app, "/api/v1/devices/SN9").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_live_and_as_of_order_by_instant() {
+    let (app, _temp_db) = common::setup_test_app();
+    // SN1 is seen last, at 10:30Z, then SN2 and SN3; as text the order would be
+    // SN1, SN3, SN2, and SN1's history 12:30+02:00, 11:00+02:00, 10:00Z
+    let checkins = [
+        ("LAPTOP-001", "SN1", "2024-03-01T10:00:00Z"),
+        ("LAPTOP-002", "SN2", "2024-03-01T10:00:00.5Z"),
+        ("LAPTOP-003", "SN3", "2024-03-01T11:00:00+02:00"),
+        ("LAPTOP-001", "SN1", "2024-03-01T11:00:00+02:00"),
+        ("LAPTOP-001", "SN1", "2024-03-01T12:30:00+02:00"),
+    ];
+    let checkins = checkins
+        .map(|(hostname, serial, timestamp)| (hostname, serial, "10.0.0.5", "jdoe", timestamp));
+    common::seed(&app, &checkins).await;
+
+    let order = |body: &str| -> Vec<String> {
+        let response: DevicesResponse = serde_json::from_str(body).unwrap();
+        response
+            .devices
+            .into_iter()
+            .map(|d| d.laptop_serial)
+            .collect()
+    };
+    let (_, live) = common::get(&app, "/api/v1/devices").await;
+    let (_, as_of) = common::get(&app, "/api/v1/devices?as_of=2024-03-02").await;
+    assert_eq!(order(&live), ["SN1", "SN2", "SN3"]);
+    assert_eq!(order(&as_of), order(&live));
+
+    // The history table comes after the current state, which repeats the latest
+    let history = |page: &str| -> Vec<usize> {
+        ["T12:30:00+02:00", "T10:00:00Z", "T11:00:00+02:00"]
+            .iter()
+            .map(|t| page.rfind(t).unwrap())
+            .collect()
+    };
+    let (_, live) = common::get(&app, "/device/SN1").await;
+    let (_, as_of) = common::get(&app, "/device/SN1?as_of=2024-03-02").await;
+    for page in [&live, &as_of] {
+        let at = history(page);
+        assert!(at[0] < at[1] && at[1] < at[2], "{page}");
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_as_of_rejected() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/api/v1/devices?as_of=last+tuesday").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["error"], "Invalid as_of");
+
+    let (status, _) = common::get(&app, "/?as_of=2024-13-01").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = common::get(&app, "/device/SN1?as_of=2024-13-01").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_index_as_of() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/?as_of=2024-03-05").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("All Devices as of end of 2024-03-05 (UTC) (2)"));
+    assert!(body.contains(r#"name="as_of" value="2024-03-05""#));
+    assert!(body.contains("jdoe"));
+    assert!(!body.contains("asmith"));
+    // Device links stay on the same instant
+    assert!(body.contains("/device/SN1?as_of=2024-03-05"));
+
+    // An empty picker shows the current state
+    let (status, body) = common::get(&app, "/?as_of=").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("All Devices (2)"));
+    assert!(body.contains("asmith"));
+}
+
+#[tokio::test]
+async fn test_device_page_as_of() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/device/SN1?as_of=2024-03-05").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Shown as of end of 2024-03-05 (UTC)"));
+    assert!(body.contains("jdoe"));
+    assert!(!body.contains("asmith"));
+    assert!(body.contains("Check-in History up to end of 2024-03-05 (UTC) (1)"));
+
+    let (status, _) = common::get(&app, "/device/SN2?as_of=2024-03-01").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
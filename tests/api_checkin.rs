@@ -348,3 +348,104 @@ async fn test_invalid_json_returns_400() {
     // Axum returns 400 for invalid JSON
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
+
+async fn post_checkin(app: &axum::Router, body: String) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn test_late_checkin_does_not_regress_current_state() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let newer = common::checkin_json_with(
+        "hostname-v2",
+        "SN001",
+        "192.168.1.2",
+        Some("user2"),
+        "2024-01-15T10:00:00Z",
+    );
+    let older = common::checkin_json_with(
+        "hostname-v1",
+        "SN001",
+        "192.168.1.1",
+        Some("user1"),
+        "2024-01-10T10:00:00Z",
+    );
+    assert_eq!(post_checkin(&app, newer).await, StatusCode::OK);
+    assert_eq!(post_checkin(&app, older).await, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "hostname-v2");
+    assert_eq!(laptop.last_seen_utc, "2024-01-15T10:00:00Z");
+
+    // The late delivery is still part of the history
+    let checkins = db::get_checkins_by_serial(&conn, "SN001").unwrap();
+    assert_eq!(checkins.len(), 2);
+}
+
+#[tokio::test]
+async fn test_late_checkin_compares_instants_not_strings() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let newer = common::checkin_json_with(
+        "hostname-v2",
+        "SN001",
+        "192.168.1.2",
+        None,
+        "2024-01-15T10:00:00Z",
+    );
+    // Sorts after the newer one as a string, but is 08:00Z
+    let older = common::checkin_json_with(
+        "hostname-v1",
+        "SN001",
+        "192.168.1.1",
+        None,
+        "2024-01-15T10:30:00+02:30",
+    );
+    assert_eq!(post_checkin(&app, newer).await, StatusCode::OK);
+    assert_eq!(post_checkin(&app, older).await, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "hostname-v2");
+}
+
+#[tokio::test]
+async fn test_duplicate_checkin_keeps_current_state() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let first = common::checkin_json_with(
+        "hostname-v1",
+        "SN001",
+        "192.168.1.1",
+        None,
+        "2024-01-15T10:00:00Z",
+    );
+    // Retried with the same timestamp; the first delivery stays current
+    let retry = common::checkin_json_with(
+        "hostname-retry",
+        "SN001",
+        "192.168.1.1",
+        None,
+        "2024-01-15T10:00:00Z",
+    );
+    assert_eq!(post_checkin(&app, first).await, StatusCode::OK);
+    assert_eq!(post_checkin(&app, retry).await, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "hostname-v1");
+    assert_eq!(db::get_checkins_by_serial(&conn, "SN001").unwrap().len(), 2);
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::{db, models::BatchResponse};
+use tower::ServiceExt;
+
+async fn post_batch(app: &Router, content_type: &str, body: String) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin/batch")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn post_json_batch(app: &Router, items: &[String]) -> BatchResponse {
+    let body = format!("[{}]", items.join(","));
+    let (status, body) = post_batch(app, "application/json", body).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn item(hostname: &str, serial: &str, timestamp: &str) -> String {
+    common::checkin_json_with(hostname, serial, "10.0.0.1", Some("user1"), timestamp)
+}
+
+#[tokio::test]
+async fn test_batch_validates_items_independently() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let response = post_json_batch(
+        &app,
+        &[
+            item("LAPTOP-A", "SN-A", "2024-01-15T10:00:00Z"),
+            item("_bad_host", "SN-B", "2024-01-15T10:00:00Z"),
+            item("LAPTOP-C", "SN-C", "2024-01-15T10:00:00Z"),
+        ],
+    )
+    .await;
+
+    assert_eq!(response.accepted, 2);
+    assert_eq!(response.rejected, 1);
+    let statuses: Vec<&str> = response.results.iter().map(|r| r.status.as_str()).collect();
+    assert_eq!(statuses, ["accepted", "rejected", "accepted"]);
+    assert_eq!(response.results[1].laptop_serial.as_deref(), Some("SN-B"));
+    assert!(response.results[1].error.is_some());
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    assert_eq!(db::get_all_laptops(&conn).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_applies_items_in_timestamp_order() {
+    let (app, temp_db) = common::setup_test_app();
+
+    // Newest first in the request; the older snapshot must not win
+    let response = post_json_batch(
+        &app,
+        &[
+            item("HOST-NEW", "SN001", "2024-01-15T12:00:00Z"),
+            item("HOST-OLD", "SN001", "2024-01-15T08:00:00Z"),
+        ],
+    )
+    .await;
+
+    assert_eq!(response.accepted, 2);
+    assert_eq!(response.results[0].current, Some(true));
+    assert_eq!(response.results[1].current, Some(true));
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "HOST-NEW");
+    assert_eq!(db::get_checkins_by_serial(&conn, "SN001").unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_does_not_overwrite_newer_current_state() {
+    let (app, temp_db) = common::setup_test_app();
+
+    post_json_batch(&app, &[item("HOST-NEW", "SN001", "2024-01-15T12:00:00Z")]).await;
+    let response = post_json_batch(
+        &app,
+        &[item("HOST-OLD", "SN001", "2024-01-15T13:00:00+02:00")],
+    )
+    .await;
+
+    // 13:00+02:00 is 11:00Z: older, even though the string sorts later
+    assert_eq!(response.results[0].status, "accepted");
+    assert_eq!(response.results[0].current, Some(false));
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "HOST-NEW");
+    assert_eq!(db::get_checkins_by_serial(&conn, "SN001").unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_accepts_ndjson() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let body = format!(
+        "{}\n{{not json\n\n{}\n",
+        item("LAPTOP-A", "SN-A", "2024-01-15T10:00:00Z"),
+        item("LAPTOP-B", "SN-B", "2024-01-15T11:00:00Z"),
+    );
+    let (status, body) = post_batch(&app, "application/x-ndjson", body).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let response: BatchResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.accepted, 2);
+    assert_eq!(response.rejected, 1);
+    assert_eq!(response.results[1].index, 1);
+    assert_eq!(response.results[1].status, "rejected");
+    assert_eq!(response.results[1].laptop_serial, None);
+}
+
+#[tokio::test]
+async fn test_batch_rejects_body_that_is_not_an_array() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let (status, _) = post_batch(&app, "application/json", common::valid_checkin_json()).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_batch_rejects_too_many_items() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.max_batch_items = 2;
+    });
+
+    let items: Vec<String> = (0..3)
+        .map(|i| item("LAPTOP", &format!("SN{i}"), "2024-01-15T10:00:00Z"))
+        .collect();
+    let (status, _) = post_batch(&app, "application/json", format!("[{}]", items.join(","))).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    assert!(db::get_all_laptops(&conn).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_batch_skips_repeated_checkin_ids() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let with_id = |body: String, id: &str| {
+        let mut payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        payload["checkin_id"] = serde_json::Value::String(id.to_string());
+        payload.to_string()
+    };
+    let first = with_id(item("LAPTOP-A", "SN-A", "2024-01-15T10:00:00Z"), "a-1");
+    let second = with_id(item("LAPTOP-A", "SN-A", "2024-01-15T11:00:00Z"), "a-2");
+    let stolen = with_id(item("LAPTOP-B", "SN-B", "2024-01-15T11:00:00Z"), "a-1");
+
+    let response = post_json_batch(&app, &[first.clone(), first.clone()]).await;
+    assert_eq!(response.accepted, 2);
+    assert!(!response.results[0].replayed);
+    assert!(response.results[1].replayed);
+
+    // A retried batch: only the new item is stored
+    let response = post_json_batch(&app, &[first, second, stolen]).await;
+    assert_eq!(response.accepted, 2);
+    assert_eq!(response.rejected, 1);
+    assert!(response.results[0].replayed);
+    assert!(!response.results[1].replayed);
+    assert_eq!(response.results[2].status, "rejected");
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    assert_eq!(db::get_checkins_by_serial(&conn, "SN-A").unwrap().len(), 2);
+    assert!(db::get_laptop_by_serial(&conn, "SN-B").unwrap().is_none());
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::{config::SkewAction, db};
+use tower::ServiceExt;
+
+fn hours_from_now(hours: i64) -> String {
+    (chrono::Utc::now() + chrono::Duration::hours(hours))
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+async fn post_checkin(app: &Router, serial: &str, timestamp: &str) -> StatusCode {
+    let body = common::checkin_json_with(
+        "LAPTOP-001",
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        timestamp,
+    );
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn get_html(app: &Router, uri: &str) -> String {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_checkin_records_receive_time_and_skew() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let status = post_checkin(&app, "SN001", "2024-01-15T10:30:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let checkins = db::get_checkins_by_serial(&conn, "SN001").unwrap();
+    let received = checkins[0].received_at_utc.as_deref().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(received).is_ok());
+    // Late delivery is allowed by default, but the gap is still recorded
+    assert_eq!(checkins[0].timestamp_utc, "2024-01-15T10:30:00Z");
+    assert!(checkins[0].clock_skew_secs.unwrap() < 0);
+
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.received_at_utc.as_deref(), Some(received));
+}
+
+#[tokio::test]
+async fn test_future_timestamp_is_clamped_and_flagged() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let status = post_checkin(&app, "SN-FUTURE", &hours_from_now(24)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN-FUTURE")
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(&laptop.last_seen_utc), laptop.received_at_utc.as_ref());
+    let skew = laptop.clock_skew_secs.unwrap();
+    assert!((86390..=86400).contains(&skew), "skew was {skew}");
+
+    let index = get_html(&app, "/").await;
+    assert!(index.contains("clock skew"));
+    let device = get_html(&app, "/device/SN-FUTURE").await;
+    assert!(device.contains("Agent clock"));
+    assert!(device.contains("ahead"));
+}
+
+#[tokio::test]
+async fn test_future_timestamp_rejected_when_configured() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().clock_skew.action = SkewAction::Reject;
+    });
+
+    let status = post_checkin(&app, "SN-FUTURE", &hours_from_now(1)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    assert!(db::get_laptop_by_serial(&conn, "SN-FUTURE")
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_small_skew_is_not_flagged() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let status = post_checkin(&app, "SN-OK", &now).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let index = get_html(&app, "/").await;
+    assert!(!index.contains("clock skew"));
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::io::Write;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+async fn post_encoded(app: &Router, uri: &str, encoding: &str, body: Vec<u8>) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_ENCODING, encoding)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn checkin_for(serial: &str) -> String {
+    common::checkin_json_with(
+        "LAPTOP-001",
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        "2024-01-15T10:30:00Z",
+    )
+}
+
+fn laptop_count(db: &tempfile::NamedTempFile) -> i64 {
+    let conn = rusqlite::Connection::open(db.path()).unwrap();
+    conn.query_row("SELECT COUNT(*) FROM laptops", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_compressed_checkins_are_accepted() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let gzipped = gzip(checkin_for("SN-GZIP").as_bytes());
+    assert_eq!(
+        post_encoded(&app, "/checkin", "gzip", gzipped).await,
+        StatusCode::OK
+    );
+
+    let deflated = deflate(checkin_for("SN-DEFLATE").as_bytes());
+    assert_eq!(
+        post_encoded(&app, "/checkin", "deflate", deflated).await,
+        StatusCode::OK
+    );
+
+    let zstd = zstd::encode_all(checkin_for("SN-ZSTD").as_bytes(), 3).unwrap();
+    assert_eq!(
+        post_encoded(&app, "/checkin", "zstd", zstd).await,
+        StatusCode::OK
+    );
+
+    assert_eq!(laptop_count(&temp_db), 3);
+}
+
+#[tokio::test]
+async fn test_compressed_batch_is_accepted() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let body = format!("[{},{}]", checkin_for("SN-A"), checkin_for("SN-B"));
+    assert_eq!(
+        post_encoded(&app, "/checkin/batch", "gzip", gzip(body.as_bytes())).await,
+        StatusCode::OK
+    );
+    assert_eq!(laptop_count(&temp_db), 2);
+}
+
+#[tokio::test]
+async fn test_decompressed_size_is_capped() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.max_body_kb = 16;
+    });
+
+    // A few hundred bytes on the wire, a megabyte once inflated
+    let mut payload: serde_json::Value = serde_json::from_str(&checkin_for("SN-BOMB")).unwrap();
+    payload["logged_in_user"] = serde_json::Value::String("a".repeat(1024 * 1024));
+    let bomb = gzip(payload.to_string().as_bytes());
+    assert!(bomb.len() < 16 * 1024);
+
+    assert_eq!(
+        post_encoded(&app, "/checkin", "gzip", bomb).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(laptop_count(&temp_db), 0);
+}
+
+#[tokio::test]
+async fn test_bad_encodings_are_rejected() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let status = post_encoded(&app, "/checkin", "compress", checkin_for("SN1").into()).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let status = post_encoded(&app, "/checkin", "gzip", b"not gzip".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    assert_eq!(laptop_count(&temp_db), 0);
+}
+
+#[tokio::test]
+async fn test_pages_are_compressed_when_accepted() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let compressed = response.into_body().collect().await.unwrap().to_bytes();
+    let mut html = String::new();
+    std::io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(&compressed[..]),
+        &mut html,
+    )
+    .unwrap();
+    assert!(html.contains("<html"));
+
+    // Without Accept-Encoding the page is sent as is
+    let response = app
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+}
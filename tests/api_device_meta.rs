@@ -0,0 +1,296 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::{
+    config::{CustomField, FieldType},
+    snapshot::{DeviceResponse, DevicesResponse},
+};
+use tower::ServiceExt;
+
+/// Submit a form on a device's page, with the token from that page; `form` is
+/// already URL-encoded
+async fn submit(app: &Router, serial: &str, action: &str, form: &str) -> (StatusCode, String) {
+    let (_, page) = common::get(app, &format!("/device/{serial}")).await;
+    let body = format!("csrf_token={}&{form}", common::csrf_token(&page));
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/device/{serial}/{action}"))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Two devices, and custom fields of each type but text-with-a-label
+async fn fleet() -> (Router, tempfile::NamedTempFile) {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        let field = |label: Option<&str>, kind, choices: &[&str]| CustomField {
+            label: label.map(str::to_string),
+            kind,
+            choices: choices.iter().map(|c| c.to_string()).collect(),
+        };
+        let fields = &mut state.settings_mut().custom_fields;
+        fields.insert(
+            "asset_tag".to_string(),
+            field(Some("Asset tag"), FieldType::Text, &[]),
+        );
+        fields.insert("cost".to_string(), field(None, FieldType::Number, &[]));
+        fields.insert(
+            "cost_center".to_string(),
+            field(Some("Cost center"), FieldType::Choice, &["IT", "Sales"]),
+        );
+        fields.insert("encrypted".to_string(), field(None, FieldType::Bool, &[]));
+    });
+    common::seed(
+        &app,
+        &[
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "jdoe",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-002",
+                "SN2",
+                "10.0.0.5",
+                "jdoe",
+                "2024-03-01T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+    (app, temp_db)
+}
+
+#[tokio::test]
+async fn test_tags_added_filtered_and_removed() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, _) = submit(&app, "SN1", "tags", "tag=Loaner").await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    submit(&app, "SN1", "tags", "tag=site%3AHQ").await;
+    submit(&app, "SN2", "tags", "tag=loaner").await;
+
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(
+        page.contains(r#"<a href="/?tag=Loaner">Loaner</a>"#),
+        "{page}"
+    );
+    assert!(page.contains(r#"<a href="/?tag=site%3AHQ">site:HQ</a>"#));
+
+    // Listed once per tag whatever the case, and matched without regard to it
+    let (_, index) = common::get(&app, "/").await;
+    assert!(index.contains("Loaner</a> (2)"), "{index}");
+    let (status, index) = common::get(&app, "/?tag=site%3Ahq").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(index.contains("All Devices tagged site:hq (1)"), "{index}");
+    assert!(index.contains("LAPTOP-001"));
+    assert!(!index.contains("LAPTOP-002"));
+
+    let (status, _) = submit(&app, "SN1", "tags/delete", "tag=LOANER").await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (_, index) = common::get(&app, "/?tag=loaner").await;
+    assert!(index.contains("tagged loaner (1)"));
+    assert!(index.contains("LAPTOP-002"));
+}
+
+#[tokio::test]
+async fn test_invalid_edits_rejected() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = submit(&app, "SN1", "tags", "tag=%3Cscript%3E").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Tags may contain"), "{body}");
+    let (status, _) = submit(&app, "SN1", "tags", "tag=+").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A bad value leaves every field unchanged
+    let (status, body) = submit(
+        &app,
+        "SN1",
+        "fields",
+        "field.asset_tag=A-1&field.cost=twelve",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("cost: \"twelve\" is not a number"), "{body}");
+    let (_, body) = common::get(&app, "/api/v1/devices/SN1").await;
+    let device: DeviceResponse = serde_json::from_str(&body).unwrap();
+    assert!(device.device.fields.is_empty());
+
+    // Devices that never checked in can't be edited
+    let (status, _) = common::get(&app, "/device/SN9").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_edits_require_csrf_token() {
+    let (app, _temp_db) = fleet().await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/device/SN1/tags")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from("tag=loaner&csrf_token=forged"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let (_, index) = common::get(&app, "/").await;
+    assert!(!index.contains("loaner"));
+}
+
+#[tokio::test]
+async fn test_notes_saved_and_escaped() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, _) = submit(
+        &app,
+        "SN1",
+        "notes",
+        "notes=Screen+cracked%0D%0A%3Cb%3Erepair%3C%2Fb%3E",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(
+        page.contains("Screen cracked\n&lt;b&gt;repair&lt;/b&gt;</textarea>"),
+        "{page}"
+    );
+    assert!(page.contains("last edited"));
+
+    // Saving them empty removes them
+    submit(&app, "SN1", "notes", "notes=").await;
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(!page.contains("last edited"));
+}
+
+#[tokio::test]
+async fn test_custom_fields_typed_and_filtered() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, _) = submit(
+        &app,
+        "SN1",
+        "fields",
+        "field.asset_tag=A-1&field.cost=1200.50&field.cost_center=it&field.encrypted=true",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    submit(
+        &app,
+        "SN2",
+        "fields",
+        "field.cost_center=Sales&field.encrypted=false",
+    )
+    .await;
+
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(
+        page.contains(r#"name="field.cost" value="1200.5""#),
+        "{page}"
+    );
+    assert!(page.contains(r#"<option value="IT" selected>IT</option>"#));
+    assert!(page.contains(r#"<option value="true" selected>Yes</option>"#));
+
+    let (_, index) = common::get(&app, "/?field=cost_center&value=IT").await;
+    assert!(
+        index.contains("All Devices with Cost center: IT (1)"),
+        "{index}"
+    );
+    assert!(index.contains("LAPTOP-001"));
+    let (_, index) = common::get(&app, "/?field=encrypted&value=no").await;
+    assert!(index.contains("with encrypted: No (1)"), "{index}");
+    assert!(index.contains("LAPTOP-002"));
+    let (_, index) = common::get(&app, "/?field=asset_tag").await;
+    assert!(index.contains("with Asset tag set (1)"), "{index}");
+
+    // Clearing one field leaves the others
+    submit(&app, "SN1", "fields", "field.asset_tag=").await;
+    let (_, index) = common::get(&app, "/?field=asset_tag").await;
+    assert!(index.contains("(0)"));
+    let (_, index) = common::get(&app, "/?field=cost_center&value=IT").await;
+    assert!(index.contains("(1)"));
+
+    let (status, _) = common::get(&app, "/?field=colour").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = common::get(&app, "/?field=cost_center&value=Legal").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_exports_include_metadata() {
+    let (app, _temp_db) = fleet().await;
+    submit(&app, "SN1", "tags", "tag=loaner").await;
+    submit(&app, "SN1", "tags", "tag=apac").await;
+    submit(&app, "SN1", "notes", "notes=Keys%2C+charger").await;
+    submit(
+        &app,
+        "SN1",
+        "fields",
+        "field.cost_center=IT&field.encrypted=yes",
+    )
+    .await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/export/devices.csv?tag=loaner")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/csv; charset=utf-8"
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let csv = String::from_utf8(body.to_vec()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2, "{csv}");
+    assert!(lines[0].ends_with(
+        "Tags,Notes,State,Check-ins While Out of Service,Asset tag,cost,Cost center,encrypted"
+    ));
+    assert!(lines[1].starts_with("SN1,LAPTOP-001,"));
+    assert!(
+        lines[1].ends_with(",apac; loaner,\"Keys, charger\",Deployed,,,,IT,Yes"),
+        "{csv}"
+    );
+
+    // The index links to the export of what it lists
+    let (_, index) = common::get(&app, "/?tag=loaner").await;
+    assert!(index.contains(r#"href="/export/devices.csv?tag=loaner""#));
+
+    let (_, body) = common::get(&app, "/api/v1/devices").await;
+    let devices: DevicesResponse = serde_json::from_str(&body).unwrap();
+    let sn1 = devices
+        .devices
+        .iter()
+        .find(|d| d.laptop_serial == "SN1")
+        .unwrap();
+    assert_eq!(sn1.tags, ["apac", "loaner"]);
+    assert_eq!(sn1.notes.as_deref(), Some("Keys, charger"));
+    assert_eq!(sn1.fields["encrypted"], "true");
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+/// Readiness thresholds that any test machine satisfies
+fn relaxed_thresholds(state: &mut inventory_server::AppState) {
+    state.settings_mut().readiness.min_free_disk_mb = 0;
+    state.settings_mut().readiness.max_wal_mb = 1024;
+}
+
+async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn check<'a>(body: &'a serde_json::Value, name: &str) -> &'a serde_json::Value {
+    body["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["name"] == name)
+        .unwrap_or_else(|| panic!("missing check {name}"))
+}
+
+#[tokio::test]
+async fn test_healthz_returns_ok() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let (status, body) = get_json(app, "/healthz").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+}
+
+#[tokio::test]
+async fn test_readyz_ready_with_fresh_database() {
+    let (app, _temp_db) = common::setup_test_app_with(relaxed_thresholds);
+
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    for name in ["database", "schema_version", "disk_space", "wal_size"] {
+        assert_eq!(check(&body, name)["ok"], true, "{name} should pass");
+    }
+}
+
+#[tokio::test]
+async fn test_readyz_schema_version_mismatch_returns_503() {
+    let (app, temp_db) = common::setup_test_app_with(relaxed_thresholds);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    conn.pragma_update(None, "user_version", 99).unwrap();
+    drop(conn);
+
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(check(&body, "schema_version")["ok"], false);
+    assert_eq!(check(&body, "database")["ok"], true);
+}
+
+#[tokio::test]
+async fn test_readyz_low_disk_space_returns_503() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        relaxed_thresholds(state);
+        state.settings_mut().readiness.min_free_disk_mb = u64::MAX;
+    });
+
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(check(&body, "disk_space")["ok"], false);
+}
+
+#[tokio::test]
+async fn test_readyz_oversized_wal_returns_503() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        relaxed_thresholds(state);
+        state.settings_mut().readiness.max_wal_mb = 0;
+    });
+
+    // Keep a connection open so the WAL isn't checkpointed away before the probe runs
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    conn.execute_batch(
+        "PRAGMA wal_autocheckpoint = 0;
+         INSERT INTO laptops (laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json)
+         VALUES ('SN-WAL', 'wal-host', '10.0.0.1', NULL, '2024-01-15T10:00:00Z', '[]');",
+    )
+    .unwrap();
+
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(check(&body, "wal_size")["ok"], false);
+    drop(conn);
+}
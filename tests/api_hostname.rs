@@ -0,0 +1,139 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::{config::HostnamePolicy, db};
+use tower::ServiceExt;
+
+async fn post_checkin(app: &Router, hostname: &str, serial: &str) -> StatusCode {
+    let body = common::checkin_json_with(
+        hostname,
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        "2024-01-15T10:30:00Z",
+    );
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr: SocketAddr = "10.0.0.5:50000".parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+async fn get_html(app: &Router, uri: &str) -> String {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn app_with(policy: HostnamePolicy) -> (Router, tempfile::NamedTempFile) {
+    common::setup_test_app_with(|state| {
+        state.settings_mut().validation.hostname = policy;
+    })
+}
+
+#[tokio::test]
+async fn test_fqdn_rejected_by_default() {
+    let (app, _temp_db) = common::setup_test_app();
+    assert_eq!(
+        post_checkin(&app, "mac-mini.example.com", "SN1").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(post_checkin(&app, "LAPTOP_01", "SN2").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_label_policy_rejects_underscores() {
+    let (app, _temp_db) = app_with(HostnamePolicy::Label);
+    assert_eq!(
+        post_checkin(&app, "LAPTOP_01", "SN1").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(post_checkin(&app, "laptop-01", "SN2").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_fqdn_is_split_into_short_name_and_domain() {
+    let (app, temp_db) = app_with(HostnamePolicy::Fqdn);
+    assert_eq!(
+        post_checkin(&app, "Mac-Mini.Corp.Example.com", "SN1").await,
+        StatusCode::OK
+    );
+    assert_eq!(post_checkin(&app, "LAPTOP_01", "SN2").await, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN1").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "Mac-Mini.Corp.Example.com");
+    assert_eq!(laptop.short_name.as_deref(), Some("Mac-Mini"));
+    assert_eq!(laptop.domain.as_deref(), Some("corp.example.com"));
+
+    let (short_name, domain): (String, Option<String>) = conn
+        .query_row(
+            "SELECT short_name, domain FROM checkins WHERE laptop_serial = 'SN2'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(short_name, "LAPTOP_01");
+    assert_eq!(domain, None);
+}
+
+#[tokio::test]
+async fn test_index_groups_by_domain() {
+    let (app, _temp_db) = app_with(HostnamePolicy::Fqdn);
+    post_checkin(&app, "web1.example.com", "SN1").await;
+    post_checkin(&app, "web2.EXAMPLE.com", "SN2").await;
+    post_checkin(&app, "build.lab.test", "SN3").await;
+    post_checkin(&app, "LAPTOP-01", "SN4").await;
+
+    let html = get_html(&app, "/").await;
+    assert!(html.contains("All Devices (4)"));
+    assert!(html.contains(r#"<a href="/?domain=example.com">example.com</a> (2)"#));
+    assert!(html.contains(r#"<a href="/?domain=lab.test">lab.test</a> (1)"#));
+    assert!(html.contains(r#"<a href="/?domain=-">no domain</a> (1)"#));
+
+    let html = get_html(&app, "/?domain=example.com").await;
+    assert!(html.contains("Devices in example.com (2)"));
+    assert!(html.contains("web1") && html.contains("web2"));
+    assert!(!html.contains("SN3") && !html.contains("SN4"));
+
+    let html = get_html(&app, "/?domain=-").await;
+    assert!(html.contains("Devices in no domain (1)"));
+    assert!(html.contains("SN4"));
+    assert!(!html.contains("SN1"));
+}
+
+#[tokio::test]
+async fn test_index_without_domains_has_no_summary() {
+    let (app, _temp_db) = common::setup_test_app();
+    post_checkin(&app, "LAPTOP-01", "SN1").await;
+
+    let html = get_html(&app, "/").await;
+    assert!(!html.contains("Domains:"));
+}
+
+#[tokio::test]
+async fn test_schema_follows_hostname_policy() {
+    let (app, _temp_db) = app_with(HostnamePolicy::Fqdn);
+    let schema: serde_json::Value =
+        serde_json::from_str(&get_html(&app, "/api/v1/schema").await).unwrap();
+    let hostname = &schema["properties"]["hostname"];
+    assert_eq!(hostname["maxLength"], 253);
+    assert!(hostname["pattern"].as_str().unwrap().contains(r"(\."));
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use tower::ServiceExt;
+
+async fn post(app: &Router, body: String, key: Option<&str>) -> Response {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(key) = key {
+        request = request.header("idempotency-key", key);
+    }
+    app.clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap()
+}
+
+fn checkin_for(serial: &str) -> String {
+    common::checkin_json_with(
+        "LAPTOP-001",
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        "2024-01-15T10:30:00Z",
+    )
+}
+
+fn with_checkin_id(body: &str, id: &str) -> String {
+    let mut payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    payload["checkin_id"] = serde_json::Value::String(id.to_string());
+    payload.to_string()
+}
+
+fn checkin_count(db: &tempfile::NamedTempFile) -> i64 {
+    let conn = rusqlite::Connection::open(db.path()).unwrap();
+    conn.query_row("SELECT COUNT(*) FROM checkins", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_header_replay_is_not_stored_again() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let first = post(&app, checkin_for("SN001"), Some("key-1")).await;
+    assert_eq!(first.status(), StatusCode::OK);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+
+    let replay = post(&app, checkin_for("SN001"), Some("key-1")).await;
+    assert_eq!(replay.status(), StatusCode::OK);
+    assert_eq!(replay.headers()["idempotent-replayed"], "true");
+
+    assert_eq!(checkin_count(&temp_db), 1);
+
+    // A different key is a new submission
+    let other = post(&app, checkin_for("SN001"), Some("key-2")).await;
+    assert_eq!(other.status(), StatusCode::OK);
+    assert_eq!(checkin_count(&temp_db), 2);
+}
+
+#[tokio::test]
+async fn test_payload_checkin_id_replay() {
+    let (app, temp_db) = common::setup_test_app();
+    let body = with_checkin_id(&checkin_for("SN001"), "6f1c2a9e-1b7d-4a53");
+
+    assert_eq!(
+        post(&app, body.clone(), None).await.status(),
+        StatusCode::OK
+    );
+    let replay = post(&app, body, None).await;
+    assert_eq!(replay.status(), StatusCode::OK);
+    assert_eq!(replay.headers()["idempotent-replayed"], "true");
+
+    assert_eq!(checkin_count(&temp_db), 1);
+}
+
+#[tokio::test]
+async fn test_replay_bypasses_min_interval() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.min_checkin_interval_secs = 300;
+    });
+
+    assert_eq!(
+        post(&app, checkin_for("SN001"), Some("key-1"))
+            .await
+            .status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        post(&app, checkin_for("SN001"), Some("key-1"))
+            .await
+            .status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        post(&app, checkin_for("SN001"), Some("key-2"))
+            .await
+            .status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(checkin_count(&temp_db), 1);
+}
+
+#[tokio::test]
+async fn test_key_reused_for_other_device_conflicts() {
+    let (app, temp_db) = common::setup_test_app();
+
+    post(&app, checkin_for("SN001"), Some("shared-key")).await;
+    let response = post(&app, checkin_for("SN002"), Some("shared-key")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    assert_eq!(checkin_count(&temp_db), 1);
+}
+
+#[tokio::test]
+async fn test_invalid_key_is_rejected() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let response = post(&app, checkin_for("SN001"), Some(&"k".repeat(129))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let body = with_checkin_id(&checkin_for("SN001"), "");
+    assert_eq!(
+        post(&app, body, None).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(checkin_count(&temp_db), 0);
+}
+
+#[tokio::test]
+async fn test_expired_key_is_accepted_again() {
+    let (app, temp_db) = common::setup_test_app();
+
+    post(&app, checkin_for("SN001"), Some("key-1")).await;
+
+    // Age the stored key past the default retention window
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    conn.execute(
+        "UPDATE idempotency_keys SET created_at_utc = '2000-01-01T00:00:00Z'",
+        [],
+    )
+    .unwrap();
+
+    let response = post(&app, checkin_for("SN001"), Some("key-1")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert_eq!(checkin_count(&temp_db), 2);
+
+    // The expired entry was pruned and the key now points at the new check-in
+    let (keys, checkin_id): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), MAX(checkin_id) FROM idempotency_keys",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((keys, checkin_id), (1, 2));
+}
@@ -0,0 +1,344 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use inventory_server::identity::{fallback_serial, FALLBACK_PREFIX};
+use tower::ServiceExt;
+
+async fn post(app: &Router, uri: &str, body: String) -> StatusCode {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr: SocketAddr = "172.16.5.9:50000".parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// Submit one of the admin page's forms; `form` is already URL-encoded
+async fn submit(app: &Router, uri: &str, form: &str) -> (StatusCode, Option<String>) {
+    let (_, page) = common::get(app, "/admin/identity").await;
+    let body = format!("csrf_token={}&{form}", common::csrf_token(&page));
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .map(|l| l.to_str().unwrap().to_string());
+    (response.status(), location)
+}
+
+/// A check-in with one drive
+fn checkin(host: &str, serial: &str, drive: &str, timestamp: &str) -> String {
+    serde_json::json!({
+        "hostname": host,
+        "laptop_serial": serial,
+        "ip_address": "10.0.0.1",
+        "logged_in_user": "alice",
+        "timestamp_utc": timestamp,
+        "drives": [{
+            "device_id": "\\\\.\\PhysicalDrive0",
+            "model": "Samsung SSD 970 EVO",
+            "serial_number": drive,
+            "size_bytes": 500107862016_i64,
+            "media_type": "SSD"
+        }]
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn test_placeholder_serials_get_fallback_identities() {
+    let (app, _temp_db) = common::setup_test_app();
+    for (host, drive) in [("LAPTOP-001", "WD-1"), ("LAPTOP-002", "WD-2")] {
+        let body = checkin(
+            host,
+            "To be filled by O.E.M.",
+            drive,
+            "2024-03-01T09:00:00Z",
+        );
+        assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    }
+    // The same machine checking in again keeps its identity
+    let body = checkin(
+        "LAPTOP-001",
+        "To be filled by O.E.M.",
+        "WD-1",
+        "2024-03-02T09:00:00Z",
+    );
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+
+    let first = fallback_serial("LAPTOP-001", ["WD-1"].into_iter());
+    let second = fallback_serial("LAPTOP-002", ["WD-2"].into_iter());
+    assert!(first.starts_with(FALLBACK_PREFIX));
+    let (_, index) = common::get(&app, "/").await;
+    assert!(index.contains(&first));
+    assert!(index.contains(&second));
+    assert!(!index.contains(">To be filled by O.E.M.<"));
+
+    let (status, page) = common::get(&app, &format!("/device/{first}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("Check-in History (2)"));
+    assert!(page.contains("<td>To be filled by O.E.M.</td>"));
+    assert_eq!(
+        common::get(&app, "/device/To%20be%20filled%20by%20O.E.M.")
+            .await
+            .0,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_collision_detected_and_split_by_hostname() {
+    let (app, _temp_db) = common::setup_test_app();
+    let body = checkin("LAPTOP-001", "SN1", "WD-1", "2024-03-01T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    // Renamed, same drive: one machine
+    let body = checkin("LAPTOP-001B", "SN1", "WD-1", "2024-03-02T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let (_, page) = common::get(&app, "/admin/identity").await;
+    assert!(page.contains("No collisions detected"));
+
+    // Another hostname and another drive: another machine
+    let body = checkin("DESKTOP-9", "SN1", "WD-9", "2024-03-03T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let (_, page) = common::get(&app, "/admin/identity").await;
+    assert!(page.contains("Serial Collisions (1)"));
+    assert!(page.contains("<td>DESKTOP-9</td>"));
+    let (_, device) = common::get(&app, "/device/SN1").await;
+    assert!(device.contains("look like they come from more than one machine"));
+
+    // The split page lists the device's hostnames
+    let (status, page) = common::get(&app, "/admin/identity?serial=SN1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("<td>LAPTOP-001B</td>"));
+    assert!(page.contains("<td>DESKTOP-9</td>"));
+    assert_eq!(
+        common::get(&app, "/admin/identity?serial=NOPE").await.0,
+        StatusCode::NOT_FOUND
+    );
+
+    // Split without a new serial moves the host to its fallback identity
+    let (status, location) = submit(
+        &app,
+        "/admin/identity/split",
+        "serial=SN1&hostname=DESKTOP-9&new_serial=&changed_by=bob&reason=Shared+serial",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let fallback = fallback_serial("DESKTOP-9", ["WD-9"].into_iter());
+    assert_eq!(location, Some(format!("/device/{fallback}")));
+
+    let (_, moved) = common::get(&app, &format!("/device/{fallback}")).await;
+    assert!(moved.contains("Check-in History (1)"));
+    assert!(moved.contains("<td>SN1</td>"));
+    let (_, device) = common::get(&app, "/device/SN1").await;
+    assert!(device.contains("Check-in History (2)"));
+    assert!(device.contains("<span>LAPTOP-001B</span>"));
+    assert!(!device.contains("more than one machine"));
+
+    // Later check-ins from that host follow the remap
+    let body = checkin("DESKTOP-9", "SN1", "WD-9", "2024-03-04T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let (_, moved) = common::get(&app, &format!("/device/{fallback}")).await;
+    assert!(moved.contains("Check-in History (2)"));
+    let (_, page) = common::get(&app, "/admin/identity").await;
+    assert!(page.contains("Serial Remaps (1)"));
+    assert!(page.contains("No collisions detected"));
+
+    // Removing the remap stores them under the reported serial again
+    let (status, _) = submit(
+        &app,
+        "/admin/identity/remaps/delete",
+        "reported_serial=SN1&hostname=DESKTOP-9",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let body = checkin("DESKTOP-9", "SN1", "WD-9", "2024-03-05T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let (_, device) = common::get(&app, "/device/SN1").await;
+    assert!(device.contains("Check-in History (3)"));
+}
+
+#[tokio::test]
+async fn test_split_refuses_bad_requests() {
+    let (app, _temp_db) = common::setup_test_app();
+    let body = checkin("LAPTOP-001", "SN1", "WD-1", "2024-03-01T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let body = checkin("LAPTOP-002", "SN1", "WD-2", "2024-03-02T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+
+    for (form, expected) in [
+        // No check-ins from that host
+        (
+            "serial=SN1&hostname=OTHER&changed_by=bob&reason=x",
+            StatusCode::BAD_REQUEST,
+        ),
+        // Who and why are required
+        (
+            "serial=SN1&hostname=LAPTOP-002&changed_by=&reason=x",
+            StatusCode::BAD_REQUEST,
+        ),
+        // Placeholders can't be chosen as a device's serial
+        (
+            "serial=SN1&hostname=LAPTOP-002&new_serial=Default+string&changed_by=bob&reason=x",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "serial=NOPE&hostname=LAPTOP-002&changed_by=bob&reason=x",
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        assert_eq!(
+            submit(&app, "/admin/identity/split", form).await.0,
+            expected
+        );
+    }
+
+    // Splitting off one host leaves the other as the device's only one
+    let (status, _) = submit(
+        &app,
+        "/admin/identity/split",
+        "serial=SN1&hostname=LAPTOP-002&new_serial=SN2&changed_by=bob&reason=x",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (status, _) = submit(
+        &app,
+        "/admin/identity/split",
+        "serial=SN1&hostname=LAPTOP-001&new_serial=SN3&changed_by=bob&reason=x",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_merge_moves_history_and_remaps() {
+    let (app, _temp_db) = common::setup_test_app();
+    // A motherboard swap changed the machine's serial
+    let body = checkin("LAPTOP-001", "OLD-SN", "WD-1", "2024-03-01T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    let body = checkin("LAPTOP-001", "NEW-SN", "WD-1", "2024-03-02T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+
+    let (_, page) = common::get(&app, "/device/OLD-SN").await;
+    let token = common::csrf_token(&page).to_string();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/device/OLD-SN/tags")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("csrf_token={token}&tag=finance")))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    for form in [
+        "source=OLD-SN&target=OLD-SN&changed_by=bob&reason=x",
+        "source=OLD-SN&target=NEW-SN&changed_by=bob&reason=",
+    ] {
+        assert_eq!(
+            submit(&app, "/admin/identity/merge", form).await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+    assert_eq!(
+        submit(
+            &app,
+            "/admin/identity/merge",
+            "source=OLD-SN&target=NOPE&changed_by=bob&reason=x"
+        )
+        .await
+        .0,
+        StatusCode::NOT_FOUND
+    );
+
+    let (status, location) = submit(
+        &app,
+        "/admin/identity/merge",
+        "source=OLD-SN&target=NEW-SN&changed_by=bob&reason=Motherboard+swap",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(location.as_deref(), Some("/device/NEW-SN"));
+
+    assert_eq!(
+        common::get(&app, "/device/OLD-SN").await.0,
+        StatusCode::NOT_FOUND
+    );
+    let (_, device) = common::get(&app, "/device/NEW-SN").await;
+    assert!(device.contains("Check-in History (2)"));
+    assert!(device.contains("<td>OLD-SN</td>"));
+    assert!(device.contains("finance"));
+
+    // A stray check-in with the old serial lands on the merged device
+    let body = checkin("LAPTOP-001", "OLD-SN", "WD-1", "2024-03-03T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+    assert_eq!(
+        common::get(&app, "/device/OLD-SN").await.0,
+        StatusCode::NOT_FOUND
+    );
+    let (_, device) = common::get(&app, "/device/NEW-SN").await;
+    assert!(device.contains("Check-in History (3)"));
+    let (_, page) = common::get(&app, "/admin/identity").await;
+    assert!(page.contains("Serial Remaps (1)"));
+    assert!(page.contains("<td>Motherboard swap</td>"));
+}
+
+#[tokio::test]
+async fn test_identity_forms_require_csrf_token() {
+    let (app, _temp_db) = common::setup_test_app();
+    let body = checkin("LAPTOP-001", "SN1", "WD-1", "2024-03-01T09:00:00Z");
+    assert_eq!(post(&app, "/checkin", body).await, StatusCode::OK);
+
+    for (uri, form) in [
+        (
+            "/admin/identity/merge",
+            "source=SN1&target=SN2&changed_by=bob&reason=x",
+        ),
+        (
+            "/admin/identity/split",
+            "serial=SN1&hostname=LAPTOP-001&changed_by=bob&reason=x",
+        ),
+        ("/admin/identity/collisions/dismiss", "serial=SN1"),
+        ("/admin/identity/remaps/delete", "reported_serial=SN1"),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(form))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+    let (_, page) = common::get(&app, "/admin/identity").await;
+    assert!(page.contains(r#"<span class="tag">Default string</span>"#));
+}
@@ -114,3 +114,40 @@ async fn test_index_shows_drive_info() {
         "Page should display drive serial number"
     );
 }
+
+#[tokio::test]
+async fn test_index_escapes_device_fields() {
+    let (app, temp_db) = common::setup_test_app();
+    let db_path = temp_db.path().to_str().unwrap();
+
+    let drives_json = serde_json::json!([
+        { "device_id": "PhysicalDrive0", "model": "SSD", "serial_number": "<script>alert(1)</script>" },
+        { "device_id": "PhysicalDrive1", "model": "HDD", "serial_number": "WD-2" }
+    ])
+    .to_string();
+
+    let conn = rusqlite::Connection::open(db_path).unwrap();
+    conn.execute(
+        "INSERT INTO laptops (laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params!["SN'/1", "laptop1", "10.0.0.1", "user1", "2024-01-15T10:00:00Z", drives_json],
+    )
+    .unwrap();
+    drop(conn);
+
+    let response = app
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body_str = String::from_utf8_lossy(&body);
+
+    assert!(
+        !body_str.contains("<script>alert"),
+        "drive serial must be escaped"
+    );
+    assert!(body_str.contains("&lt;script&gt;alert(1)&lt;/script&gt;<br>WD-2"));
+    // The serial can't end the script string in the row's onclick
+    assert!(body_str.contains("window.location='/device/SN%27%2F1'"));
+}
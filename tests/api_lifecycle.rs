@@ -0,0 +1,260 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use inventory_server::{
+    lifecycle::LifecycleState,
+    snapshot::{DeviceResponse, DevicesResponse},
+};
+use tower::ServiceExt;
+
+async fn post(app: &Router, uri: &str, body: String) -> StatusCode {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr: SocketAddr = "172.16.5.9:50000".parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// Record a state change from the device's page; `form` is already URL-encoded
+async fn change_state(app: &Router, serial: &str, form: &str) -> StatusCode {
+    let (_, page) = common::get(app, &format!("/device/{serial}")).await;
+    let body = format!("csrf_token={}&{form}", common::csrf_token(&page));
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/device/{serial}/lifecycle"))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+/// Two devices that checked in at the start of March 2024
+async fn fleet() -> (Router, tempfile::NamedTempFile) {
+    let (app, temp_db) = common::setup_test_app();
+    common::seed(
+        &app,
+        &[
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.1",
+                "alice",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-002",
+                "SN2",
+                "10.0.0.1",
+                "alice",
+                "2024-03-01T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+    (app, temp_db)
+}
+
+#[tokio::test]
+async fn test_change_state_records_history() {
+    let (app, _temp_db) = fleet().await;
+
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(page.contains("<h2>Lifecycle</h2>"));
+    assert!(page.contains(r#"<a href="/?state=deployed">Deployed</a>"#));
+
+    let status = change_state(
+        &app,
+        "SN1",
+        "state=in_repair&changed_by=bob&reason=Broken+hinge",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let status = change_state(
+        &app,
+        "SN1",
+        "state=retired&changed_by=carol&reason=Wiped+%3Cok%3E",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(page.contains(r#"<a href="/?state=retired">Retired</a>"#));
+    let retired = page.find("<td>carol</td>").unwrap();
+    let repair = page.find("<td>bob</td>").unwrap();
+    assert!(retired < repair, "latest change first");
+    assert!(page.contains("<td>Wiped &lt;ok&gt;</td>"));
+    assert!(page.contains("<td>In repair</td>"));
+
+    // Unknown states, and changes without who or why, are refused
+    for form in [
+        "state=stolen&changed_by=bob&reason=x",
+        "state=lost&changed_by=&reason=x",
+        "state=lost&changed_by=bob&reason=+",
+    ] {
+        assert_eq!(
+            change_state(&app, "SN1", form).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    assert!(page.contains(r#"<a href="/?state=retired">Retired</a>"#));
+}
+
+#[tokio::test]
+async fn test_change_state_requires_device_and_token() {
+    let (app, _temp_db) = fleet().await;
+    let (_, page) = common::get(&app, "/device/SN1").await;
+    let token = common::csrf_token(&page).to_string();
+
+    let send = |serial: &str, body: String| {
+        app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/device/{serial}/lifecycle"))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+    let form = "state=lost&changed_by=bob&reason=Stolen";
+    let response = send("SN1", form.to_string()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send("SN9", format!("csrf_token={token}&{form}"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_index_hides_retired_devices() {
+    let (app, _temp_db) = fleet().await;
+    let form = "state=retired&changed_by=bob&reason=Wiped";
+    assert_eq!(change_state(&app, "SN2", form).await, StatusCode::SEE_OTHER);
+
+    let (_, index) = common::get(&app, "/").await;
+    assert!(index.contains("LAPTOP-001"));
+    assert!(!index.contains("LAPTOP-002"));
+    assert!(index.contains("1 retired or disposed device is not listed."));
+
+    let (_, index) = common::get(&app, "/?state=all").await;
+    assert!(index.contains("LAPTOP-001"));
+    assert!(index.contains("LAPTOP-002"));
+    assert!(index.contains(r#"<span class="state">Retired</span>"#));
+    assert!(!index.contains("not listed"));
+
+    let (_, index) = common::get(&app, "/?state=retired").await;
+    assert!(!index.contains("LAPTOP-001"));
+    assert!(index.contains("LAPTOP-002"));
+
+    let (_, index) = common::get(&app, "/?state=deployed").await;
+    assert!(index.contains(r#"href="/export/devices.csv?state=deployed""#));
+
+    let (status, _) = common::get(&app, "/?state=gone").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Before it was retired, the device was listed
+    let (_, index) = common::get(&app, "/?as_of=2024-03-02").await;
+    assert!(index.contains("LAPTOP-002"));
+}
+
+#[tokio::test]
+async fn test_checkin_after_retirement_is_flagged() {
+    let (app, _temp_db) = fleet().await;
+    let form = "state=retired&changed_by=bob&reason=Wiped";
+    assert_eq!(change_state(&app, "SN2", form).await, StatusCode::SEE_OTHER);
+
+    // A late delivery taken before the retirement is not flagged
+    let late = common::checkin_json_with(
+        "LAPTOP-002",
+        "SN2",
+        "10.0.0.2",
+        None,
+        "2024-03-01T10:00:00Z",
+    );
+    assert_eq!(post(&app, "/checkin", late).await, StatusCode::OK);
+    let (_, index) = common::get(&app, "/?state=flagged").await;
+    assert!(!index.contains("LAPTOP-002"));
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let again = common::checkin_json_with("LAPTOP-002", "SN2", "10.0.0.2", None, &now);
+    assert_eq!(post(&app, "/checkin", again.clone()).await, StatusCode::OK);
+    let batch = format!("[{again}]");
+    assert_eq!(post(&app, "/checkin/batch", batch).await, StatusCode::OK);
+
+    // Flagged devices show up even while retired ones are hidden
+    let (_, index) = common::get(&app, "/").await;
+    assert!(!index.contains("LAPTOP-002"));
+    assert!(index.contains(r#"<a href="/?state=flagged">Checked in while out of service</a> (1)"#));
+    let (_, index) = common::get(&app, "/?state=flagged").await;
+    assert!(index.contains("LAPTOP-002"));
+    assert!(index.contains(">checked in</span>"));
+
+    let (_, page) = common::get(&app, "/device/SN2").await;
+    assert!(page.contains("This device is marked retired but checked in 2 times since"));
+
+    let (_, body) = common::get(&app, "/api/v1/devices/SN2").await;
+    let device = serde_json::from_str::<DeviceResponse>(&body)
+        .unwrap()
+        .device;
+    assert_eq!(device.lifecycle_state, LifecycleState::Retired);
+    assert_eq!(device.lifecycle_alert.unwrap().checkins, 2);
+
+    // Recording a new state settles the alert
+    let form = "state=deployed&changed_by=bob&reason=Reissued";
+    assert_eq!(change_state(&app, "SN2", form).await, StatusCode::SEE_OTHER);
+    let (_, page) = common::get(&app, "/device/SN2").await;
+    assert!(!page.contains("This device is marked"));
+    let (_, index) = common::get(&app, "/").await;
+    assert!(index.contains("LAPTOP-002"));
+}
+
+#[tokio::test]
+async fn test_exports_include_state() {
+    let (app, _temp_db) = fleet().await;
+    let form = "state=lost&changed_by=bob&reason=Left+on+a+train";
+    assert_eq!(change_state(&app, "SN1", form).await, StatusCode::SEE_OTHER);
+
+    let (_, body) = common::get(&app, "/api/v1/devices").await;
+    let devices = serde_json::from_str::<DevicesResponse>(&body)
+        .unwrap()
+        .devices;
+    let state = |serial: &str| {
+        devices
+            .iter()
+            .find(|d| d.laptop_serial == serial)
+            .unwrap()
+            .lifecycle_state
+    };
+    assert_eq!(state("SN1"), LifecycleState::Lost);
+    assert_eq!(state("SN2"), LifecycleState::Deployed);
+    assert!(body.contains(r#""lifecycle_state":"lost""#));
+
+    // As of before the change, the device was deployed
+    let (_, body) = common::get(&app, "/api/v1/devices/SN1?as_of=2024-03-02").await;
+    let device = serde_json::from_str::<DeviceResponse>(&body)
+        .unwrap()
+        .device;
+    assert_eq!(device.lifecycle_state, LifecycleState::Deployed);
+
+    let (_, csv) = common::get(&app, "/export/devices.csv?state=lost").await;
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2, "{csv}");
+    assert!(lines[1].starts_with("SN1,LAPTOP-001,"));
+    assert!(lines[1].ends_with(",Lost,"), "{csv}");
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use inventory_server::{config::IpNetwork, network::NetworkResponse};
+
+async fn network(app: &Router, q: &str) -> NetworkResponse {
+    let uri = format!("/api/v1/network?q={}", q.replace(' ', "+"));
+    let (status, body) = common::get(app, &uri).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    serde_json::from_str(&body).unwrap()
+}
+
+fn serials(response: &NetworkResponse) -> Vec<&str> {
+    response
+        .results
+        .iter()
+        .map(|r| r.laptop_serial.as_str())
+        .collect()
+}
+
+/// SN1 moves from the office to the VPN; SN2 stays in the office; SN3 is on IPv6
+async fn fleet() -> (Router, tempfile::NamedTempFile) {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        let net = |s: &str| IpNetwork::try_from(s.to_string()).unwrap();
+        let subnets = &mut state.settings_mut().subnets;
+        subnets.insert(
+            "HQ".to_string(),
+            vec![net("10.1.0.0/16"), net("fd00:1::/48")],
+        );
+        subnets.insert("VPN".to_string(), vec![net("10.200.0.0/20")]);
+    });
+    common::seed(
+        &app,
+        &[
+            (
+                "LAPTOP-1",
+                "SN1",
+                "10.1.4.20",
+                "jdoe",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-2",
+                "SN2",
+                "10.1.7.3",
+                "jdoe",
+                "2024-03-02T09:00:00Z",
+            ),
+            (
+                "LAPTOP-3",
+                "SN3",
+                "fd00:1::20",
+                "jdoe",
+                "2024-03-06T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+    // Behind NAT: reported home address, observed VPN address
+    let body = common::checkin_json_with(
+        "LAPTOP-1",
+        "SN1",
+        "192.168.0.10",
+        Some("jdoe"),
+        "2024-03-05T09:00:00Z",
+    );
+    common::post_checkin_from(&app, body, "10.200.1.9").await;
+    (app, temp_db)
+}
+
+#[tokio::test]
+async fn test_cidr_search_current_and_past() {
+    let (app, _temp_db) = fleet().await;
+
+    let response = network(&app, "10.1.0.0/16").await;
+    assert_eq!(response.networks, ["10.1.0.0/16"]);
+    assert_eq!(serials(&response), ["SN2", "SN1"]);
+    assert!(response.results[0].current);
+    // SN1 has since moved to the VPN
+    let sn1 = &response.results[1];
+    assert!(!sn1.current);
+    assert_eq!(sn1.ip_address, "10.1.4.20");
+    assert_eq!(sn1.location.as_deref(), Some("HQ"));
+    assert_eq!(sn1.matching_checkins, 1);
+    assert_eq!(sn1.last_seen_utc, "2024-03-01T09:00:00Z");
+
+    // Host bits are ignored, and a single address works too
+    assert_eq!(
+        serials(&network(&app, "10.1.99.99/16").await),
+        ["SN2", "SN1"]
+    );
+    assert_eq!(serials(&network(&app, "10.1.7.3").await), ["SN2"]);
+    assert!(network(&app, "10.1.7.4").await.results.is_empty());
+}
+
+#[tokio::test]
+async fn test_search_matches_observed_address() {
+    let (app, _temp_db) = fleet().await;
+
+    let response = network(&app, "10.200.0.0/20").await;
+    assert_eq!(serials(&response), ["SN1"]);
+    assert!(response.results[0].current);
+    assert_eq!(response.results[0].source_ip.as_deref(), Some("10.200.1.9"));
+    assert_eq!(response.results[0].location.as_deref(), Some("VPN"));
+}
+
+#[tokio::test]
+async fn test_ipv6_and_subnet_names() {
+    let (app, _temp_db) = fleet().await;
+
+    assert_eq!(serials(&network(&app, "fd00:1::/64").await), ["SN3"]);
+    assert!(network(&app, "fd00:2::/64").await.results.is_empty());
+
+    // A subnet name searches all its networks
+    let response = network(&app, "hq").await;
+    assert_eq!(response.networks, ["10.1.0.0/16", "fd00:1::/48"]);
+    assert_eq!(serials(&response), ["SN3", "SN2", "SN1"]);
+}
+
+#[tokio::test]
+async fn test_invalid_network_query() {
+    let (app, _temp_db) = fleet().await;
+
+    for uri in [
+        "/api/v1/network",
+        "/api/v1/network?q=10.1.0.0/33",
+        "/api/v1/network?q=Paris",
+    ] {
+        let (status, body) = common::get(&app, uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        assert!(body.contains("network query"), "{body}");
+    }
+    let (status, _) = common::get(&app, "/network?q=nowhere").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_pages_show_location() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<th>Location</th>"));
+    assert!(body.contains("<td>VPN</td>"));
+    assert!(body.contains("<td>HQ</td>"));
+
+    let (_, body) = common::get(&app, "/device/SN1").await;
+    assert!(body.contains(r#"<a href="/network?q=VPN">VPN</a>"#));
+
+    let (status, body) = common::get(&app, "/network").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("10.1.0.0/16, fd00:1::/48"));
+
+    let (status, body) = common::get(&app, "/network?q=10.1.0.0%2F16").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("2 devices seen in 10.1.0.0/16"));
+}
+
+#[tokio::test]
+async fn test_index_without_subnets_has_no_location() {
+    let (app, _temp_db) = common::setup_test_app();
+    let (_, body) = common::get(&app, "/").await;
+    assert!(!body.contains("<th>Location</th>"));
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::rate_limit::Limit;
+use tower::ServiceExt;
+
+fn checkin_request(body: String, from: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    if let Some(addr) = from {
+        let addr: SocketAddr = addr.parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(addr));
+    }
+    request
+}
+
+async fn post(app: &Router, body: String, from: Option<&str>) -> axum::response::Response {
+    app.clone()
+        .oneshot(checkin_request(body, from))
+        .await
+        .unwrap()
+}
+
+fn checkin_for(serial: &str) -> String {
+    common::checkin_json_with(
+        "LAPTOP-001",
+        serial,
+        "192.168.1.100",
+        Some("testuser"),
+        "2024-01-15T10:30:00Z",
+    )
+}
+
+#[tokio::test]
+async fn test_per_ip_limit_returns_429_with_retry_after() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.per_ip_per_minute = 2;
+    });
+
+    for serial in ["SN001", "SN002"] {
+        let response = post(&app, checkin_for(serial), Some("10.0.0.5:50000")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let response = post(&app, checkin_for("SN003"), Some("10.0.0.5:50001")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Too many requests");
+
+    // Another client is unaffected
+    let response = post(&app, checkin_for("SN003"), Some("10.0.0.6:50000")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_default_config_never_rate_limits() {
+    // As a fleet behind one NAT address or proxy looks: every agent shares a source IP
+    let (app, _temp_db) = common::setup_test_app();
+    for n in 0..150 {
+        let response = post(
+            &app,
+            checkin_for(&format!("SN{n:03}")),
+            Some("10.0.0.5:50000"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK, "check-in {n}");
+    }
+    for _ in 0..20 {
+        let response = post(&app, checkin_for("SN000"), Some("10.0.0.5:50000")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_per_serial_limit_applies_across_sources() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.per_serial_per_minute = 1;
+    });
+
+    let response = post(&app, checkin_for("SN001"), Some("10.0.0.5:50000")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = post(&app, checkin_for("SN001"), Some("10.0.0.6:50000")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let response = post(&app, checkin_for("SN002"), Some("10.0.0.6:50000")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_min_interval_between_device_checkins() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.min_checkin_interval_secs = 300;
+    });
+
+    let response = post(&app, checkin_for("SN001"), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = post(&app, checkin_for("SN001"), None).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((299..=300).contains(&retry_after));
+
+    // The rejected check-in was not stored
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM checkins", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_oversized_body_returns_413() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().limits.max_body_kb = 1;
+    });
+
+    let mut payload: serde_json::Value = serde_json::from_str(&checkin_for("SN001")).unwrap();
+    payload["logged_in_user"] = serde_json::Value::String("a".repeat(2048));
+
+    let response = post(&app, payload.to_string(), Some("10.0.0.5:50000")).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_rejections_are_counted() {
+    let temp_db = tempfile::NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap().to_string();
+    inventory_server::db::open_and_init(&db_path).unwrap();
+    let mut state = inventory_server::AppState::new(db_path);
+    state.settings_mut().limits.per_serial_per_minute = 1;
+    state.settings_mut().limits.max_body_kb = 1;
+    let state = std::sync::Arc::new(state);
+    let app = inventory_server::routes::build_router(state.clone());
+
+    post(&app, checkin_for("SN001"), None).await;
+    post(&app, checkin_for("SN001"), None).await;
+    post(&app, "x".repeat(4096), None).await;
+
+    assert_eq!(state.limiter.rejected(Limit::Serial), 1);
+    assert_eq!(state.limiter.rejected(Limit::BodySize), 1);
+    assert_eq!(state.limiter.rejected(Limit::SourceIp), 0);
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+fn request_id_header(response: &axum::response::Response) -> String {
+    response
+        .headers()
+        .get("x-request-id")
+        .expect("response should carry x-request-id")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_request_id_generated_when_absent() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let response = app
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let id = request_id_header(&response);
+    assert!(
+        uuid::Uuid::parse_str(&id).is_ok(),
+        "expected UUID, got {id}"
+    );
+}
+
+#[tokio::test]
+async fn test_request_id_honored_when_supplied() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .header("x-request-id", "agent-7f3a-retry-1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(request_id_header(&response), "agent-7f3a-retry-1");
+}
+
+#[tokio::test]
+async fn test_unprintable_request_id_replaced() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("x-request-id", "has spaces in it")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let id = request_id_header(&response);
+    assert_ne!(id, "has spaces in it");
+    assert!(uuid::Uuid::parse_str(&id).is_ok());
+}
+
+#[tokio::test]
+async fn test_checkin_error_body_includes_request_id() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let payload = serde_json::json!({
+        "hostname": "valid-hostname",
+        "laptop_serial": "SN001",
+        "ip_address": "not-an-ip-address",
+        "logged_in_user": null,
+        "timestamp_utc": "2024-01-15T10:00:00Z",
+        "drives": []
+    })
+    .to_string();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header("content-type", "application/json")
+                .header("x-request-id", "failed-checkin-42")
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(request_id_header(&response), "failed-checkin-42");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"], "Invalid input data");
+    assert_eq!(body["request_id"], "failed-checkin-42");
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+fn post_checkin(body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn payload() -> serde_json::Value {
+    serde_json::from_str(&common::valid_checkin_json()).unwrap()
+}
+
+#[tokio::test]
+async fn test_explicit_current_version_is_accepted() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let mut body = payload();
+    body["schema_version"] = serde_json::json!(1);
+    let (status, _) = send(&app, post_checkin(body)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_unsupported_version_returns_400_naming_it() {
+    let (app, temp_db) = common::setup_test_app();
+
+    let mut body = payload();
+    body["schema_version"] = serde_json::json!(99);
+    let (status, json) = send(&app, post_checkin(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Unsupported schema_version");
+    let detail = json["detail"].as_str().unwrap();
+    assert!(detail.contains("99"), "{detail}");
+    assert!(detail.contains("supported: 1"), "{detail}");
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM checkins", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn test_batch_item_with_unsupported_version_is_rejected() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let mut old = payload();
+    old["schema_version"] = serde_json::json!(0);
+    let body = serde_json::json!([payload(), old]);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/checkin/batch")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let (status, json) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["accepted"], 1);
+    assert_eq!(json["results"][1]["status"], "rejected");
+    assert!(json["results"][1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("schema_version 0 is not supported"));
+}
+
+#[tokio::test]
+async fn test_schema_endpoint_describes_payload() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let request = Request::builder()
+        .uri("/api/v1/schema")
+        .body(Body::empty())
+        .unwrap();
+    let (status, schema) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        schema["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    assert_eq!(schema["properties"]["schema_version"]["maximum"], 1);
+    for field in ["hostname", "laptop_serial", "drives", "timestamp_utc"] {
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!(field)));
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use inventory_server::{config::Charset, search::SearchResponse};
+
+async fn search(app: &Router, q: &str) -> SearchResponse {
+    let uri = format!("/api/v1/search?q={}", q.replace(' ', "+"));
+    let (status, body) = common::get(app, &uri).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    serde_json::from_str(&body).unwrap()
+}
+
+fn checkin(serial: &str, hostname: &str, user: &str, timestamp: &str) -> String {
+    serde_json::json!({
+        "hostname": hostname,
+        "laptop_serial": serial,
+        "ip_address": "192.168.1.100",
+        "logged_in_user": user,
+        "timestamp_utc": timestamp,
+        "drives": [{
+            "device_id": "\\\\.\\PhysicalDrive0",
+            "model": "Samsung SSD 970 EVO",
+            "serial_number": format!("S4EV-{serial}")
+        }]
+    })
+    .to_string()
+}
+
+async fn fleet() -> (Router, tempfile::NamedTempFile) {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().validation.charset = Charset::Unicode;
+    });
+    common::post_checkin(
+        &app,
+        checkin("SN1", "LAPTOP-1", "CONTOSO\\jdoe", "2024-01-01T10:00:00Z"),
+    )
+    .await;
+    common::post_checkin(
+        &app,
+        checkin("SN1", "LAPTOP-1", "CONTOSO\\asmith", "2024-01-02T10:00:00Z"),
+    )
+    .await;
+    common::post_checkin(
+        &app,
+        checkin("SN2", "LAPTOP-2", "José", "2024-01-03T10:00:00Z"),
+    )
+    .await;
+    (app, temp_db)
+}
+
+#[tokio::test]
+async fn test_search_includes_past_checkins() {
+    let (app, _temp_db) = fleet().await;
+
+    let response = search(&app, "jdoe").await;
+    assert_eq!(response.results.len(), 1);
+    let result = &response.results[0];
+    assert_eq!(result.laptop_serial, "SN1");
+    assert_eq!(result.hostname, "LAPTOP-1");
+    assert!(!result.current, "jdoe is no longer the current user");
+    assert_eq!(result.matching_checkins, 1);
+    assert_eq!(result.last_match_utc, "2024-01-01T10:00:00Z");
+    assert_eq!(result.matches[0].field, "logged_in_user");
+    assert_eq!(result.matches[0].value, "CONTOSO\\jdoe");
+    assert_eq!(result.matches[0].highlighted, "CONTOSO\\<mark>jdoe</mark>");
+
+    let result = &search(&app, "asmith").await.results[0];
+    assert!(result.current);
+}
+
+#[tokio::test]
+async fn test_search_orders_checkins_by_instant() {
+    let (app, _temp_db) = common::setup_test_app();
+    // As strings the -08:00 check-in sorts first, but it is the latest instant
+    for (user, timestamp) in [
+        ("jdoe", "2024-01-01T20:00:00-08:00"),
+        ("jdoe2", "2024-01-01T10:00:00Z"),
+        ("jdoe3", "2024-01-02T01:00:00+02:00"),
+    ] {
+        common::post_checkin(&app, checkin("SN1", "LAPTOP-1", user, timestamp)).await;
+    }
+
+    let response = search(&app, "jdoe").await;
+    let result = &response.results[0];
+    assert_eq!(result.matching_checkins, 3);
+    assert_eq!(result.first_match_utc, "2024-01-01T10:00:00Z");
+    assert_eq!(result.last_match_utc, "2024-01-01T20:00:00-08:00");
+    assert_eq!(result.matches[0].value, "jdoe");
+    assert!(result.current);
+}
+
+#[tokio::test]
+async fn test_search_matches_prefixes_in_every_field() {
+    let (app, _temp_db) = fleet().await;
+
+    let serials = |r: SearchResponse| -> Vec<String> {
+        r.results.into_iter().map(|r| r.laptop_serial).collect()
+    };
+    assert_eq!(serials(search(&app, "laptop-2").await), ["SN2"]);
+    assert_eq!(serials(search(&app, "SN").await).len(), 2);
+    assert_eq!(serials(search(&app, "s4ev-sn1").await), ["SN1"]);
+    assert_eq!(serials(search(&app, "samsung 970").await).len(), 2);
+    // Source address as well as the reported one
+    assert_eq!(serials(search(&app, "172.16.5").await).len(), 2);
+    assert_eq!(serials(search(&app, "192.168.1.10").await).len(), 2);
+    // Every word must match
+    assert_eq!(serials(search(&app, "jdoe laptop-2").await).len(), 0);
+    // Accents are ignored
+    assert_eq!(serials(search(&app, "jose").await), ["SN2"]);
+
+    let result = &search(&app, "s4ev").await.results[0];
+    let drive = result
+        .matches
+        .iter()
+        .find(|m| m.field == "drive_serials")
+        .unwrap();
+    assert!(
+        drive.highlighted.starts_with("<mark>S4EV</mark>"),
+        "{}",
+        drive.highlighted
+    );
+}
+
+#[tokio::test]
+async fn test_search_operators_are_literal() {
+    let (app, _temp_db) = fleet().await;
+
+    for q in ["OR", "NEAR(jdoe", "\"", "jdoe*", "-", "col:x", "^a"] {
+        let uri = format!("/api/v1/search?q={}", urlencode(q));
+        let (status, body) = common::get(&app, &uri).await;
+        assert_eq!(status, StatusCode::OK, "{q}: {body}");
+    }
+}
+
+#[tokio::test]
+async fn test_search_requires_a_query() {
+    let (app, _temp_db) = fleet().await;
+
+    let (status, body) = common::get(&app, "/api/v1/search?q=+").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Missing search query"));
+
+    let long = "a".repeat(300);
+    let (status, _) = common::get(&app, &format!("/api/v1/search?q={long}")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_search_limit() {
+    let (app, _temp_db) = fleet().await;
+
+    let (_, body) = common::get(&app, "/api/v1/search?q=laptop&limit=1").await;
+    let response: SearchResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(response.results.len(), 1);
+    assert!(response.truncated);
+}
+
+#[tokio::test]
+async fn test_search_page_highlights_and_escapes() {
+    let (app, _temp_db) = fleet().await;
+    common::post_checkin(
+        &app,
+        checkin("SN3", "LAPTOP-3", "<b>bold</b>", "2024-01-04T10:00:00Z"),
+    )
+    .await;
+
+    let (status, html) = common::get(&app, "/search?q=jdoe").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(html.contains("1 device matching"));
+    assert!(html.contains("CONTOSO\\<mark>jdoe</mark>"));
+    assert!(html.contains("past only"));
+    assert!(html.contains("/device/SN1"));
+
+    let (_, html) = common::get(&app, "/search?q=bold").await;
+    assert!(
+        html.contains("&lt;b&gt;<mark>bold</mark>&lt;/b&gt;"),
+        "{html}"
+    );
+
+    let (status, html) = common::get(&app, "/search").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!html.contains("matching"));
+}
+
+fn urlencode(s: &str) -> String {
+    s.bytes().map(|b| format!("%{b:02X}")).collect()
+}
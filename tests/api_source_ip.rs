@@ -0,0 +1,86 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::{config::IpNetwork, db};
+use tower::ServiceExt;
+
+async fn post_from(app: &Router, peer: &str, forwarded_for: Option<&str>) -> StatusCode {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(xff) = forwarded_for {
+        builder = builder.header("x-forwarded-for", xff);
+    }
+    let mut request = builder
+        .body(Body::from(common::valid_checkin_json()))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+fn stored_source_ip(temp_db: &tempfile::NamedTempFile) -> Option<String> {
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let checkins = db::get_checkins_by_serial(&conn, "SN123456789").unwrap();
+    let laptop = db::get_laptop_by_serial(&conn, "SN123456789")
+        .unwrap()
+        .unwrap();
+    assert_eq!(checkins[0].source_ip, laptop.source_ip);
+    laptop.source_ip
+}
+
+#[tokio::test]
+async fn test_checkin_records_peer_address() {
+    let (app, temp_db) = common::setup_test_app();
+
+    // The forwarded header is ignored from a peer that isn't a trusted proxy
+    let status = post_from(&app, "203.0.113.7:50123", Some("10.9.9.9")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored_source_ip(&temp_db).as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn test_checkin_behind_trusted_proxy_records_forwarded_address() {
+    let (app, temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().trusted_proxies =
+            vec![IpNetwork::try_from("127.0.0.1".to_string()).unwrap()];
+    });
+
+    let status = post_from(&app, "127.0.0.1:40000", Some("198.51.100.20")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored_source_ip(&temp_db).as_deref(), Some("198.51.100.20"));
+}
+
+#[tokio::test]
+async fn test_device_page_shows_reported_and_observed_addresses() {
+    let (app, _temp_db) = common::setup_test_app();
+    post_from(&app, "203.0.113.7:50123", None).await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/device/SN123456789")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(html.contains("192.168.1.100"), "reported address shown");
+    assert!(html.contains("203.0.113.7"), "observed address shown");
+}
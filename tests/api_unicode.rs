@@ -0,0 +1,153 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::config::Charset;
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn post_checkin(body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+fn checkin(serial: &str, user: &str, model: &str, drive_serial: &str) -> serde_json::Value {
+    serde_json::json!({
+        "hostname": "LAPTOP-001",
+        "laptop_serial": serial,
+        "ip_address": "192.168.1.100",
+        "logged_in_user": user,
+        "timestamp_utc": "2024-01-15T10:30:00Z",
+        "drives": [{
+            "device_id": "PhysicalDrive0",
+            "model": model,
+            "serial_number": drive_serial
+        }]
+    })
+}
+
+fn unicode_app() -> (Router, tempfile::NamedTempFile) {
+    common::setup_test_app_with(|state| {
+        let validation = &mut state.settings_mut().validation;
+        validation.charset = Charset::Unicode;
+        validation.detailed_errors = true;
+    })
+}
+
+#[tokio::test]
+async fn test_non_ascii_user_is_rejected_by_default() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let body = checkin("SN1", "CONTOSO\\José", "Samsung SSD", "S1");
+    let (status, _) = send(&app, post_checkin(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_mixed_scripts_are_stored_and_shown() {
+    let (app, _temp_db) = unicode_app();
+
+    let devices = [
+        (
+            "SN-LATIN",
+            "CONTOSO\\José Müller",
+            "Samsung SSD 970",
+            "S4EV-Ø1",
+        ),
+        ("SN-CJK", "山田 太郎", "東芝 MQ04ABF100", "X7-東京"),
+        ("SN-CYRILLIC", "Иван Petrov", "Накопитель", "ЖД-1"),
+        ("SN-ARABIC", "محمد علي", "WD Blue", "ΑΒΓ-123"),
+    ];
+    for (serial, user, model, drive_serial) in devices {
+        let body = checkin(serial, user, model, drive_serial);
+        let (status, text) = send(&app, post_checkin(body)).await;
+        assert_eq!(status, StatusCode::OK, "{user}: {text}");
+    }
+
+    let (_, index) = send(&app, get("/")).await;
+    for (_, user, _, drive_serial) in devices {
+        assert!(index.contains(user), "{user} missing from index");
+        assert!(
+            index.contains(drive_serial),
+            "{drive_serial} missing from index"
+        );
+    }
+
+    let (status, device) = send(&app, get("/device/SN-CJK")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(device.contains("山田 太郎"));
+    assert!(device.contains("東芝 MQ04ABF100"));
+}
+
+#[tokio::test]
+async fn test_unsafe_unicode_is_rejected() {
+    let (app, _temp_db) = unicode_app();
+
+    for (user, code) in [
+        // Would display as "admin.exe" reversed around the override
+        ("exe.\u{202E}nimda", "invalid_characters"),
+        ("user\u{2067}x", "invalid_characters"),
+        ("José\u{0}", "invalid_characters"),
+        ("Jose\u{301}", "not_normalized"),
+    ] {
+        let body = checkin("SN1", user, "Samsung SSD", "S1");
+        let (status, text) = send(&app, post_checkin(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{user:?}");
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["errors"][0]["field"], "logged_in_user");
+        assert_eq!(json["errors"][0]["code"], code, "{user:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_laptop_serial_stays_ascii() {
+    let (app, _temp_db) = unicode_app();
+
+    let body = checkin("SN-東京", "山田", "Samsung SSD", "S1");
+    let (status, text) = send(&app, post_checkin(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(text.contains("laptop_serial"), "{text}");
+}
+
+#[tokio::test]
+async fn test_schema_follows_charset() {
+    let (app, _temp_db) = common::setup_test_app();
+    let (_, ascii) = send(&app, get("/api/v1/schema")).await;
+    let ascii: serde_json::Value = serde_json::from_str(&ascii).unwrap();
+    assert_eq!(ascii["properties"]["logged_in_user"]["pattern"], "^[ -~]*$");
+
+    let (app, _temp_db) = unicode_app();
+    let (_, unicode) = send(&app, get("/api/v1/schema")).await;
+    let unicode: serde_json::Value = serde_json::from_str(&unicode).unwrap();
+    let pattern = unicode["properties"]["logged_in_user"]["pattern"]
+        .as_str()
+        .unwrap();
+    assert!(pattern.contains(r"\u202A-\u202E"), "{pattern}");
+    assert_eq!(
+        unicode["$defs"]["Drive"]["properties"]["model"]["pattern"],
+        pattern
+    );
+    // Still ASCII
+    assert_eq!(
+        unicode["properties"]["laptop_serial"]["pattern"],
+        "^[ -~]*$"
+    );
+}
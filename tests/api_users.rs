@@ -0,0 +1,176 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+
+/// jdoe moves from LAPTOP-001, where asmith takes over, to LAPTOP-002, reported
+/// once with the NetBIOS domain and once as a UPN
+async fn fleet(app: &Router) {
+    common::seed(
+        app,
+        &[
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "CONTOSO\\jdoe",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "CONTOSO\\jdoe",
+                "2024-03-02T09:00:00Z",
+            ),
+            (
+                "LAPTOP-002",
+                "SN2",
+                "10.0.0.5",
+                "jdoe@contoso.com",
+                "2024-03-05T09:00:00Z",
+            ),
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                "asmith",
+                "2024-03-10T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_users_listing() {
+    let (app, _temp_db) = common::setup_test_app();
+    fleet(&app).await;
+
+    let (status, body) = common::get(&app, "/users").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Users (2) on 2 devices"), "{body}");
+    // Listed under the name last reported, with the other form alongside
+    assert!(body.contains("/user/contoso%5Cjdoe"));
+    assert!(body.contains("<td>jdoe@contoso.com</td>"));
+    assert!(body.contains("CONTOSO\\jdoe"));
+    // asmith was seen most recently
+    assert!(body.find("/user/asmith").unwrap() < body.find("/user/contoso%5Cjdoe").unwrap());
+}
+
+#[tokio::test]
+async fn test_user_page_any_name_form() {
+    let (app, _temp_db) = common::setup_test_app();
+    fleet(&app).await;
+
+    for uri in [
+        "/user/contoso%5Cjdoe",
+        "/user/CONTOSO%5CJDoe",
+        "/user/jdoe@contoso.com",
+    ] {
+        let (status, body) = common::get(&app, uri).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert!(body.contains("Devices (2)"), "{uri}");
+        assert!(
+            body.contains("Check-ins</label>\n            <span>3</span>"),
+            "{body}"
+        );
+    }
+
+    let (_, body) = common::get(&app, "/user/jdoe@contoso.com").await;
+    // LAPTOP-002 first (most recent), with jdoe its current user
+    let laptop2 = body.find("LAPTOP-002").unwrap();
+    let laptop1 = body.find("LAPTOP-001").unwrap();
+    assert!(laptop2 < laptop1);
+    assert_eq!(body.matches("current user").count(), 1);
+    assert!(body.contains("2024-03-01T09:00:00Z"));
+    assert!(body.contains("2024-03-02T09:00:00Z"));
+}
+
+#[tokio::test]
+async fn test_user_page_not_found() {
+    let (app, _temp_db) = common::setup_test_app();
+    fleet(&app).await;
+
+    let (status, _) = common::get(&app, "/user/FABRIKAM%5Cjdoe").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // A bare name is a different account from a domain one
+    let (status, _) = common::get(&app, "/user/jdoe").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_user_domain_aliases() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state
+            .settings_mut()
+            .users
+            .domain_aliases
+            .insert("corp.example.com".to_string(), "EXAMPLE".to_string());
+    });
+    let checkins = [
+        ("EXAMPLE\\mlee", "2024-03-01T09:00:00Z"),
+        ("mlee@corp.example.com", "2024-03-02T09:00:00Z"),
+    ];
+    for (user, timestamp) in checkins {
+        let body =
+            common::checkin_json_with("LAPTOP-003", "SN3", "10.0.0.5", Some(user), timestamp);
+        common::post_checkin(&app, body).await;
+    }
+
+    let (status, body) = common::get(&app, "/user/EXAMPLE%5Cmlee").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains("Check-ins</label>\n            <span>2</span>"),
+        "{body}"
+    );
+}
+
+#[tokio::test]
+async fn test_device_page_links_users() {
+    let (app, _temp_db) = common::setup_test_app();
+    fleet(&app).await;
+
+    let (_, body) = common::get(&app, "/device/SN1").await;
+    assert!(body.contains(r#"<a href="/user/asmith">asmith</a>"#));
+    assert!(body.contains(r#"<a href="/user/CONTOSO%5Cjdoe">"#));
+}
+
+#[tokio::test]
+async fn test_local_accounts_are_per_machine() {
+    let (app, _temp_db) = common::setup_test_app();
+    common::seed(
+        &app,
+        &[
+            (
+                "LAPTOP-001",
+                "SN1",
+                "10.0.0.5",
+                ".\\admin",
+                "2024-03-01T09:00:00Z",
+            ),
+            (
+                "LAPTOP-002",
+                "SN2",
+                "10.0.0.5",
+                ".\\admin",
+                "2024-03-02T09:00:00Z",
+            ),
+        ],
+    )
+    .await;
+
+    let (_, body) = common::get(&app, "/users").await;
+    assert!(body.contains("Users (2) on 2 devices"), "{body}");
+    assert!(body.contains("/user/laptop-001%5Cadmin"));
+    assert!(body.contains("/user/laptop-002%5Cadmin"));
+
+    let (_, device) = common::get(&app, "/device/SN1").await;
+    assert!(device.contains(r#"<a href="/user/LAPTOP-001%5Cadmin">.\admin</a>"#));
+    let (status, body) = common::get(&app, "/user/LAPTOP-001%5Cadmin").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Devices (1)"), "{body}");
+    assert!(!body.contains("LAPTOP-002"));
+    // Without the machine it could be anyone's
+    let (status, _) = common::get(&app, "/user/.%5Cadmin").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use inventory_server::db;
+use tower::ServiceExt;
+
+async fn post(
+    app: &Router,
+    uri: &str,
+    body: String,
+    from: &str,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr: SocketAddr = from.parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+/// Bad hostname, and a control character in the second drive's serial
+fn invalid_checkin() -> String {
+    serde_json::json!({
+        "hostname": "_bad_host",
+        "laptop_serial": "SN001",
+        "ip_address": "192.168.1.100",
+        "logged_in_user": "testuser",
+        "timestamp_utc": "2024-01-15T10:30:00Z",
+        "drives": [
+            { "device_id": "PhysicalDrive0", "model": "Disk A", "serial_number": "S1" },
+            { "device_id": "PhysicalDrive1", "model": "Disk B", "serial_number": "S2\u{1b}" }
+        ]
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn test_generic_error_by_default() {
+    let (app, _temp_db) = common::setup_test_app();
+
+    let (status, body) = post(&app, "/checkin", invalid_checkin(), "10.0.0.5:50000").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid input data");
+    assert!(body.get("errors").is_none());
+}
+
+#[tokio::test]
+async fn test_detailed_errors_list_field_paths_and_codes() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().validation.detailed_errors = true;
+    });
+
+    let (status, body) = post(&app, "/checkin", invalid_checkin(), "10.0.0.5:50000").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid input data");
+    assert_eq!(
+        body["errors"],
+        serde_json::json!([
+            { "field": "drives[1].serial_number", "code": "invalid_characters" },
+            { "field": "hostname", "code": "invalid_hostname" }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_detailed_errors_for_batch_items() {
+    let (app, _temp_db) = common::setup_test_app_with(|state| {
+        state.settings_mut().validation.detailed_errors = true;
+    });
+
+    let body = format!("[{},{}]", common::valid_checkin_json(), invalid_checkin());
+    let (status, body) = post(&app, "/checkin/batch", body, "10.0.0.5:50000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["results"][0].get("errors").is_none());
+    assert_eq!(
+        body["results"][1]["errors"][1],
+        serde_json::json!({ "field": "hostname", "code": "invalid_hostname" })
+    );
+}
+
+#[tokio::test]
+async fn test_rejections_are_counted_per_source() {
+    let (app, temp_db) = common::setup_test_app();
+
+    post(&app, "/checkin", invalid_checkin(), "10.0.0.5:50000").await;
+    post(&app, "/checkin", invalid_checkin(), "10.0.0.5:50001").await;
+    post(&app, "/checkin", invalid_checkin(), "10.0.0.6:50000").await;
+    let batch = format!("[{},{}]", invalid_checkin(), invalid_checkin());
+    post(&app, "/checkin/batch", batch, "10.0.0.6:50000").await;
+
+    // Accepted check-ins aren't counted
+    post(
+        &app,
+        "/checkin",
+        common::valid_checkin_json(),
+        "10.0.0.7:50000",
+    )
+    .await;
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let counts = db::get_rejection_counts(&conn).unwrap();
+    let totals: Vec<(&str, i64, &str)> = counts
+        .iter()
+        .map(|c| (c.source.as_str(), c.rejected_count, c.last_error.as_str()))
+        .collect();
+    assert_eq!(
+        totals,
+        [
+            ("10.0.0.6", 3, "invalid_characters"),
+            ("10.0.0.5", 2, "invalid_characters"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_batch_rejections_are_counted_per_code() {
+    let (app, temp_db) = common::setup_test_app();
+
+    // Two invalid hostnames, then an item missing its fields
+    let batch = format!(
+        "[{},{},{{}},{}]",
+        invalid_checkin(),
+        invalid_checkin(),
+        common::valid_checkin_json()
+    );
+    let (status, _) = post(&app, "/checkin/batch", batch, "10.0.0.6:50000").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+    let counts = db::get_rejection_counts(&conn).unwrap();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].rejected_count, 3);
+    // The last rejected item's code
+    assert_eq!(counts[0].last_error, "invalid_payload");
+    assert_eq!(
+        counts[0].by_code,
+        [
+            ("invalid_characters".to_string(), 2),
+            ("invalid_payload".to_string(), 1),
+        ]
+    );
+}
@@ -1,31 +1,37 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
-    routing::{get, post},
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
     Router,
 };
-use inventory_server::{db, handlers, AppState};
+use http_body_util::BodyExt;
+use inventory_server::{db, routes, AppState};
 use tempfile::NamedTempFile;
+use tower::ServiceExt;
 
 /// Creates a test application with a temporary SQLite database.
 /// Returns the router and the temp file (which must be kept alive for the duration of the test).
+#[allow(dead_code)]
 pub fn setup_test_app() -> (Router, NamedTempFile) {
+    setup_test_app_with(|_| {})
+}
+
+/// Like [`setup_test_app`], but lets the test adjust the state before the router is built.
+#[allow(dead_code)]
+pub fn setup_test_app_with(configure: impl FnOnce(&mut AppState)) -> (Router, NamedTempFile) {
     let temp_db = NamedTempFile::new().expect("Failed to create temp db file");
     let db_path = temp_db.path().to_str().unwrap().to_string();
 
     // Initialize the database schema
     db::open_and_init(&db_path).expect("Failed to initialize test database");
 
-    let state = Arc::new(AppState {
-        db_path,
-        debug_mode: false,
-    });
+    let mut state = AppState::new(db_path);
+    configure(&mut state);
 
-    let app = Router::new()
-        .route("/", get(handlers::index))
-        .route("/device/:serial", get(handlers::device_detail))
-        .route("/checkin", post(handlers::checkin))
-        .with_state(state);
+    let app = routes::build_router(Arc::new(state));
 
     (app, temp_db)
 }
@@ -71,3 +77,58 @@ pub fn checkin_json_with(
     })
     .to_string()
 }
+
+/// The CSRF token in a page's forms
+#[allow(dead_code)]
+pub fn csrf_token(html: &str) -> &str {
+    let start = html
+        .find(r#"name="csrf_token" value=""#)
+        .expect("page has no form token")
+        + r#"name="csrf_token" value=""#.len();
+    let len = html[start..].find('"').unwrap();
+    &html[start..start + len]
+}
+
+/// Posts a check-in from an agent at 172.16.5.9, asserting it's accepted
+#[allow(dead_code)]
+pub async fn post_checkin(app: &Router, body: String) {
+    post_checkin_from(app, body, "172.16.5.9").await
+}
+
+/// Like [`post_checkin`], from the agent address `source`
+#[allow(dead_code)]
+pub async fn post_checkin_from(app: &Router, body: String, source: &str) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/checkin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let addr = SocketAddr::new(source.parse().unwrap(), 50000);
+    request.extensions_mut().insert(ConnectInfo(addr));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// Posts each `(hostname, serial, ip, user, timestamp)` check-in in turn, from the
+/// address it reports, as an agent with nothing in between would
+#[allow(dead_code)]
+pub async fn seed(app: &Router, checkins: &[(&str, &str, &str, &str, &str)]) {
+    for &(hostname, serial, ip, user, timestamp) in checkins {
+        let body = checkin_json_with(hostname, serial, ip, Some(user), timestamp);
+        post_checkin_from(app, body, ip).await;
+    }
+}
+
+/// GETs `uri`, returning the status and body
+#[allow(dead_code)]
+pub async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
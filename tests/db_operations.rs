@@ -1,4 +1,4 @@
-use inventory_server::db;
+use inventory_server::{db, models, network};
 use rusqlite::params;
 use tempfile::NamedTempFile;
 
@@ -55,7 +55,7 @@ fn test_open_and_init_creates_indexes() {
             |row| row.get(0),
         )
         .unwrap();
-    assert_eq!(index_count, 2, "should have 2 checkins indexes");
+    assert_eq!(index_count, 4, "should have 4 checkins indexes");
 }
 
 #[test]
@@ -229,3 +229,595 @@ fn test_get_checkins_filters_by_serial() {
     assert_eq!(checkins.len(), 1);
     assert_eq!(checkins[0].hostname, "laptop1");
 }
+
+#[test]
+fn test_checkpoint_truncates_wal() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap();
+
+    let conn = db::open_and_init(db_path).unwrap();
+    conn.execute(
+        "INSERT INTO laptops (laptop_serial, hostname, ip_address, logged_in_user, last_seen_utc, drives_json)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params!["SN001", "laptop1", "192.168.1.1", "user1", "2024-01-15T10:00:00Z", "[]"],
+    )
+    .unwrap();
+
+    let (busy, _, _) = db::checkpoint(&conn).unwrap();
+
+    assert!(!busy, "checkpoint should not be blocked");
+    let wal_len = std::fs::metadata(format!("{db_path}-wal"))
+        .map(|m| m.len())
+        .unwrap_or(0);
+    assert_eq!(wal_len, 0, "WAL should be truncated");
+}
+
+#[test]
+fn test_open_and_init_migrates_version_1_database() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap();
+
+    // Tables as created by version 1, with a row written before the upgrade
+    {
+        let conn = rusqlite::Connection::open(db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE laptops (
+               laptop_serial TEXT PRIMARY KEY, hostname TEXT NOT NULL, ip_address TEXT NOT NULL,
+               logged_in_user TEXT, last_seen_utc TEXT NOT NULL, drives_json TEXT NOT NULL);
+             CREATE TABLE checkins (
+               id INTEGER PRIMARY KEY AUTOINCREMENT, laptop_serial TEXT NOT NULL,
+               hostname TEXT NOT NULL, ip_address TEXT NOT NULL, logged_in_user TEXT,
+               timestamp_utc TEXT NOT NULL, drives_json TEXT NOT NULL);
+             INSERT INTO laptops VALUES ('SN001', 'laptop1', '10.0.0.1', NULL, '2024-01-15T10:00:00Z', '[]');
+             INSERT INTO laptops VALUES ('SN002', 'host.Example.com', '10.0.0.2', NULL, '2024-01-15T10:00:00Z', '[]');
+             INSERT INTO checkins (laptop_serial, hostname, ip_address, logged_in_user, timestamp_utc, drives_json)
+               VALUES ('SN001', 'laptop1', '10.0.0.1', 'jdoe', '2024-01-15T10:00:00Z',
+                       '[{\"model\": \"WD Blue\", \"serial_number\": \"WD-123\", \"device_id\": \"PHYSICALDRIVE0\"}]');
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+    }
+
+    let conn = db::open_and_init(db_path).unwrap();
+
+    assert_eq!(db::schema_version(&conn).unwrap(), db::SCHEMA_VERSION);
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "laptop1");
+    assert_eq!(laptop.received_at_utc, None);
+    assert_eq!(laptop.clock_skew_secs, None);
+    assert_eq!(laptop.short_name.as_deref(), Some("laptop1"));
+    assert_eq!(laptop.domain, None);
+
+    let laptop = db::get_laptop_by_serial(&conn, "SN002").unwrap().unwrap();
+    assert_eq!(laptop.short_name.as_deref(), Some("host"));
+    assert_eq!(laptop.domain.as_deref(), Some("example.com"));
+
+    // Existing check-ins are searchable
+    let hits = db::search_checkins(&conn, "\"wd-12\"*", "[", "]", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].serial, "SN001");
+    assert_eq!(hits[0].drive_serials.as_deref(), Some("[WD-123]"));
+
+    // Existing check-ins are found by point-in-time queries
+    let as_of = db::parse_instant("2024-01-15T10:00:00Z").unwrap();
+    let snapshot = db::get_all_laptops_as_of(&conn, as_of).unwrap();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].logged_in_user.as_deref(), Some("jdoe"));
+
+    // Existing check-ins are found by address
+    let range = network::key_range(&"10.0.0.0/24".parse().unwrap());
+    let hits = db::find_checkins_by_address(&conn, &[range], 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].laptop_serial, "SN001");
+
+    // Devices start without tags, notes or custom fields
+    assert!(db::get_all_device_meta(&conn).unwrap().is_empty());
+
+    // ... and without lifecycle changes
+    assert!(db::get_lifecycle_states(&conn, None).unwrap().is_empty());
+
+    // ... and without remaps or collisions
+    assert!(db::get_serial_remaps(&conn).unwrap().is_empty());
+    assert!(db::get_serial_collisions(&conn).unwrap().is_empty());
+    assert!(db::get_rejection_counts(&conn).unwrap().is_empty());
+
+    // Existing check-ins are found by user account
+    let devices = db::get_account_devices(&conn, "jdoe").unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].laptop_serial, "SN001");
+    let history = db::get_checkins_by_serial(&conn, "SN001").unwrap();
+    assert_eq!(history[0].reported_serial, None);
+
+    // Running again on an up-to-date database is a no-op
+    drop(conn);
+    assert!(db::open_and_init(db_path).is_ok());
+}
+
+#[test]
+fn test_open_and_init_rejects_newer_schema() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap();
+
+    let conn = db::open_and_init(db_path).unwrap();
+    conn.pragma_update(None, "user_version", db::SCHEMA_VERSION + 1)
+        .unwrap();
+    drop(conn);
+
+    assert!(db::open_and_init(db_path).is_err());
+}
+
+#[test]
+fn test_upsert_laptop_if_newer() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+
+    let mut checkin = db::NewCheckin {
+        laptop_serial: "SN001".to_string(),
+        hostname: "laptop1".to_string(),
+        short_name: "laptop1".to_string(),
+        domain: None,
+        ip_address: "10.0.0.1".to_string(),
+        logged_in_user: None,
+        timestamp_utc: "2024-01-15T10:00:00Z".to_string(),
+        drives_json: "[]".to_string(),
+        received_at_utc: "2024-01-15T10:00:05Z".to_string(),
+        clock_skew_secs: -5,
+        source_ip: None,
+        reported_serial: None,
+    };
+    assert!(db::upsert_laptop_if_newer(&conn, &checkin).unwrap());
+
+    // Same instant written differently, then an older one: neither replaces the row
+    checkin.timestamp_utc = "2024-01-15T11:00:00+01:00".to_string();
+    assert!(!db::upsert_laptop_if_newer(&conn, &checkin).unwrap());
+    checkin.timestamp_utc = "2024-01-14T10:00:00Z".to_string();
+    assert!(!db::upsert_laptop_if_newer(&conn, &checkin).unwrap());
+
+    checkin.timestamp_utc = "2024-01-15T10:00:00.5Z".to_string();
+    checkin.hostname = "laptop1-renamed".to_string();
+    assert!(db::upsert_laptop_if_newer(&conn, &checkin).unwrap());
+
+    let laptop = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "laptop1-renamed");
+}
+
+fn new_checkin(serial: &str, user: &str, timestamp_utc: &str) -> db::NewCheckin {
+    db::NewCheckin {
+        laptop_serial: serial.to_string(),
+        hostname: format!("host-{serial}"),
+        short_name: format!("host-{serial}"),
+        domain: None,
+        ip_address: "10.0.0.1".to_string(),
+        logged_in_user: Some(user.to_string()),
+        timestamp_utc: timestamp_utc.to_string(),
+        drives_json: "[]".to_string(),
+        received_at_utc: timestamp_utc.to_string(),
+        clock_skew_secs: 0,
+        source_ip: None,
+        reported_serial: None,
+    }
+}
+
+#[test]
+fn test_get_laptops_as_of() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+
+    let checkins = [
+        new_checkin("SN001", "alice", "2024-03-01T09:00:00Z"),
+        // 2024-03-03T22:30:00Z, written with an offset
+        new_checkin("SN001", "bob", "2024-03-04T00:30:00+02:00"),
+        new_checkin("SN001", "carol", "2024-03-10T09:00:00Z"),
+        new_checkin("SN002", "dave", "2024-03-05T09:00:00Z"),
+    ];
+    for c in &checkins {
+        db::insert_checkin(&conn, c).unwrap();
+        db::upsert_laptop_if_newer(&conn, c).unwrap();
+    }
+
+    let at = |ts: &str| db::parse_instant(ts).unwrap();
+    let users = |rows: Vec<models::LaptopRow>| -> Vec<(String, String)> {
+        rows.into_iter()
+            .map(|r| (r.laptop_serial, r.logged_in_user.unwrap()))
+            .collect()
+    };
+
+    assert!(db::get_all_laptops_as_of(&conn, at("2024-02-01T00:00:00Z"))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        users(db::get_all_laptops_as_of(&conn, at("2024-03-03T23:59:59Z")).unwrap()),
+        [("SN001".to_string(), "bob".to_string())]
+    );
+    // Most recently seen first, like the current list
+    assert_eq!(
+        users(db::get_all_laptops_as_of(&conn, at("2024-03-06T00:00:00Z")).unwrap()),
+        [
+            ("SN002".to_string(), "dave".to_string()),
+            ("SN001".to_string(), "bob".to_string()),
+        ]
+    );
+    // The instant itself is included
+    let laptop = db::get_laptop_by_serial_as_of(&conn, "SN001", at("2024-03-01T09:00:00Z"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(laptop.logged_in_user.as_deref(), Some("alice"));
+    assert_eq!(laptop.last_seen_utc, "2024-03-01T09:00:00Z");
+    assert!(
+        db::get_laptop_by_serial_as_of(&conn, "SN002", at("2024-03-01T09:00:00Z"))
+            .unwrap()
+            .is_none()
+    );
+
+    let history =
+        db::get_checkins_by_serial_as_of(&conn, "SN001", at("2024-03-05T00:00:00Z")).unwrap();
+    let timestamps: Vec<&str> = history.iter().map(|c| c.timestamp_utc.as_str()).collect();
+    assert_eq!(
+        timestamps,
+        ["2024-03-04T00:30:00+02:00", "2024-03-01T09:00:00Z"]
+    );
+}
+
+#[test]
+fn test_laptops_as_of_same_instant_matches_current_state() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+
+    // Two check-ins at the same instant, the second written with an offset
+    let checkins = [
+        new_checkin("SN001", "alice", "2024-03-01T09:00:00Z"),
+        new_checkin("SN001", "bob", "2024-03-01T10:00:00+01:00"),
+    ];
+    for c in &checkins {
+        db::insert_checkin(&conn, c).unwrap();
+        db::upsert_laptop_if_newer(&conn, c).unwrap();
+    }
+
+    let current = db::get_laptop_by_serial(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(current.logged_in_user.as_deref(), Some("alice"));
+
+    let now = chrono::Utc::now();
+    let as_of = db::get_laptop_by_serial_as_of(&conn, "SN001", now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(as_of.logged_in_user, current.logged_in_user);
+    let fleet = db::get_all_laptops_as_of(&conn, now).unwrap();
+    assert_eq!(fleet[0].logged_in_user, current.logged_in_user);
+}
+
+#[test]
+fn test_laptops_as_of_uses_serial_time_index() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+
+    let plan: Vec<String> = conn
+        .prepare(
+            "EXPLAIN QUERY PLAN
+             SELECT c.id FROM laptops l
+             JOIN checkins c ON c.id = (
+               SELECT id FROM checkins
+               WHERE laptop_serial = l.laptop_serial AND timestamp_ms <= ?1
+               ORDER BY timestamp_ms DESC, id DESC LIMIT 1)",
+        )
+        .unwrap()
+        .query_map([0], |row| row.get::<_, String>(3))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(
+        plan.iter().any(|p| p.contains("idx_checkins_serial_time")),
+        "{plan:?}"
+    );
+    assert!(
+        !plan.iter().any(|p| p.contains("SCAN checkins")),
+        "{plan:?}"
+    );
+}
+
+#[test]
+fn test_account_devices_use_user_account_index() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+
+    for (user, serial) in [
+        ("CONTOSO\\JDoe", "SN001"),
+        ("jdoe@contoso.com", "SN001"),
+        ("FABRIKAM\\jdoe", "SN002"),
+        ("CONTOSO\\asmith", "SN002"),
+    ] {
+        db::insert_checkin(&conn, &new_checkin(serial, user, "2024-03-01T09:00:00Z")).unwrap();
+    }
+    let mut users: Vec<_> = db::get_account_devices(&conn, "jdoe")
+        .unwrap()
+        .into_iter()
+        .map(|r| r.logged_in_user)
+        .collect();
+    users.sort();
+    assert_eq!(
+        users,
+        ["CONTOSO\\JDoe", "FABRIKAM\\jdoe", "jdoe@contoso.com"]
+    );
+
+    let plan: Vec<String> = conn
+        .prepare(
+            "EXPLAIN QUERY PLAN
+             SELECT c.logged_in_user, COUNT(*) FROM checkins c
+             WHERE c.user_account = ?1 GROUP BY c.logged_in_user, c.laptop_serial",
+        )
+        .unwrap()
+        .query_map(["jdoe"], |row| row.get::<_, String>(3))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(
+        plan.iter().any(|p| p.contains("idx_checkins_user_account")),
+        "{plan:?}"
+    );
+}
+
+#[test]
+fn test_device_meta() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+    let at = "2024-03-01T09:00:00Z";
+
+    assert_eq!(
+        db::get_device_meta(&conn, "SN001").unwrap(),
+        models::DeviceMeta::default()
+    );
+
+    // Tags are unique and removed without regard to case
+    assert!(db::add_device_tag(&conn, "SN001", "Loaner").unwrap());
+    assert!(!db::add_device_tag(&conn, "SN001", "loaner").unwrap());
+    assert!(db::add_device_tag(&conn, "SN001", "apac").unwrap());
+    assert!(db::add_device_tag(&conn, "SN002", "loaner").unwrap());
+    assert!(db::remove_device_tag(&conn, "SN002", "LOANER").unwrap());
+    assert!(!db::remove_device_tag(&conn, "SN002", "loaner").unwrap());
+
+    db::set_device_notes(&conn, "SN001", "Screen replaced", at).unwrap();
+    db::set_device_field(&conn, "SN001", "asset_tag", Some("A-1"), at).unwrap();
+    db::set_device_field(&conn, "SN001", "asset_tag", Some("A-2"), at).unwrap();
+    db::set_device_field(&conn, "SN001", "cost_center", Some("IT"), at).unwrap();
+    db::set_device_field(&conn, "SN001", "cost_center", None, at).unwrap();
+
+    let meta = db::get_device_meta(&conn, "SN001").unwrap();
+    assert_eq!(meta.tags, ["apac", "Loaner"]);
+    assert_eq!(meta.notes.as_deref(), Some("Screen replaced"));
+    assert_eq!(meta.notes_updated_utc.as_deref(), Some(at));
+    assert_eq!(meta.fields.len(), 1);
+    assert_eq!(meta.fields["asset_tag"], "A-2");
+
+    // Empty notes remove them
+    db::set_device_notes(&conn, "SN001", "", at).unwrap();
+    assert_eq!(db::get_device_meta(&conn, "SN001").unwrap().notes, None);
+
+    let all = db::get_all_device_meta(&conn).unwrap();
+    assert_eq!(all.len(), 1);
+    assert_eq!(all["SN001"].tags.len(), 2);
+}
+
+#[test]
+fn test_lifecycle_events() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+    let event = |state: &str, changed_at_utc: &str| db::NewLifecycleEvent {
+        laptop_serial: "SN001".to_string(),
+        state: state.to_string(),
+        changed_at_utc: changed_at_utc.to_string(),
+        changed_at_ms: db::parse_instant(changed_at_utc)
+            .unwrap()
+            .timestamp_millis(),
+        changed_by: "alice".to_string(),
+        reason: "Test".to_string(),
+    };
+
+    db::insert_lifecycle_event(&conn, &event("in_repair", "2024-03-01T09:00:00Z")).unwrap();
+    db::insert_lifecycle_event(&conn, &event("retired", "2024-03-05T09:00:00Z")).unwrap();
+
+    let events = db::get_lifecycle_events(&conn, "SN001", None).unwrap();
+    assert_eq!(
+        events.iter().map(|e| e.state.as_str()).collect::<Vec<_>>(),
+        ["retired", "in_repair"]
+    );
+    let as_of = db::parse_instant("2024-03-02T00:00:00Z")
+        .unwrap()
+        .timestamp_millis();
+    assert_eq!(
+        db::get_lifecycle_events(&conn, "SN001", Some(as_of))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        db::get_lifecycle_states(&conn, None).unwrap()["SN001"].state,
+        "retired"
+    );
+    assert_eq!(
+        db::get_lifecycle_states(&conn, Some(as_of)).unwrap()["SN001"].state,
+        "in_repair"
+    );
+    assert!(db::get_lifecycle_states(&conn, Some(0)).unwrap().is_empty());
+
+    // Only check-ins taken after the retirement are flagged
+    let before = new_checkin("SN001", "alice", "2024-03-04T09:00:00Z");
+    assert_eq!(db::flag_lifecycle_checkin(&conn, &before).unwrap(), None);
+    let other = new_checkin("SN002", "alice", "2024-03-06T09:00:00Z");
+    assert_eq!(db::flag_lifecycle_checkin(&conn, &other).unwrap(), None);
+    for timestamp in ["2024-03-06T09:00:00Z", "2024-03-07T09:00:00Z"] {
+        let after = new_checkin("SN001", "alice", timestamp);
+        assert_eq!(
+            db::flag_lifecycle_checkin(&conn, &after)
+                .unwrap()
+                .as_deref(),
+            Some("retired")
+        );
+    }
+    let alert = db::get_lifecycle_alert(&conn, "SN001").unwrap().unwrap();
+    assert_eq!(alert.checkins, 2);
+    assert_eq!(alert.first_received_utc, "2024-03-06T09:00:00Z");
+    assert_eq!(alert.last_received_utc, "2024-03-07T09:00:00Z");
+    assert_eq!(db::get_lifecycle_alerts(&conn).unwrap().len(), 1);
+
+    // A new state settles the alert
+    db::insert_lifecycle_event(&conn, &event("deployed", "2024-03-08T09:00:00Z")).unwrap();
+    assert_eq!(db::get_lifecycle_alert(&conn, "SN001").unwrap(), None);
+}
+
+#[test]
+fn test_merge_and_split_devices() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+    let store = |checkin: &db::NewCheckin| {
+        db::insert_checkin(&conn, checkin).unwrap();
+        db::upsert_laptop_if_newer(&conn, checkin).unwrap();
+    };
+    let remap = |source: &str, hostname: &str, target: &str| db::NewSerialRemap {
+        reported_serial: source.to_string(),
+        hostname: hostname.to_string(),
+        laptop_serial: target.to_string(),
+        created_at_utc: "2024-03-10T09:00:00Z".to_string(),
+        created_by: "alice".to_string(),
+        reason: "Test".to_string(),
+    };
+
+    store(&new_checkin("OLD", "alice", "2024-03-01T09:00:00Z"));
+    store(&new_checkin("NEW", "alice", "2024-03-02T09:00:00Z"));
+    db::add_device_tag(&conn, "OLD", "finance").unwrap();
+    let at = "2024-03-01T10:00:00Z";
+    db::set_device_notes(&conn, "OLD", "Old board", at).unwrap();
+    db::set_device_notes(&conn, "NEW", "New board", at).unwrap();
+
+    let moved = db::merge_devices(&conn, &remap("OLD", "", "NEW")).unwrap();
+    assert_eq!(moved, 1);
+    assert!(db::get_laptop_by_serial(&conn, "OLD").unwrap().is_none());
+    let history = db::get_checkins_by_serial(&conn, "NEW").unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].reported_serial.as_deref(), Some("OLD"));
+    let meta = db::get_device_meta(&conn, "NEW").unwrap();
+    assert_eq!(meta.tags, ["finance"]);
+    assert_eq!(meta.notes.as_deref(), Some("New board\n\nOld board"));
+    assert!(db::get_device_meta(&conn, "OLD").unwrap().tags.is_empty());
+    assert_eq!(
+        db::find_serial_remap(&conn, "OLD", "any-host")
+            .unwrap()
+            .as_deref(),
+        Some("NEW")
+    );
+
+    // Merging the target onward repoints the earlier remap
+    store(&new_checkin("NEWER", "alice", "2024-03-03T09:00:00Z"));
+    db::merge_devices(&conn, &remap("NEW", "", "NEWER")).unwrap();
+    assert_eq!(
+        db::find_serial_remap(&conn, "OLD", "any-host")
+            .unwrap()
+            .as_deref(),
+        Some("NEWER")
+    );
+    assert_eq!(db::get_checkins_by_serial(&conn, "NEWER").unwrap().len(), 3);
+
+    // Split off one hostname's check-ins; a hostname remap beats one for any host
+    let mut other = new_checkin("NEWER", "bob", "2024-03-04T09:00:00Z");
+    other.hostname = "DESKTOP-9".to_string();
+    store(&other);
+    let hostnames = db::get_device_hostnames(&conn, "NEWER").unwrap();
+    assert_eq!(hostnames.len(), 4);
+
+    let moved = db::split_device(&conn, &remap("NEWER", "desktop-9", "SPLIT")).unwrap();
+    assert_eq!(moved, 1);
+    let laptop = db::get_laptop_by_serial(&conn, "NEWER").unwrap().unwrap();
+    assert_eq!(laptop.hostname, "host-NEWER");
+    let split = db::get_laptop_by_serial(&conn, "SPLIT").unwrap().unwrap();
+    assert_eq!(split.hostname, "DESKTOP-9");
+    assert_eq!(
+        db::find_serial_remap(&conn, "NEWER", "DESKTOP-9")
+            .unwrap()
+            .as_deref(),
+        Some("SPLIT")
+    );
+    assert_eq!(
+        db::find_serial_remap(&conn, "NEWER", "host-NEWER").unwrap(),
+        None
+    );
+
+    assert!(db::delete_serial_remap(&conn, "NEWER", "DESKTOP-9").unwrap());
+    assert_eq!(
+        db::find_serial_remap(&conn, "NEWER", "DESKTOP-9").unwrap(),
+        None
+    );
+    assert_eq!(db::get_serial_remaps(&conn).unwrap().len(), 2);
+}
+
+#[test]
+fn test_merge_keeps_current_state_on_same_instant() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+    for c in [
+        new_checkin("OLD", "carol", "2024-03-01T09:00:00Z"),
+        new_checkin("NEW", "alice", "2024-03-02T09:00:00Z"),
+        new_checkin("NEW", "bob", "2024-03-02T10:00:00+01:00"),
+    ] {
+        db::insert_checkin(&conn, &c).unwrap();
+        db::upsert_laptop_if_newer(&conn, &c).unwrap();
+    }
+    let before = db::get_laptop_by_serial(&conn, "NEW").unwrap().unwrap();
+    assert_eq!(before.logged_in_user.as_deref(), Some("alice"));
+
+    let remap = db::NewSerialRemap {
+        reported_serial: "OLD".to_string(),
+        hostname: String::new(),
+        laptop_serial: "NEW".to_string(),
+        created_at_utc: "2024-03-10T09:00:00Z".to_string(),
+        created_by: "alice".to_string(),
+        reason: "Test".to_string(),
+    };
+    db::merge_devices(&conn, &remap).unwrap();
+    let after = db::get_laptop_by_serial(&conn, "NEW").unwrap().unwrap();
+    assert_eq!(after.logged_in_user, before.logged_in_user);
+    assert_eq!(after.last_seen_utc, before.last_seen_utc);
+}
+
+#[test]
+fn test_device_hostnames_ordered_by_instant() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let conn = db::open_and_init(temp_db.path().to_str().unwrap()).unwrap();
+    let checkin = |hostname: &str, timestamp_utc: &str| {
+        let mut c = new_checkin("SN001", "alice", timestamp_utc);
+        c.hostname = hostname.to_string();
+        db::insert_checkin(&conn, &c).unwrap();
+    };
+    // As strings "2024-03-01T20:00:00-08:00" sorts first but is the latest instant
+    checkin("laptop-1", "2024-03-01T20:00:00-08:00");
+    checkin("LAPTOP-1", "2024-03-01T09:00:00Z");
+    checkin("LAPTOP-1", "2024-03-02T01:00:00+02:00");
+    checkin("DESKTOP-9", "2024-03-01T12:00:00Z");
+
+    let hostnames = db::get_device_hostnames(&conn, "SN001").unwrap();
+    let summary: Vec<_> = hostnames
+        .iter()
+        .map(|h| {
+            (
+                h.hostname.as_str(),
+                h.checkins,
+                h.first_seen_utc.as_str(),
+                h.last_seen_utc.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "laptop-1",
+                3,
+                "2024-03-01T09:00:00Z",
+                "2024-03-01T20:00:00-08:00"
+            ),
+            (
+                "DESKTOP-9",
+                1,
+                "2024-03-01T12:00:00Z",
+                "2024-03-01T12:00:00Z"
+            ),
+        ]
+    );
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use inventory_server::{db, routes, shutdown, AppState};
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+
+struct RunningServer {
+    addr: SocketAddr,
+    stop: oneshot::Sender<()>,
+    server: tokio::task::JoinHandle<std::io::Result<()>>,
+    db: NamedTempFile,
+}
+
+async fn start_server() -> RunningServer {
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    db::open_and_init(&db_path).unwrap();
+
+    let app = routes::build_router(Arc::new(AppState::new(db_path)));
+    let handle = axum_server::Handle::new();
+    let (stop, stopped) = oneshot::channel::<()>();
+
+    tokio::spawn(shutdown::drain_on(
+        async {
+            stopped.await.ok();
+        },
+        handle.clone(),
+        Duration::from_secs(5),
+    ));
+
+    let server = tokio::spawn(
+        axum_server::bind("127.0.0.1:0".parse().unwrap())
+            .handle(handle.clone())
+            .serve(app.into_make_service()),
+    );
+    let addr = handle.listening().await.expect("server should start");
+
+    RunningServer {
+        addr,
+        stop,
+        server,
+        db,
+    }
+}
+
+fn checkin_request_head(body_len: usize) -> String {
+    format!(
+        "POST /checkin HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {body_len}\r\nConnection: close\r\n\r\n"
+    )
+}
+
+async fn read_status_line(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_in_flight_checkin_completes_during_shutdown() {
+    let server = start_server().await;
+    let body = common::valid_checkin_json();
+
+    // Start a request but hold back the body so it is still in flight when the stop arrives
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    stream
+        .write_all(checkin_request_head(body.len()).as_bytes())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    server.stop.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    stream.write_all(body.as_bytes()).await.unwrap();
+    assert_eq!(read_status_line(&mut stream).await, "HTTP/1.1 200 OK");
+
+    tokio::time::timeout(Duration::from_secs(5), server.server)
+        .await
+        .expect("server should stop once drained")
+        .unwrap()
+        .unwrap();
+
+    let conn = rusqlite::Connection::open(server.db.path()).unwrap();
+    let laptops = db::get_all_laptops(&conn).unwrap();
+    assert_eq!(laptops.len(), 1, "drained check-in should be committed");
+}
+
+#[tokio::test]
+async fn test_new_connections_refused_after_shutdown() {
+    let server = start_server().await;
+
+    server.stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server.server)
+        .await
+        .expect("idle server should stop promptly")
+        .unwrap()
+        .unwrap();
+
+    assert!(TcpStream::connect(server.addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_finish_checkpoints_wal() {
+    let server = start_server().await;
+    let body = common::valid_checkin_json();
+
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    stream
+        .write_all(format!("{}{body}", checkin_request_head(body.len())).as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(read_status_line(&mut stream).await, "HTTP/1.1 200 OK");
+
+    server.stop.send(()).unwrap();
+    server.server.await.unwrap().unwrap();
+
+    let db_path = server.db.path().to_str().unwrap();
+    shutdown::finish(db_path).unwrap();
+
+    let wal_len = std::fs::metadata(format!("{db_path}-wal"))
+        .map(|m| m.len())
+        .unwrap_or(0);
+    assert_eq!(wal_len, 0, "WAL should be truncated after checkpoint");
+}
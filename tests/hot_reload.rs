@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum_server::tls_rustls::RustlsConfig;
+use inventory_server::{
+    config,
+    reload::{Reloader, TlsFiles},
+    AppState, Settings,
+};
+use tempfile::TempDir;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(FIXTURES).join(name)
+}
+
+fn write_config(dir: &TempDir, contents: &str) -> PathBuf {
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn reloader(config_path: PathBuf, force_debug: bool, tls: Option<TlsFiles>) -> Reloader {
+    let startup_config = config::read_config(&config_path).unwrap();
+    let state = Arc::new(AppState::with_settings(
+        "unused.db".to_string(),
+        Settings::from_config(&startup_config, force_debug),
+    ));
+    Reloader {
+        state,
+        config_path,
+        force_debug,
+        startup_config,
+        log: None,
+        tls,
+    }
+}
+
+#[test]
+fn test_reload_config_applies_safe_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(&dir, "debug = false\n");
+    let reloader = reloader(path.clone(), false, None);
+    assert!(!reloader.state.settings().debug_mode);
+
+    write_config(
+        &dir,
+        "debug = true\n[readiness]\nmax_wal_mb = 16\n[logging]\nredact_user_names = false\n",
+    );
+    reloader.reload_config().unwrap();
+
+    let settings = reloader.state.settings();
+    assert!(settings.debug_mode);
+    assert_eq!(settings.readiness.max_wal_mb, 16);
+    assert!(!settings.redact_user_names);
+}
+
+#[test]
+fn test_reload_config_invalid_file_keeps_current_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(&dir, "debug = true\n");
+    let reloader = reloader(path, false, None);
+
+    write_config(&dir, "debug = \"definitely\"\n");
+
+    assert!(reloader.reload_config().is_err());
+    assert!(reloader.state.settings().debug_mode);
+}
+
+#[test]
+fn test_reload_config_respects_forced_debug() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(&dir, "debug = true\n");
+    let reloader = reloader(path, true, None);
+
+    write_config(&dir, "debug = false\n");
+    reloader.reload_config().unwrap();
+
+    assert!(reloader.state.settings().debug_mode);
+}
+
+async fn tls_files(dir: &TempDir, cert: &str, key: &str) -> TlsFiles {
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::copy(fixture(cert), &cert_path).unwrap();
+    std::fs::copy(fixture(key), &key_path).unwrap();
+
+    TlsFiles {
+        config: RustlsConfig::from_pem_file(&cert_path, &key_path)
+            .await
+            .unwrap(),
+        cert_path,
+        key_path,
+    }
+}
+
+#[tokio::test]
+async fn test_reload_tls_swaps_certificate() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(&dir, "");
+    let tls = tls_files(&dir, "first-cert.pem", "first-key.pem").await;
+    let live = tls.config.clone();
+    let before = live.get_inner();
+
+    std::fs::copy(fixture("second-cert.pem"), &tls.cert_path).unwrap();
+    std::fs::copy(fixture("second-key.pem"), &tls.key_path).unwrap();
+    reloader(config_path, false, Some(tls))
+        .reload_tls()
+        .await
+        .unwrap();
+
+    assert!(
+        !Arc::ptr_eq(&before, &live.get_inner()),
+        "listener should use the reloaded certificate"
+    );
+}
+
+#[tokio::test]
+async fn test_reload_tls_unreadable_key_keeps_current_certificate() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(&dir, "");
+    let tls = tls_files(&dir, "first-cert.pem", "first-key.pem").await;
+    let live = tls.config.clone();
+    let before = live.get_inner();
+
+    // A renewal caught halfway: new certificate written, key still being replaced
+    std::fs::copy(fixture("second-cert.pem"), &tls.cert_path).unwrap();
+    std::fs::write(&tls.key_path, "").unwrap();
+    let result = reloader(config_path, false, Some(tls)).reload_tls().await;
+
+    assert!(result.is_err());
+    assert!(Arc::ptr_eq(&before, &live.get_inner()));
+}
+
+#[tokio::test]
+async fn test_watch_picks_up_config_file_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(&dir, "debug = false\n");
+    let reloader = reloader(path, false, None);
+    let state = reloader.state.clone();
+
+    let watcher = tokio::spawn(reloader.watch(Duration::from_millis(20)));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    write_config(&dir, "debug = true # enabled while investigating\n");
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !state.settings().debug_mode {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "watcher should reload the changed config"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    watcher.abort();
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use inventory_server::{db, routes, AppState};
+use tempfile::NamedTempFile;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_redirect_router_sends_permanent_redirect_to_https() {
+    let app = routes::redirect_router(8443);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/device/SN123?tab=history")
+                .header(header::HOST, "inventory.contoso.com:8080")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://inventory.contoso.com:8443/device/SN123?tab=history"
+    );
+}
+
+#[tokio::test]
+async fn test_redirect_router_omits_default_https_port() {
+    let app = routes::redirect_router(443);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header(header::HOST, "inventory.contoso.com")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // 308 keeps the method and body, so agents still pointed at HTTP can follow it
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://inventory.contoso.com/checkin"
+    );
+}
+
+#[tokio::test]
+async fn test_redirect_router_requires_host() {
+    let app = routes::redirect_router(8443);
+
+    let response = app
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_health_router_only_serves_probes() {
+    let temp_db = NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap().to_string();
+    db::open_and_init(&db_path).unwrap();
+    let app = routes::health_router(Arc::new(AppState::new(db_path)));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(common::valid_checkin_json()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_listener_serves_and_cleans_up() {
+    use std::time::Duration;
+
+    use inventory_server::{
+        config::ListenerRole,
+        listeners::{self, Address, Listener},
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+    use tokio::sync::oneshot;
+
+    let temp_db = NamedTempFile::new().unwrap();
+    let db_path = temp_db.path().to_str().unwrap().to_string();
+    db::open_and_init(&db_path).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("inventory.sock");
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(listeners::serve(
+        vec![Listener {
+            address: Address::Unix(socket.clone()),
+            tls: false,
+            role: ListenerRole::All,
+            redirect_port: None,
+        }],
+        Arc::new(AppState::new(db_path)),
+        None,
+        Duration::from_secs(5),
+        async {
+            stopped.await.ok();
+        },
+    ));
+
+    let mut stream = loop {
+        match UnixStream::connect(&socket).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "got: {response}");
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), server)
+        .await
+        .expect("server should stop")
+        .unwrap()
+        .unwrap();
+    assert!(!socket.exists());
+}
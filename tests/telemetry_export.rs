@@ -0,0 +1,90 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use inventory_server::{config::TelemetryConfig, telemetry};
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+type Received = Arc<Mutex<Vec<Bytes>>>;
+
+/// Stand-in for an OpenTelemetry collector: records every OTLP/HTTP trace export
+async fn start_collector() -> (String, Received) {
+    let received: Received = Arc::default();
+
+    let app = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(received): State<Received>, body: Bytes| async move {
+                received.lock().unwrap().push(body);
+                StatusCode::OK
+            }),
+        )
+        .with_state(received.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (format!("http://{addr}/v1/traces"), received)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_spans_exported_to_otlp_collector() {
+    let (endpoint, received) = start_collector().await;
+
+    let provider = telemetry::init_tracer_provider(&TelemetryConfig {
+        otlp_endpoint: Some(endpoint),
+        service_name: "inventory-server-test".to_string(),
+    })
+    .unwrap()
+    .expect("provider should be built when an endpoint is set");
+
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let (app, _temp_db) = common::setup_test_app();
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/checkin")
+                .header("content-type", "application/json")
+                .header("x-request-id", "otel-trace-check")
+                .body(Body::from(common::valid_checkin_json()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // The request span stays open until the response is dropped
+    drop(response);
+    drop(guard);
+
+    tokio::task::spawn_blocking(move || provider.shutdown())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let received = received.lock().unwrap();
+    assert!(!received.is_empty(), "collector should receive an export");
+
+    // OTLP protobuf stores strings verbatim, so span names and attributes can be found in the raw bytes
+    let exported: Vec<u8> = received.iter().flat_map(|b| b.to_vec()).collect();
+    let contains = |needle: &str| {
+        exported
+            .windows(needle.len())
+            .any(|w| w == needle.as_bytes())
+    };
+    assert!(contains("inventory-server-test"), "service name missing");
+    assert!(contains("otel-trace-check"), "request ID attribute missing");
+    assert!(contains("checkin"), "handler span missing");
+    assert!(contains("record_checkin"), "database span missing");
+}